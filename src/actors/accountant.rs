@@ -1,28 +1,61 @@
-#![allow(dead_code)]
+use crate::{
+    api::{audit::AuditEvent, responses::LoggedResponse},
+    config::AccountantConfig,
+    errors::PersistenceError,
+    storage::{make_log_sink, LogSink},
+};
 
-use crate::{api::responses::LoggedResponse, config::AccountantConfig};
-
-use actix::{Actor, Context, Handler, Message};
-use tracing::info;
+use actix::{Actor, AsyncContext, Context, Handler, Message};
+use std::time::Duration;
+use tracing::{info, warn};
+use uuid::Uuid;
 
 pub struct Accountant {
     config: AccountantConfig,
+    sink: Box<dyn LogSink>,
+    buffer: Vec<LoggedResponse>,
 }
 
 impl Accountant {
-    pub fn new(config: AccountantConfig) -> Self {
-        Self { config }
+    pub fn new(config: AccountantConfig) -> Result<Self, PersistenceError> {
+        let sink = make_log_sink(&config)?;
+        info!(sink = ?config.sink, path = ?config.dir, "Opened request log sink");
+        Ok(Self {
+            buffer: Vec::with_capacity(config.batch_size),
+            config,
+            sink,
+        })
+    }
+
+    // logs are kept on a best effort basis, a batch that cannot be written is dropped so that a
+    // failing sink cannot exhaust memory
+    fn flush(&mut self) {
+        if self.buffer.is_empty() {
+            return;
+        }
+        if let Err(err) = self.sink.write_batch(&self.buffer) {
+            warn!(error = %err, dropped = self.buffer.len(), "Failed to write request logs");
+        }
+        self.buffer.clear();
     }
 }
 
 impl Actor for Accountant {
     type Context = Context<Self>;
 
-    fn started(&mut self, _: &mut Self::Context) {
+    fn started(&mut self, ctx: &mut Self::Context) {
         info!("Starting Accountant");
+
+        ctx.run_interval(
+            Duration::from_secs(self.config.flush_every.max(1)),
+            |accountant, _| {
+                accountant.flush();
+            },
+        );
     }
 
     fn stopped(&mut self, _: &mut Self::Context) {
+        self.flush();
         info!("Stopped Accountant");
     }
 }
@@ -34,30 +67,59 @@ pub struct LogResponse {
     pub response: LoggedResponse,
 }
 
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct FlushLogs;
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct RecordAudit {
+    pub event: AuditEvent,
+}
+
+#[derive(Message)]
+#[rtype(result = "Result<Vec<AuditEvent>, PersistenceError>")]
+pub struct GetAuditTrail {
+    pub experiment_id: Uuid,
+    pub from: Option<f64>,
+    pub to: Option<f64>,
+}
+
 // Handlers
 impl Handler<LogResponse> for Accountant {
     type Result = ();
 
     fn handle(&mut self, msg: LogResponse, _: &mut Self::Context) -> Self::Result {
-        info!(
-            id = %msg.response.id,
-            route = %msg.response.route,
-            status = msg.response.status,
-            timestamp = msg.response.timestamp,
-            "Persisting log entry"
-        );
+        self.buffer.push(msg.response);
+        if self.buffer.len() >= self.config.batch_size {
+            self.flush();
+        }
+    }
+}
+
+impl Handler<FlushLogs> for Accountant {
+    type Result = ();
+
+    fn handle(&mut self, _: FlushLogs, _: &mut Self::Context) -> Self::Result {
+        self.flush();
+    }
+}
+
+// audit events are written right away so that they can be queried as soon as the change is made
+impl Handler<RecordAudit> for Accountant {
+    type Result = ();
+
+    fn handle(&mut self, msg: RecordAudit, _: &mut Self::Context) -> Self::Result {
+        if let Err(err) = self.sink.write_audit(&msg.event) {
+            warn!(error = %err, id = %msg.event.experiment_id, "Failed to write audit event");
+        }
+    }
+}
+
+impl Handler<GetAuditTrail> for Accountant {
+    type Result = Result<Vec<AuditEvent>, PersistenceError>;
 
-        //TODO: Implement database storage
-        // Example with sqlx:
-        // let pool = self.db_pool.clone();
-        // let log = msg.response;
-        // actix::spawn(async move {
-        //     sqlx::query!(
-        //         "INSERT INTO request_logs (id, timestamp, route, status) VALUES ($1, $2, $3, $4)",
-        //         log.id, log.timestamp, log.route, log.status as i16
-        //     )
-        //     .execute(&pool)
-        //     .await
-        // });
+    fn handle(&mut self, msg: GetAuditTrail, _: &mut Self::Context) -> Self::Result {
+        self.sink.audit_trail(msg.experiment_id, msg.from, msg.to)
     }
 }
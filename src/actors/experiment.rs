@@ -1,43 +1,535 @@
 use super::state_store::{SaveState, StateStore};
 
-use crate::actors::state_store::{DeleteState, LoadState};
+use crate::actors::state_store::{DeleteState, LoadSnapshot, LoadState};
+use crate::config::{ExperimentConfig, LateUpdates};
 use crate::errors::{ExperimentError, PolicyError};
-use crate::policies::{BatchUpdateElement, DrawResult, Policy, PolicyStats};
+use crate::evaluation::{
+    analyze, leader, Conclusion, PruningRule, StoppingReason, StoppingRule, ANALYSIS_SAMPLES,
+};
+use crate::metrics::METRICS;
+use crate::policies::{
+    get_timestamp, ArmPosterior, BatchUpdateElement, BatchUpdateReport, DrawResult, Policy,
+    PolicyStats, RejectedUpdate, DEFAULT_LEVEL,
+};
+use crate::storage::{
+    event_log_path, replay_tail, EventLog, ExperimentEvent, ExperimentMetadata, ExperimentStatus,
+    InteractionKind, InteractionLog, InteractionRecord, StoredState,
+};
 
 use actix::prelude::*;
+use std::collections::{HashMap, VecDeque};
 use std::time::Duration;
-use tracing::info;
+use tracing::{info, warn};
 use uuid::Uuid;
 
+// statuses in which arms and rules can still be changed
+const EDITABLE: [ExperimentStatus; 6] = [
+    ExperimentStatus::Draft,
+    ExperimentStatus::Scheduled,
+    ExperimentStatus::Running,
+    ExperimentStatus::Paused,
+    ExperimentStatus::Ended,
+    ExperimentStatus::Concluded,
+];
+// statuses in which rewards are learned from
+const LEARNING: [ExperimentStatus; 2] = [ExperimentStatus::Running, ExperimentStatus::Concluded];
+
+// What was served by a draw, so that its reward can later be attributed to it. Tickets are only held
+// in memory, those issued before a restart of the experiment being unknown afterwards.
+struct DrawTicket {
+    arm_id: usize,
+    context: Option<Vec<f64>>,
+    issued_at: f64,
+    is_redeemed: bool,
+}
+
+impl DrawTicket {
+    fn is_expired(&self, now: f64, ttl: u64) -> bool {
+        now - self.issued_at > ttl as f64
+    }
+}
+
+pub struct TicketedDraw {
+    pub ticket: Uuid,
+    pub result: DrawResult,
+}
+
+// Outcome of a change along with the stats of the policy around it, for the audit trail
+#[derive(Debug)]
+pub struct Audited<T> {
+    pub value: T,
+    pub before: Option<PolicyStats>,
+    pub after: Option<PolicyStats>,
+}
+
+// Move from one stage of the lifecycle of an experiment to another
+#[derive(Debug, Clone, Copy)]
+pub enum Transition {
+    // a draft follows its schedule, a scheduled experiment starts right away
+    Start,
+    Pause { fallback_arm: usize },
+    Resume,
+    // the leader of a fresh analysis is declared the winner unless one is given
+    Conclude { winner: Option<usize> },
+    // only taken by the experiment itself, once its schedule is over
+    End,
+    Archive,
+}
+
+impl Transition {
+    fn status(&self) -> ExperimentStatus {
+        match self {
+            Self::Start | Self::Resume => ExperimentStatus::Running,
+            Self::Pause { .. } => ExperimentStatus::Paused,
+            Self::Conclude { .. } => ExperimentStatus::Concluded,
+            Self::End => ExperimentStatus::Ended,
+            Self::Archive => ExperimentStatus::Archived,
+        }
+    }
+}
+
 pub struct Experiment {
     id: Uuid,
     policy: Option<Box<dyn Policy + Send>>,
+    metadata: ExperimentMetadata,
+    snapshot_seq: u64,
+    event_log: Option<EventLog>,
+    interaction_log: Option<InteractionLog>,
     state_store: Addr<StateStore>,
-    save_every: u64,
+    config: ExperimentConfig,
+    tickets: HashMap<Uuid, DrawTicket>,
+    // tickets in the order they were issued, the oldest being evicted first
+    ticket_order: VecDeque<Uuid>,
 }
 
 impl Experiment {
     pub fn new(
         id: Uuid,
-        policy: Option<Box<dyn Policy + Send>>,
+        state: Option<StoredState>,
         state_store: Addr<StateStore>,
-        save_every: u64,
+        config: ExperimentConfig,
     ) -> Self {
+        let (policy, snapshot_seq, metadata) = state
+            .map_or((None, 0, ExperimentMetadata::default()), |state| {
+                (Some(state.policy), state.event_seq, state.metadata)
+            });
         Self {
             id,
             policy,
+            metadata,
+            snapshot_seq,
+            event_log: None,
+            interaction_log: None,
             state_store,
-            save_every,
+            config,
+            tickets: HashMap::new(),
+            ticket_order: VecDeque::new(),
         }
     }
 
-    fn persist(&self) {
-        if let Some(policy) = &self.policy {
-            self.state_store.do_send(SaveState {
-                experiment_id: self.id,
-                policy: policy.clone_box(),
+    // redeemed tickets are kept until they expire so that duplicates can be told apart from forged
+    // ones, tickets being issued in order so that expired ones come first
+    fn purge_tickets(&mut self) {
+        let now = get_timestamp();
+        let ttl = self.config.ticket_ttl;
+        while let Some(ticket) = self.ticket_order.front() {
+            if self
+                .tickets
+                .get(ticket)
+                .is_some_and(|ticket| !ticket.is_expired(now, ttl))
+            {
+                break;
+            }
+            if let Some(ticket) = self.ticket_order.pop_front() {
+                self.tickets.remove(&ticket);
+            }
+        }
+    }
+
+    // beyond the capacity, the oldest ticket is evicted and can no longer be redeemed
+    fn issue_ticket(&mut self, ticket: Uuid, draw_ticket: DrawTicket) {
+        let capacity = self.config.ticket_capacity;
+        while capacity > 0 && self.tickets.len() >= capacity {
+            let Some(oldest) = self.ticket_order.pop_front() else {
+                break;
+            };
+            self.tickets.remove(&oldest);
+        }
+        self.tickets.insert(ticket, draw_ticket);
+        self.ticket_order.push_back(ticket);
+    }
+
+    // the context of a redeemed ticket is dropped, only its id being needed to reject duplicates
+    fn redeem_ticket(&mut self, ticket: Uuid) {
+        if let Some(ticket) = self.tickets.get_mut(&ticket) {
+            ticket.is_redeemed = true;
+            ticket.context = None;
+        }
+    }
+
+    fn open_event_log(&mut self) {
+        let path = event_log_path(&self.config.event_log_dir, self.id);
+        match EventLog::open(path, self.snapshot_seq) {
+            Ok(event_log) => self.event_log = Some(event_log),
+            Err(err) => {
+                warn!(error = %err, id = %self.id, "Failed to open event log, changes will only be kept by snapshots")
+            }
+        }
+    }
+
+    fn open_interaction_log(&mut self) {
+        let Some(dir) = &self.config.interaction_record_dir else {
+            return;
+        };
+        match InteractionLog::open(dir, self.id, self.config.interaction_record_size) {
+            Ok(interaction_log) => self.interaction_log = Some(interaction_log),
+            Err(err) => {
+                warn!(error = %err, id = %self.id, "Failed to open interaction log, draws and rewards will not be recorded")
+            }
+        }
+    }
+
+    fn record_interaction(&mut self, record: InteractionRecord) {
+        if let Some(interaction_log) = self.interaction_log.as_mut() {
+            if let Err(err) = interaction_log.append(&record) {
+                warn!(error = %err, id = %self.id, "Failed to record interaction");
+            }
+        }
+    }
+
+    fn record_update(
+        &mut self,
+        timestamp: f64,
+        arm_id: usize,
+        reward: f64,
+        ticket: Option<Uuid>,
+        context: Option<Vec<f64>>,
+    ) {
+        METRICS.arm_updated(self.id, arm_id, reward);
+        self.record_interaction(InteractionRecord {
+            kind: InteractionKind::Update,
+            timestamp,
+            arm_id,
+            ticket,
+            propensity: None,
+            reward: Some(reward),
+            context,
+        });
+    }
+
+    fn log_event(&mut self, event: ExperimentEvent) {
+        if let Some(event_log) = self.event_log.as_mut() {
+            if let Err(err) = event_log.append(event) {
+                warn!(error = %err, id = %self.id, "Failed to append to event log");
+            }
+        }
+    }
+
+    fn last_event_seq(&self) -> u64 {
+        self.event_log
+            .as_ref()
+            .map_or(self.snapshot_seq, EventLog::last_seq)
+    }
+
+    // the events covered by a snapshot are dropped from the log once it is safely stored
+    fn snapshot_stored(&mut self, event_seq: u64) {
+        self.snapshot_seq = event_seq;
+        if let Some(event_log) = self.event_log.as_mut() {
+            if let Err(err) = event_log.compact(event_seq) {
+                warn!(error = %err, id = %self.id, "Failed to compact event log");
+            }
+        }
+    }
+
+    fn store_snapshot(&mut self) -> ResponseActFuture<Self, Result<(), ExperimentError>> {
+        let Some(policy) = &self.policy else {
+            return Box::pin(fut::ready(Ok(())));
+        };
+        let event_seq = self.last_event_seq();
+
+        Box::pin(
+            self.state_store
+                .send(SaveState {
+                    experiment_id: self.id,
+                    policy: policy.clone_box(),
+                    event_seq,
+                    metadata: self.metadata.clone(),
+                })
+                .into_actor(self)
+                .map(move |result, actor, _| {
+                    result??;
+                    actor.snapshot_stored(event_seq);
+                    Ok(())
+                }),
+        )
+    }
+
+    // failures are already reported by the state store, the next interval will try again
+    fn persist(&mut self, ctx: &mut Context<Self>) {
+        ctx.spawn(self.store_snapshot().map(|_, _, _| ()));
+    }
+
+    // arms dominated by another one are disabled, along with the evidence they were disabled on,
+    // except for the default arm the schedule serves once it is over
+    fn prune_arms(&mut self) -> bool {
+        if self.metadata.status != ExperimentStatus::Running {
+            return false;
+        }
+        let (Some(pruning_rule), Some(policy)) = (&self.metadata.pruning_rule, &self.policy) else {
+            return false;
+        };
+        let mut dominated_arms =
+            pruning_rule.dominated_arms(&policy.stats_at(pruning_rule.level), get_timestamp());
+        dominated_arms.retain(|(arm_id, _)| self.metadata.schedule.default_arm != Some(*arm_id));
+
+        let mut is_pruned = false;
+        for (arm_id, pruned_arm) in dominated_arms {
+            match self.apply_event(ExperimentEvent::DisableArm { arm_id }) {
+                Ok(()) => {
+                    info!(
+                        id = %self.id,
+                        arm_id,
+                        upper = pruned_arm.upper,
+                        dominated_by = pruned_arm.dominated_by,
+                        dominating_lower = pruned_arm.dominating_lower,
+                        "Pruned dominated arm"
+                    );
+                    self.metadata.pruned_arms.insert(arm_id, pruned_arm);
+                    is_pruned = true;
+                }
+                Err(err) => warn!(error = %err, id = %self.id, arm_id, "Failed to prune arm"),
+            }
+        }
+        is_pruned
+    }
+
+    // once its stopping rule is met, the experiment is concluded for good along with the analysis
+    // the decision was taken on
+    fn evaluate_stopping_rule(&mut self) -> bool {
+        if self.metadata.status != ExperimentStatus::Running {
+            return false;
+        }
+        let (Some(stopping_rule), Some(policy)) = (&self.metadata.stopping_rule, &self.policy)
+        else {
+            return false;
+        };
+        let posteriors = policy.posteriors();
+        if posteriors.is_empty() {
+            return false;
+        }
+        let analysis = match analyze(&posteriors, ANALYSIS_SAMPLES, &mut rand::rng()) {
+            Ok(analysis) => analysis,
+            Err(err) => {
+                warn!(error = %err, id = %self.id, "Failed to evaluate stopping rule");
+                return false;
+            }
+        };
+
+        let now = get_timestamp();
+        let stats = policy.stats();
+        let samples = stats.arms.values().map(|arm| arm.pulls).sum();
+        let min_arm_pulls = stats
+            .arms
+            .values()
+            .filter(|arm| arm.is_active)
+            .map(|arm| arm.pulls)
+            .min()
+            .unwrap_or_default();
+        if let Some(conclusion) = stopping_rule.check(
+            analysis,
+            min_arm_pulls,
+            now - self.metadata.started_at(),
+            samples,
+            now,
+        ) {
+            info!(
+                id = %self.id,
+                winner = conclusion.winner,
+                reason = ?conclusion.reason,
+                "Concluded experiment"
+            );
+            self.metadata.conclusion = Some(conclusion);
+            self.metadata.status = ExperimentStatus::Concluded;
+            return true;
+        }
+        false
+    }
+
+    fn ensure_status(
+        &self,
+        allowed: &[ExperimentStatus],
+        action: &'static str,
+    ) -> Result<(), ExperimentError> {
+        if allowed.contains(&self.metadata.status) {
+            Ok(())
+        } else {
+            Err(ExperimentError::InvalidStatus {
+                status: self.metadata.status,
+                action,
+            })
+        }
+    }
+
+    // rewards sent for a raw arm id cannot be checked against the draw that served it
+    fn ensure_raw_updates(&self) -> Result<(), ExperimentError> {
+        if self.metadata.raw_updates {
+            Ok(())
+        } else {
+            Err(ExperimentError::RawUpdatesDisabled)
+        }
+    }
+
+    // the arm served by a paused or concluded experiment has to stay available, as does the default
+    // arm of the schedule whatever the status, to be served outside of its window
+    fn ensure_not_served(&self, arm_id: usize) -> Result<(), ExperimentError> {
+        if self.metadata.served_arm() == Some(arm_id)
+            || self.metadata.schedule.default_arm == Some(arm_id)
+        {
+            return Err(ExperimentError::ServedArm(arm_id));
+        }
+        Ok(())
+    }
+
+    fn ensure_active_arm(&self, arm_id: usize) -> Result<(), ExperimentError> {
+        let policy = self.policy.as_ref().ok_or(ExperimentError::NoPolicy)?;
+        match policy.stats().arms.get(&arm_id) {
+            None => Err(PolicyError::ArmNotFound(arm_id).into()),
+            Some(arm) if !arm.is_active => Err(PolicyError::InactiveArm(arm_id).into()),
+            Some(_) => Ok(()),
+        }
+    }
+
+    // the initial reward of an arm is the mean of the rewards it starts from
+    fn validate_initial(&self, mean_reward: f64) -> Result<(), ExperimentError> {
+        let policy = self.policy.as_ref().ok_or(ExperimentError::NoPolicy)?;
+        policy
+            .reward_domain()
+            .validate_mean(mean_reward)
+            .map_err(Into::into)
+    }
+
+    fn conclude(&self, winner: Option<usize>) -> Result<Conclusion, ExperimentError> {
+        if let Some(winner) = winner {
+            self.ensure_active_arm(winner)?;
+        }
+        let policy = self.policy.as_ref().ok_or(ExperimentError::NoPolicy)?;
+        let analysis = analyze(&policy.posteriors(), ANALYSIS_SAMPLES, &mut rand::rng())?;
+        let winner = match winner {
+            Some(winner) => winner,
+            None => leader(&analysis)
+                .map(|(arm_id, _)| arm_id)
+                .ok_or(PolicyError::NoArmsAvailable)?,
+        };
+
+        let now = get_timestamp();
+        Ok(Conclusion {
+            winner,
+            reason: StoppingReason::Manual,
+            concluded_at: now,
+            elapsed: now - self.metadata.started_at(),
+            samples: policy.stats().arms.values().map(|arm| arm.pulls).sum(),
+            analysis,
+        })
+    }
+
+    fn change_status(&mut self, transition: Transition) -> Result<(), ExperimentError> {
+        let now = get_timestamp();
+        let status = match transition {
+            Transition::Start if self.metadata.status == ExperimentStatus::Draft => {
+                self.metadata.schedule.status_at(now)
+            }
+            _ => transition.status(),
+        };
+        if !self.metadata.status.can_become(status) {
+            return Err(ExperimentError::InvalidTransition {
+                from: self.metadata.status,
+                to: status,
             });
         }
+        // the default arm may only have been added in the draft, and is checked again at each step
+        // of the schedule in case it was stored before it was protected
+        if let (Transition::Start | Transition::End, Some(default_arm)) =
+            (transition, self.metadata.schedule.default_arm)
+        {
+            self.ensure_active_arm(default_arm)?;
+        }
+        match transition {
+            Transition::Start => {
+                if status == ExperimentStatus::Running {
+                    self.metadata.started_at = Some(now);
+                }
+            }
+            Transition::Pause { fallback_arm } => {
+                self.ensure_active_arm(fallback_arm)?;
+                self.metadata.fallback_arm = Some(fallback_arm);
+            }
+            Transition::Resume => {
+                self.metadata.fallback_arm = None;
+            }
+            Transition::Conclude { winner } => {
+                self.metadata.conclusion = Some(self.conclude(winner)?);
+                self.metadata.fallback_arm = None;
+            }
+            Transition::End => {
+                self.metadata.fallback_arm = None;
+            }
+            Transition::Archive => (),
+        }
+        info!(id = %self.id, from = %self.metadata.status, to = %status, "Changed experiment status");
+        self.metadata.status = status;
+        Ok(())
+    }
+
+    // timers switch the experiment on and off along its schedule, those set before a change of
+    // status made through the API doing nothing once they fire
+    fn follow_schedule(&mut self, ctx: &mut Context<Self>) {
+        let now = get_timestamp();
+        let delay = |at: f64| Duration::from_secs_f64((at - now).max(0.0));
+        let schedule = &self.metadata.schedule;
+        if let (ExperimentStatus::Scheduled, Some(starts_at)) =
+            (self.metadata.status, schedule.starts_at)
+        {
+            ctx.run_later(delay(starts_at), |experiment, ctx| {
+                if experiment.metadata.status == ExperimentStatus::Scheduled {
+                    experiment.switch(Transition::Start, ctx);
+                }
+            });
+        }
+        if let Some(ends_at) = schedule.ends_at {
+            if self.metadata.status.can_become(ExperimentStatus::Ended) {
+                ctx.run_later(delay(ends_at), |experiment, ctx| {
+                    if experiment
+                        .metadata
+                        .status
+                        .can_become(ExperimentStatus::Ended)
+                    {
+                        experiment.switch(Transition::End, ctx);
+                    }
+                });
+            }
+        }
+    }
+
+    fn switch(&mut self, transition: Transition, ctx: &mut Context<Self>) {
+        match self.change_status(transition) {
+            Ok(()) => self.persist(ctx),
+            Err(err) => warn!(error = %err, id = %self.id, "Failed to follow schedule"),
+        }
+    }
+
+    // rewards received once the schedule is over are either rejected or only recorded
+    fn records_late_updates(&self) -> bool {
+        self.metadata.status == ExperimentStatus::Ended
+            && self.config.late_updates == LateUpdates::Record
+    }
+
+    // decisions taken by the rules are stored right away
+    fn evaluate_rules(&mut self, ctx: &mut Context<Self>) {
+        let is_pruned = self.prune_arms();
+        let is_concluded = self.evaluate_stopping_rule();
+        if is_pruned || is_concluded {
+            self.persist(ctx);
+        }
     }
 
     fn with_policy_mut<F, R, E>(&mut self, f: F) -> Result<R, ExperimentError>
@@ -48,6 +540,45 @@ impl Experiment {
         let policy = self.policy.as_mut().ok_or(ExperimentError::NoPolicy)?;
         f(policy.as_mut()).map_err(Into::into)
     }
+
+    // an arm pruned then enabled again before its metadata was stored is not reported as pruned
+    fn stats_at(&self, level: f64) -> Result<PolicyStats, ExperimentError> {
+        let mut policy_stats = self
+            .policy
+            .as_ref()
+            .ok_or(ExperimentError::NoPolicy)?
+            .stats_at(level);
+        for (arm_id, arm) in policy_stats.arms.iter_mut() {
+            arm.is_pruned = !arm.is_active && self.metadata.pruned_arms.contains_key(arm_id);
+        }
+        Ok(policy_stats)
+    }
+
+    fn audited<T>(&self, before: Option<PolicyStats>, value: T) -> Audited<T> {
+        Audited {
+            value,
+            before,
+            after: self.stats_at(DEFAULT_LEVEL).ok(),
+        }
+    }
+
+    // record the stats before a change stored asynchronously, and those after it once stored
+    fn audited_snapshot(
+        &mut self,
+        before: Option<PolicyStats>,
+    ) -> ResponseActFuture<Self, Result<Audited<()>, ExperimentError>> {
+        Box::pin(
+            self.store_snapshot()
+                .map(move |result, actor, _| result.map(|()| actor.audited(before, ()))),
+        )
+    }
+
+    // apply a change to the policy, logging it once it succeeded
+    fn apply_event(&mut self, event: ExperimentEvent) -> Result<(), ExperimentError> {
+        self.with_policy_mut(|policy| event.apply(policy, get_timestamp()))?;
+        self.log_event(event);
+        Ok(())
+    }
 }
 
 impl Actor for Experiment {
@@ -63,23 +594,54 @@ impl Actor for Experiment {
             ctx.spawn(
                 async move {
                     match state_store.send(LoadState { experiment_id }).await {
-                        Ok(Some(policy)) => Some(policy),
+                        Ok(Some(state)) => Some(state),
                         _ => None,
                     }
                 }
                 .into_actor(self)
-                .map(|maybe_policy, actor, _| {
-                    if let Some(policy) = maybe_policy {
-                        actor.policy = Some(policy);
+                .map(|maybe_state, actor, ctx| {
+                    if let Some(mut state) = maybe_state {
+                        // changes made since the last snapshot are only found in the event log
+                        let path = event_log_path(&actor.config.event_log_dir, actor.id);
+                        match replay_tail(state.policy.as_mut(), &path, state.event_seq) {
+                            Ok(replayed) => info!(id = %actor.id, replayed, "Replayed event log"),
+                            Err(err) => {
+                                warn!(error = %err, id = %actor.id, "Failed to replay event log")
+                            }
+                        }
+                        actor.policy = Some(state.policy);
+                        actor.metadata = state.metadata;
+                        actor.snapshot_seq = state.event_seq;
                         info!(id = %actor.id, "Reloaded policy state for experiment");
                     }
+                    actor.open_event_log();
+                    actor.follow_schedule(ctx);
                 }),
             );
+        } else {
+            self.open_event_log();
+            self.follow_schedule(ctx);
         }
+        self.open_interaction_log();
 
-        ctx.run_interval(Duration::from_secs(self.save_every), |experiment, _| {
-            experiment.persist();
-        });
+        ctx.run_interval(
+            Duration::from_secs(self.config.save_every),
+            |experiment, ctx| {
+                experiment.persist(ctx);
+            },
+        );
+        ctx.run_interval(
+            Duration::from_secs(self.config.evaluate_every.max(1)),
+            |experiment, ctx| {
+                experiment.evaluate_rules(ctx);
+            },
+        );
+        ctx.run_interval(
+            Duration::from_secs(self.config.ticket_ttl.max(1)),
+            |experiment, _| {
+                experiment.purge_tickets();
+            },
+        );
     }
 
     fn stopped(&mut self, _: &mut Self::Context) {
@@ -87,7 +649,12 @@ impl Actor for Experiment {
     }
 }
 
-impl Supervised for Experiment {}
+impl Supervised for Experiment {
+    fn restarting(&mut self, _: &mut Self::Context) {
+        warn!(id = %self.id, "Restarting Experiment");
+        METRICS.experiment_restarted(self.id);
+    }
+}
 
 // Messages
 #[derive(Message)]
@@ -96,10 +663,15 @@ pub struct Ping;
 
 #[derive(Message)]
 #[rtype(result = "Result<(), ExperimentError>")]
+pub struct Flush;
+
+// replied with the stats of the policy before its deletion
+#[derive(Message)]
+#[rtype(result = "Option<PolicyStats>")]
 pub struct Delete;
 
 #[derive(Message)]
-#[rtype(result = "Result<(), ExperimentError>")]
+#[rtype(result = "Result<Audited<()>, ExperimentError>")]
 pub struct Reset {
     pub arm_id: Option<usize>,
     pub cumulative_reward: Option<f64>,
@@ -107,33 +679,35 @@ pub struct Reset {
 }
 
 #[derive(Message)]
-#[rtype(result = "Result<usize, ExperimentError>")]
+#[rtype(result = "Result<Audited<usize>, ExperimentError>")]
 pub struct AddArm {
     pub initial_reward: Option<f64>,
     pub initial_count: Option<u64>,
 }
 
 #[derive(Message)]
-#[rtype(result = "Result<(), ExperimentError>")]
+#[rtype(result = "Result<Audited<()>, ExperimentError>")]
 pub struct DisableArm {
     pub arm_id: usize,
 }
 
 #[derive(Message)]
-#[rtype(result = "Result<(), ExperimentError>")]
+#[rtype(result = "Result<Audited<()>, ExperimentError>")]
 pub struct EnableArm {
     pub arm_id: usize,
 }
 
 #[derive(Message)]
-#[rtype(result = "Result<(), ExperimentError>")]
+#[rtype(result = "Result<Audited<()>, ExperimentError>")]
 pub struct DeleteArm {
     pub arm_id: usize,
 }
 
 #[derive(Message)]
-#[rtype(result = "Result<DrawResult, ExperimentError>")]
-pub struct Draw;
+#[rtype(result = "Result<TicketedDraw, ExperimentError>")]
+pub struct Draw {
+    pub context: Option<Vec<f64>>,
+}
 
 #[derive(Message)]
 #[rtype(result = "Result<(), ExperimentError>")]
@@ -141,17 +715,63 @@ pub struct Update {
     pub timestamp: f64,
     pub arm_id: usize,
     pub reward: f64,
+    pub context: Option<Vec<f64>>,
 }
 
 #[derive(Message)]
 #[rtype(result = "Result<(), ExperimentError>")]
+pub struct RedeemTicket {
+    pub ticket: Uuid,
+    pub timestamp: f64,
+    pub reward: f64,
+}
+
+#[derive(Message)]
+#[rtype(result = "Result<BatchUpdateReport, ExperimentError>")]
 pub struct UpdateBatch {
     pub updates: Vec<BatchUpdateElement>,
+    pub skip_invalid: bool,
+}
+
+#[derive(Message)]
+#[rtype(result = "Result<Audited<()>, ExperimentError>")]
+pub struct RestoreSnapshot {
+    pub snapshot_id: u64,
+}
+
+// an empty rule removes the current one, a concluded experiment staying concluded either way
+#[derive(Message)]
+#[rtype(result = "Result<Audited<()>, ExperimentError>")]
+pub struct SetStoppingRule {
+    pub stopping_rule: StoppingRule,
+}
+
+// arms already pruned stay disabled when the rule is removed
+#[derive(Message)]
+#[rtype(result = "Result<Audited<()>, ExperimentError>")]
+pub struct SetPruningRule {
+    pub pruning_rule: Option<PruningRule>,
+}
+
+#[derive(Message)]
+#[rtype(result = "Result<Audited<()>, ExperimentError>")]
+pub struct ChangeStatus {
+    pub transition: Transition,
 }
 
+#[derive(Message)]
+#[rtype(result = "ExperimentMetadata")]
+pub struct GetMetadata;
+
 #[derive(Message)]
 #[rtype(result = "Result<PolicyStats, ExperimentError>")]
-pub struct GetStats;
+pub struct GetStats {
+    pub level: f64,
+}
+
+#[derive(Message)]
+#[rtype(result = "Result<HashMap<usize, ArmPosterior>, ExperimentError>")]
+pub struct GetPosteriors;
 
 // Handlers
 impl Handler<Ping> for Experiment {
@@ -160,69 +780,185 @@ impl Handler<Ping> for Experiment {
     fn handle(&mut self, _: Ping, _: &mut Self::Context) -> Self::Result {}
 }
 
+impl Handler<Flush> for Experiment {
+    type Result = ResponseActFuture<Self, Result<(), ExperimentError>>;
+
+    fn handle(&mut self, _: Flush, _: &mut Self::Context) -> Self::Result {
+        self.store_snapshot()
+    }
+}
+
 impl Handler<Delete> for Experiment {
-    type Result = Result<(), ExperimentError>;
+    type Result = MessageResult<Delete>;
 
     fn handle(&mut self, _: Delete, ctx: &mut Self::Context) -> Self::Result {
+        let before = self.stats_at(DEFAULT_LEVEL).ok();
+        if let Some(policy) = &self.policy {
+            METRICS.remove_experiment(self.id, policy.stats().arms.into_keys());
+        }
         self.state_store.do_send(DeleteState {
             experiment_id: self.id,
         });
+        if let Some(event_log) = self.event_log.take() {
+            if let Err(err) = event_log.delete() {
+                warn!(error = %err, id = %self.id, "Failed to delete event log");
+            }
+        }
+        if let Some(interaction_log) = self.interaction_log.take() {
+            if let Err(err) = interaction_log.delete() {
+                warn!(error = %err, id = %self.id, "Failed to delete interaction log");
+            }
+        }
         ctx.stop();
-        Ok(())
+        MessageResult(before)
     }
 }
 
 impl Handler<Reset> for Experiment {
-    type Result = Result<(), ExperimentError>;
+    type Result = Result<Audited<()>, ExperimentError>;
 
     fn handle(&mut self, msg: Reset, _: &mut Self::Context) -> Self::Result {
-        self.with_policy_mut(|policy| policy.reset(msg.arm_id, msg.cumulative_reward, msg.count))
+        self.ensure_status(&EDITABLE, "reset its arms")?;
+        if let Some(cumulative_reward) = msg.cumulative_reward {
+            self.validate_initial(cumulative_reward)?;
+        }
+        let before = self.stats_at(DEFAULT_LEVEL).ok();
+        self.apply_event(ExperimentEvent::Reset {
+            arm_id: msg.arm_id,
+            cumulative_reward: msg.cumulative_reward,
+            count: msg.count,
+        })?;
+        Ok(self.audited(before, ()))
     }
 }
 
 impl Handler<AddArm> for Experiment {
-    type Result = Result<usize, ExperimentError>;
+    type Result = Result<Audited<usize>, ExperimentError>;
 
     fn handle(&mut self, msg: AddArm, _: &mut Self::Context) -> Self::Result {
-        self.with_policy_mut(|policy| {
-            let arm_id = policy.add_arm(
-                msg.initial_reward.unwrap_or_default(),
-                msg.initial_count.unwrap_or_default(),
-            );
-            Ok::<usize, PolicyError>(arm_id)
-        })
+        self.ensure_status(&EDITABLE, "change its arms")?;
+        let initial_reward = msg.initial_reward.unwrap_or_default();
+        let initial_count = msg.initial_count.unwrap_or_default();
+        self.validate_initial(initial_reward)?;
+        let before = self.stats_at(DEFAULT_LEVEL).ok();
+        let arm_id = self.with_policy_mut(|policy| {
+            Ok::<usize, PolicyError>(policy.add_arm(initial_reward, initial_count))
+        })?;
+        self.log_event(ExperimentEvent::AddArm {
+            initial_reward,
+            initial_count,
+        });
+        Ok(self.audited(before, arm_id))
     }
 }
 
 impl Handler<DisableArm> for Experiment {
-    type Result = Result<(), ExperimentError>;
+    type Result = Result<Audited<()>, ExperimentError>;
 
     fn handle(&mut self, msg: DisableArm, _: &mut Self::Context) -> Self::Result {
-        self.with_policy_mut(|policy| policy.disable_arm(msg.arm_id))
+        self.ensure_status(&EDITABLE, "change its arms")?;
+        self.ensure_not_served(msg.arm_id)?;
+        let before = self.stats_at(DEFAULT_LEVEL).ok();
+        self.apply_event(ExperimentEvent::DisableArm { arm_id: msg.arm_id })?;
+        Ok(self.audited(before, ()))
     }
 }
 
 impl Handler<EnableArm> for Experiment {
-    type Result = Result<(), ExperimentError>;
+    type Result = Result<Audited<()>, ExperimentError>;
 
+    // a pruned arm enabled again may be pruned anew while the pruning rule is set
     fn handle(&mut self, msg: EnableArm, _: &mut Self::Context) -> Self::Result {
-        self.with_policy_mut(|policy| policy.enable_arm(msg.arm_id))
+        self.ensure_status(&EDITABLE, "change its arms")?;
+        let before = self.stats_at(DEFAULT_LEVEL).ok();
+        self.apply_event(ExperimentEvent::EnableArm { arm_id: msg.arm_id })?;
+        self.metadata.pruned_arms.remove(&msg.arm_id);
+        Ok(self.audited(before, ()))
     }
 }
 
 impl Handler<DeleteArm> for Experiment {
-    type Result = Result<(), ExperimentError>;
+    type Result = Result<Audited<()>, ExperimentError>;
 
     fn handle(&mut self, msg: DeleteArm, _: &mut Self::Context) -> Self::Result {
-        self.with_policy_mut(|policy| policy.delete_arm(msg.arm_id))
+        self.ensure_status(&EDITABLE, "change its arms")?;
+        self.ensure_not_served(msg.arm_id)?;
+        let before = self.stats_at(DEFAULT_LEVEL).ok();
+        self.apply_event(ExperimentEvent::DeleteArm { arm_id: msg.arm_id })?;
+        self.metadata.pruned_arms.remove(&msg.arm_id);
+        Ok(self.audited(before, ()))
     }
 }
 
 impl Handler<Draw> for Experiment {
-    type Result = Result<DrawResult, ExperimentError>;
+    type Result = Result<TicketedDraw, ExperimentError>;
 
-    fn handle(&mut self, _: Draw, _: &mut Self::Context) -> Self::Result {
-        self.with_policy_mut(|policy| policy.draw())
+    // a paused experiment serves its fallback arm, a concluded one its winner and one outside of
+    // its schedule the default arm, leaving the policy untouched. Draws served while paused or
+    // before the schedule started cannot be rewarded.
+    fn handle(&mut self, msg: Draw, _: &mut Self::Context) -> Self::Result {
+        let status = self.metadata.status;
+        let is_served = match status {
+            ExperimentStatus::Draft | ExperimentStatus::Archived => false,
+            ExperimentStatus::Scheduled | ExperimentStatus::Ended => {
+                self.metadata.served_arm().is_some()
+            }
+            _ => true,
+        };
+        if !is_served {
+            return Err(ExperimentError::InvalidStatus {
+                status: self.metadata.status,
+                action: "serve draws",
+            });
+        }
+        let result = match self.metadata.served_arm() {
+            Some(arm_id) => DrawResult {
+                timestamp: get_timestamp(),
+                arm_id,
+                propensity: Some(1.0),
+            },
+            None => {
+                let (result, is_logged) = self.with_policy_mut(|policy| {
+                    policy
+                        .draw(msg.context.as_deref())
+                        .map(|result| (result, policy.draw_changes_state()))
+                })?;
+                // other draws are left out of the event log, which keeps them off the disk
+                if is_logged {
+                    self.log_event(ExperimentEvent::Draw {
+                        context: msg.context.clone(),
+                    });
+                }
+                result
+            }
+        };
+        let ticket = Uuid::new_v4();
+        METRICS.arm_drawn(self.id, result.arm_id);
+        self.record_interaction(InteractionRecord {
+            kind: InteractionKind::Draw,
+            timestamp: result.timestamp,
+            arm_id: result.arm_id,
+            ticket: Some(ticket),
+            propensity: result.propensity,
+            reward: None,
+            context: msg.context.clone(),
+        });
+        if !matches!(
+            status,
+            ExperimentStatus::Paused | ExperimentStatus::Scheduled
+        ) {
+            self.issue_ticket(
+                ticket,
+                DrawTicket {
+                    arm_id: result.arm_id,
+                    context: msg.context,
+                    issued_at: result.timestamp,
+                    is_redeemed: false,
+                },
+            );
+        }
+
+        Ok(TicketedDraw { ticket, result })
     }
 }
 
@@ -230,25 +966,277 @@ impl Handler<Update> for Experiment {
     type Result = Result<(), ExperimentError>;
 
     fn handle(&mut self, msg: Update, _: &mut Self::Context) -> Self::Result {
-        self.with_policy_mut(|policy| policy.update(msg.timestamp, msg.arm_id, msg.reward))
+        self.ensure_raw_updates()?;
+        if self.records_late_updates() {
+            self.record_update(msg.timestamp, msg.arm_id, msg.reward, None, msg.context);
+            return Ok(());
+        }
+        self.ensure_status(&LEARNING, "learn from rewards")?;
+        self.apply_event(ExperimentEvent::Update {
+            timestamp: msg.timestamp,
+            arm_id: msg.arm_id,
+            reward: msg.reward,
+            context: msg.context.clone(),
+        })?;
+        self.record_update(msg.timestamp, msg.arm_id, msg.reward, None, msg.context);
+        Ok(())
     }
 }
 
-impl Handler<UpdateBatch> for Experiment {
+impl Handler<RedeemTicket> for Experiment {
     type Result = Result<(), ExperimentError>;
 
+    fn handle(&mut self, msg: RedeemTicket, _: &mut Self::Context) -> Self::Result {
+        let is_late = self.records_late_updates();
+        if !is_late {
+            self.ensure_status(&LEARNING, "learn from rewards")?;
+        }
+        let ticket = self
+            .tickets
+            .get(&msg.ticket)
+            .ok_or(ExperimentError::UnknownTicket(msg.ticket))?;
+        if ticket.is_redeemed {
+            return Err(ExperimentError::DuplicateTicket(msg.ticket));
+        }
+        if ticket.is_expired(get_timestamp(), self.config.ticket_ttl) {
+            return Err(ExperimentError::ExpiredTicket(msg.ticket));
+        }
+
+        let arm_id = ticket.arm_id;
+        let context = ticket.context.clone();
+        if is_late {
+            self.redeem_ticket(msg.ticket);
+            self.record_update(msg.timestamp, arm_id, msg.reward, Some(msg.ticket), context);
+            return Ok(());
+        }
+        self.apply_event(ExperimentEvent::Redeem {
+            timestamp: msg.timestamp,
+            arm_id,
+            reward: msg.reward,
+            context: context.clone(),
+        })?;
+
+        self.redeem_ticket(msg.ticket);
+        self.record_update(msg.timestamp, arm_id, msg.reward, Some(msg.ticket), context);
+        Ok(())
+    }
+}
+
+impl Handler<UpdateBatch> for Experiment {
+    type Result = Result<BatchUpdateReport, ExperimentError>;
+
+    // updates are applied to a copy of the policy which replaces the current one only on success,
+    // so that a batch is either fully applied or not at all
     fn handle(&mut self, msg: UpdateBatch, _: &mut Self::Context) -> Self::Result {
-        let mut updates = msg.updates;
-        updates.sort_unstable_by(|a, b| a.timestamp.total_cmp(&b.timestamp));
+        self.ensure_raw_updates()?;
+        if self.records_late_updates() {
+            for update in msg.updates {
+                self.record_update(
+                    update.timestamp,
+                    update.arm_id,
+                    update.reward,
+                    None,
+                    update.context,
+                );
+            }
+            return Ok(BatchUpdateReport::default());
+        }
+        self.ensure_status(&LEARNING, "learn from rewards")?;
+        let mut policy = self
+            .policy
+            .as_ref()
+            .ok_or(ExperimentError::NoPolicy)?
+            .clone_box();
+
+        // keep track of the position of each update in the request to report rejections
+        let mut updates = msg.updates.into_iter().enumerate().collect::<Vec<_>>();
+        updates.sort_by(|(_, a), (_, b)| a.timestamp.total_cmp(&b.timestamp));
+
+        let mut events = Vec::new();
+        let report = if msg.skip_invalid {
+            let mut report = BatchUpdateReport::default();
+            for (index, update) in updates {
+                match policy.update(
+                    update.timestamp,
+                    update.arm_id,
+                    update.reward,
+                    update.context.as_deref(),
+                ) {
+                    Ok(()) => {
+                        report.applied += 1;
+                        events.push(ExperimentEvent::Update {
+                            timestamp: update.timestamp,
+                            arm_id: update.arm_id,
+                            reward: update.reward,
+                            context: update.context,
+                        });
+                    }
+                    Err(err) => report.rejected.push(RejectedUpdate {
+                        index,
+                        arm_id: update.arm_id,
+                        reason: err.to_string(),
+                    }),
+                }
+            }
+            report
+                .rejected
+                .sort_unstable_by_key(|rejected| rejected.index);
+            report
+        } else {
+            let updates = updates
+                .into_iter()
+                .map(|(_, update)| update)
+                .collect::<Vec<_>>();
+            policy.update_batch(&updates)?;
+            let report = BatchUpdateReport {
+                applied: updates.len(),
+                rejected: Vec::new(),
+            };
+            events.push(ExperimentEvent::UpdateBatch { updates });
+            report
+        };
 
-        self.with_policy_mut(|policy| policy.update_batch(&updates))
+        self.policy = Some(policy);
+        for event in events {
+            match &event {
+                ExperimentEvent::Update {
+                    timestamp,
+                    arm_id,
+                    reward,
+                    context,
+                } => self.record_update(*timestamp, *arm_id, *reward, None, context.clone()),
+                ExperimentEvent::UpdateBatch { updates } => {
+                    for update in updates {
+                        self.record_update(
+                            update.timestamp,
+                            update.arm_id,
+                            update.reward,
+                            None,
+                            update.context.clone(),
+                        );
+                    }
+                }
+                _ => (),
+            }
+            self.log_event(event);
+        }
+        Ok(report)
+    }
+}
+
+impl Handler<RestoreSnapshot> for Experiment {
+    type Result = AtomicResponse<Self, Result<Audited<()>, ExperimentError>>;
+
+    // the restored policy is stored as the current state before replacing the live one, so that
+    // neither a restart nor the events logged until now can undo the restoration. No other message
+    // is handled in the meantime.
+    fn handle(&mut self, msg: RestoreSnapshot, _: &mut Self::Context) -> Self::Result {
+        if let Err(err) = self.ensure_status(&EDITABLE, "restore a snapshot") {
+            return AtomicResponse::new(Box::pin(fut::ready(Err(err))));
+        }
+        let experiment_id = self.id;
+        let snapshot_id = msg.snapshot_id;
+        let state_store = self.state_store.clone();
+        let event_seq = self.last_event_seq();
+        // the stopping rule and conclusion are not part of what a snapshot restores
+        let metadata = self.metadata.clone();
+        let before = self.stats_at(DEFAULT_LEVEL).ok();
+
+        AtomicResponse::new(Box::pin(
+            async move {
+                let state = state_store
+                    .send(LoadSnapshot {
+                        experiment_id,
+                        snapshot_id,
+                    })
+                    .await??
+                    .ok_or(ExperimentError::SnapshotNotFound(snapshot_id))?;
+                state_store
+                    .send(SaveState {
+                        experiment_id,
+                        policy: state.policy.clone_box(),
+                        event_seq,
+                        metadata,
+                    })
+                    .await??;
+                Ok::<_, ExperimentError>(state.policy)
+            }
+            .into_actor(self)
+            .map(move |result, actor, _| {
+                let policy = result?;
+                actor.policy = Some(policy);
+                actor.snapshot_stored(event_seq);
+                info!(id = %actor.id, snapshot_id, "Restored experiment snapshot");
+                Ok(actor.audited(before, ()))
+            }),
+        ))
+    }
+}
+
+impl Handler<SetStoppingRule> for Experiment {
+    type Result = ResponseActFuture<Self, Result<Audited<()>, ExperimentError>>;
+
+    fn handle(&mut self, msg: SetStoppingRule, _: &mut Self::Context) -> Self::Result {
+        if let Err(err) = self.ensure_status(&EDITABLE, "change its rules") {
+            return Box::pin(fut::ready(Err(err)));
+        }
+        let before = self.stats_at(DEFAULT_LEVEL).ok();
+        self.metadata.stopping_rule = (!msg.stopping_rule.is_empty()).then_some(msg.stopping_rule);
+        self.audited_snapshot(before)
+    }
+}
+
+impl Handler<SetPruningRule> for Experiment {
+    type Result = ResponseActFuture<Self, Result<Audited<()>, ExperimentError>>;
+
+    fn handle(&mut self, msg: SetPruningRule, _: &mut Self::Context) -> Self::Result {
+        if let Err(err) = self.ensure_status(&EDITABLE, "change its rules") {
+            return Box::pin(fut::ready(Err(err)));
+        }
+        let before = self.stats_at(DEFAULT_LEVEL).ok();
+        self.metadata.pruning_rule = msg.pruning_rule;
+        self.audited_snapshot(before)
+    }
+}
+
+impl Handler<ChangeStatus> for Experiment {
+    type Result = ResponseActFuture<Self, Result<Audited<()>, ExperimentError>>;
+
+    // the new status is stored right away, so that a restart cannot undo it
+    fn handle(&mut self, msg: ChangeStatus, ctx: &mut Self::Context) -> Self::Result {
+        let before = self.stats_at(DEFAULT_LEVEL).ok();
+        if let Err(err) = self.change_status(msg.transition) {
+            return Box::pin(fut::ready(Err(err)));
+        }
+        if let Transition::Start = msg.transition {
+            self.follow_schedule(ctx);
+        }
+        self.audited_snapshot(before)
+    }
+}
+
+impl Handler<GetMetadata> for Experiment {
+    type Result = MessageResult<GetMetadata>;
+
+    fn handle(&mut self, _: GetMetadata, _: &mut Self::Context) -> Self::Result {
+        MessageResult(self.metadata.clone())
     }
 }
 
 impl Handler<GetStats> for Experiment {
     type Result = Result<PolicyStats, ExperimentError>;
 
-    fn handle(&mut self, _: GetStats, _: &mut Self::Context) -> Self::Result {
-        self.with_policy_mut(|policy| Ok::<PolicyStats, PolicyError>(policy.stats()))
+    fn handle(&mut self, msg: GetStats, _: &mut Self::Context) -> Self::Result {
+        self.stats_at(msg.level)
+    }
+}
+
+impl Handler<GetPosteriors> for Experiment {
+    type Result = Result<HashMap<usize, ArmPosterior>, ExperimentError>;
+
+    fn handle(&mut self, _: GetPosteriors, _: &mut Self::Context) -> Self::Result {
+        self.with_policy_mut(|policy| {
+            Ok::<HashMap<usize, ArmPosterior>, PolicyError>(policy.posteriors())
+        })
     }
 }
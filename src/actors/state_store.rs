@@ -1,25 +1,43 @@
 use crate::config::StateStoreConfig;
-use crate::policies::Policy;
+use crate::errors::PersistenceError;
+use crate::metrics::METRICS;
+use crate::policies::{get_timestamp, Policy};
+use crate::storage::{
+    make_state_backend, ExperimentMetadata, QuarantinedState, SnapshotInfo, StateBackend,
+    StoredState,
+};
 
 use actix::prelude::*;
-use std::{collections::HashMap, fs, io::BufReader, path::PathBuf};
+use std::collections::HashMap;
+use std::time::Instant;
 use tracing::{info, warn};
 use uuid::Uuid;
 
 pub struct StateStore {
-    config: StateStoreConfig,
+    backend: Box<dyn StateBackend>,
+    history_every: u64,
+    // time and event sequence number of the last snapshot taken of each experiment since startup
+    last_snapshots: HashMap<Uuid, (f64, u64)>,
 }
 
 impl StateStore {
-    pub fn new(config: StateStoreConfig) -> Self {
-        if let Err(err) = fs::create_dir_all(&config.dir) {
-            warn!(error = %err, path = ?config.dir, "Could not create state store directory");
-        }
-        Self { config }
+    pub fn new(config: StateStoreConfig) -> Result<Self, PersistenceError> {
+        let backend = make_state_backend(&config)?;
+        info!(backend = ?config.backend, path = ?config.dir, "Opened state store");
+        Ok(Self {
+            backend,
+            history_every: config.history_every,
+            last_snapshots: HashMap::new(),
+        })
     }
 
-    fn path_for(&self, experiment_id: Uuid) -> PathBuf {
-        self.config.dir.join(format!("{experiment_id}.json"))
+    // periodic saves only add to the history once the experiment has changed and enough time passed
+    fn is_snapshot_due(&self, experiment_id: Uuid, event_seq: u64, now: f64) -> bool {
+        self.last_snapshots
+            .get(&experiment_id)
+            .is_none_or(|&(taken_at, snapshot_seq)| {
+                event_seq > snapshot_seq && now - taken_at >= self.history_every as f64
+            })
     }
 }
 
@@ -36,11 +54,14 @@ impl Actor for StateStore {
 }
 
 // Messages
+// the state covers every event logged by the experiment up to event_seq
 #[derive(Message)]
-#[rtype(result = "()")]
+#[rtype(result = "Result<(), PersistenceError>")]
 pub struct SaveState {
     pub experiment_id: Uuid,
     pub policy: Box<dyn Policy + Send>,
+    pub event_seq: u64,
+    pub metadata: ExperimentMetadata,
 }
 
 #[derive(Message)]
@@ -50,32 +71,59 @@ pub struct DeleteState {
 }
 
 #[derive(Message)]
-#[rtype(result = "Option<Box<dyn Policy + Send>>")]
+#[rtype(result = "Option<StoredState>")]
 pub struct LoadState {
     pub experiment_id: Uuid,
 }
 
 #[derive(Message)]
-#[rtype(result = "HashMap<Uuid, Box<dyn Policy + Send>>")]
+#[rtype(result = "HashMap<Uuid, StoredState>")]
 pub struct LoadAllStates;
 
+#[derive(Message)]
+#[rtype(result = "Result<Vec<QuarantinedState>, PersistenceError>")]
+pub struct ListQuarantinedStates;
+
+#[derive(Message)]
+#[rtype(result = "Result<Vec<SnapshotInfo>, PersistenceError>")]
+pub struct ListSnapshots {
+    pub experiment_id: Uuid,
+}
+
+#[derive(Message)]
+#[rtype(result = "Result<Option<StoredState>, PersistenceError>")]
+pub struct LoadSnapshot {
+    pub experiment_id: Uuid,
+    pub snapshot_id: u64,
+}
+
 // Handlers
 impl Handler<SaveState> for StateStore {
-    type Result = ();
+    type Result = Result<(), PersistenceError>;
 
     fn handle(&mut self, msg: SaveState, _: &mut Self::Context) -> Self::Result {
         info!(id = %msg.experiment_id, "Saving state for experiment");
-        let path = self.path_for(msg.experiment_id);
-        match serde_json::to_string(&msg.policy) {
-            Ok(serialized) => {
-                if let Err(err) = fs::write(&path, serialized) {
-                    warn!(error = %err, id = %msg.experiment_id, "Failed to write experiment state");
-                }
-            }
-            Err(err) => {
-                warn!(error = %err, id = %msg.experiment_id, "Failed to serialize experiment state");
-            }
+        let started_at = Instant::now();
+        let now = get_timestamp();
+        let snapshot = self.is_snapshot_due(msg.experiment_id, msg.event_seq, now);
+        let result = self
+            .backend
+            .save(
+                msg.experiment_id,
+                msg.policy.as_ref(),
+                msg.event_seq,
+                &msg.metadata,
+                snapshot,
+            )
+            .inspect_err(|err| {
+                warn!(error = %err, id = %msg.experiment_id, "Failed to save experiment state")
+            });
+        METRICS.state_store_write(started_at.elapsed(), result.is_ok());
+        if result.is_ok() && snapshot {
+            self.last_snapshots
+                .insert(msg.experiment_id, (now, msg.event_seq));
         }
+        result
     }
 }
 
@@ -84,27 +132,22 @@ impl Handler<DeleteState> for StateStore {
 
     fn handle(&mut self, msg: DeleteState, _: &mut Self::Context) -> Self::Result {
         info!(id = %msg.experiment_id, "Deleting state for experiment");
-        let path = self.path_for(msg.experiment_id);
-        if let Err(err) = fs::remove_file(&path) {
-            if err.kind() != std::io::ErrorKind::NotFound {
-                warn!(error = %err, id = %msg.experiment_id, "Failed to delete experiment state");
-            }
+        self.last_snapshots.remove(&msg.experiment_id);
+        if let Err(err) = self.backend.delete(msg.experiment_id) {
+            warn!(error = %err, id = %msg.experiment_id, "Failed to delete experiment state");
         }
     }
 }
 
 impl Handler<LoadState> for StateStore {
-    type Result = Option<Box<dyn Policy + Send>>;
+    type Result = Option<StoredState>;
 
     fn handle(&mut self, msg: LoadState, _: &mut Self::Context) -> Self::Result {
-        let path = self.path_for(msg.experiment_id);
-        let file = fs::File::open(&path).ok()?;
-        let reader = BufReader::new(file);
-        serde_json::from_reader(reader)
-            .map_err(
-                |err| warn!(error = %err, id = %msg.experiment_id, "Failed to deserialize state"),
-            )
+        self.backend
+            .load(msg.experiment_id)
+            .map_err(|err| warn!(error = %err, id = %msg.experiment_id, "Failed to load state"))
             .ok()
+            .flatten()
     }
 }
 
@@ -112,43 +155,36 @@ impl Handler<LoadAllStates> for StateStore {
     type Result = MessageResult<LoadAllStates>;
 
     fn handle(&mut self, _: LoadAllStates, _: &mut Self::Context) -> Self::Result {
-        let mut states: HashMap<Uuid, Box<dyn Policy + Send>> = HashMap::new();
-
-        let entries = match fs::read_dir(&self.config.dir) {
-            Ok(e) => e,
-            Err(err) => {
-                warn!(error = %err, path = ?self.config.dir, "Failed to read state store directory");
-                return MessageResult(states);
-            }
-        };
-
-        for entry in entries.flatten() {
-            let path = entry.path();
-            if path.extension().and_then(|e| e.to_str()) != Some("json") {
-                continue;
-            }
-            let Some(stem) = path.file_stem().and_then(|s| s.to_str()) else {
-                continue;
-            };
-            let Ok(experiment_id) = Uuid::try_parse(stem) else {
-                warn!(path = ?path, "Skipping file with non-UUID name in state store directory");
-                continue;
-            };
-            match fs::File::open(&path).map(BufReader::new) {
-                Ok(reader) => match serde_json::from_reader(reader) {
-                    Ok(policy) => {
-                        states.insert(experiment_id, policy);
-                    }
-                    Err(err) => {
-                        warn!(error = %err, id = %experiment_id, "Failed to deserialize state");
-                    }
-                },
-                Err(err) => {
-                    warn!(error = %err, id = %experiment_id, "Failed to open state file");
-                }
-            }
-        }
-
+        let states = self.backend.load_all().unwrap_or_else(|err| {
+            warn!(error = %err, "Failed to load experiment states");
+            HashMap::new()
+        });
         MessageResult(states)
     }
 }
+
+impl Handler<ListQuarantinedStates> for StateStore {
+    type Result = Result<Vec<QuarantinedState>, PersistenceError>;
+
+    fn handle(&mut self, _: ListQuarantinedStates, _: &mut Self::Context) -> Self::Result {
+        self.backend.quarantined()
+    }
+}
+
+impl Handler<ListSnapshots> for StateStore {
+    type Result = Result<Vec<SnapshotInfo>, PersistenceError>;
+
+    fn handle(&mut self, msg: ListSnapshots, _: &mut Self::Context) -> Self::Result {
+        self.backend.snapshots(msg.experiment_id)
+    }
+}
+
+impl Handler<LoadSnapshot> for StateStore {
+    type Result = Result<Option<StoredState>, PersistenceError>;
+
+    fn handle(&mut self, msg: LoadSnapshot, _: &mut Self::Context) -> Self::Result {
+        info!(id = %msg.experiment_id, snapshot_id = msg.snapshot_id, "Loading snapshot for experiment");
+        self.backend
+            .load_snapshot(msg.experiment_id, msg.snapshot_id)
+    }
+}
@@ -0,0 +1,70 @@
+use crate::actors::accountant::{Accountant, RecordAudit};
+use crate::policies::{get_timestamp, PolicyStats};
+
+use actix::Addr;
+use actix_web::HttpRequest;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    Create,
+    Reset,
+    ResetArm,
+    Delete,
+    AddArm,
+    DisableArm,
+    EnableArm,
+    DeleteArm,
+    SetStoppingRule,
+    SetPruningRule,
+    Start,
+    Pause,
+    Resume,
+    Conclude,
+    Archive,
+    Clear,
+    RestoreSnapshot,
+}
+
+// A change made to an experiment through the API, along with who made it and its effect on the arms
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuditEvent {
+    pub id: Uuid,
+    pub timestamp: f64,
+    pub experiment_id: Uuid,
+    pub action: AuditAction,
+    pub client: Option<String>,
+    pub payload: Value,
+    pub before: Option<PolicyStats>,
+    pub after: Option<PolicyStats>,
+}
+
+// the client is identified by its address, as forwarded by a proxy if any
+pub(super) fn record_audit(
+    accountant: &Addr<Accountant>,
+    request: &HttpRequest,
+    experiment_id: Uuid,
+    action: AuditAction,
+    payload: Value,
+    before: Option<PolicyStats>,
+    after: Option<PolicyStats>,
+) {
+    accountant.do_send(RecordAudit {
+        event: AuditEvent {
+            id: Uuid::new_v4(),
+            timestamp: get_timestamp(),
+            experiment_id,
+            action,
+            client: request
+                .connection_info()
+                .realip_remote_addr()
+                .map(str::to_string),
+            payload,
+            before,
+            after,
+        },
+    });
+}
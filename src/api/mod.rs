@@ -1,3 +1,4 @@
+pub mod audit;
 mod requests;
 pub mod responses;
 pub mod routes;
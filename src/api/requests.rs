@@ -1,6 +1,11 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use uuid::Uuid;
 
-use crate::policies::BatchUpdateElement;
+use crate::evaluation::LoggedInteraction;
+use crate::policies::{BatchUpdateElement, PolicyType};
+use crate::storage::{ExportFormat, Schedule};
 
 #[derive(Debug, Deserialize)]
 pub(super) struct AddArmPayload {
@@ -14,24 +19,138 @@ pub(super) struct ResetArmPayload {
     pub count: Option<u64>,
 }
 
+#[derive(Debug, Deserialize)]
+pub(super) struct DrawPayload {
+    pub context: Option<Vec<f64>>,
+}
+
+// a reward is attributed through the ticket of the draw that served it, or to a raw arm id along
+// with its context when the experiment allows raw updates
 #[derive(Debug, Deserialize)]
 pub(super) struct UpdatePayload {
+    pub timestamp: f64,
+    pub ticket: Option<Uuid>,
+    pub arm_id: Option<usize>,
+    pub reward: f64,
+    pub context: Option<Vec<f64>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(super) struct UpdateBatchElementPayload {
     pub timestamp: f64,
     pub arm_id: usize,
     pub reward: f64,
+    pub context: Option<Vec<f64>>,
 }
 
-impl Into<BatchUpdateElement> for UpdatePayload {
-    fn into(self) -> BatchUpdateElement {
-        BatchUpdateElement {
-            timestamp: self.timestamp,
-            arm_id: self.arm_id,
-            reward: self.reward,
+impl From<UpdateBatchElementPayload> for BatchUpdateElement {
+    fn from(payload: UpdateBatchElementPayload) -> Self {
+        Self {
+            timestamp: payload.timestamp,
+            arm_id: payload.arm_id,
+            reward: payload.reward,
+            context: payload.context,
         }
     }
 }
 
 #[derive(Debug, Deserialize)]
 pub(super) struct UpdateBatchPayload {
-    pub updates: Vec<UpdatePayload>,
+    pub updates: Vec<UpdateBatchElementPayload>,
+    #[serde(default)]
+    pub skip_invalid: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub(super) struct EvaluatePayload {
+    pub policy: PolicyType,
+    pub logs: Vec<LoggedInteraction>,
+    pub level: Option<f64>,
+}
+
+// format and time range of the interactions to export, in seconds since the epoch
+#[derive(Debug, Deserialize)]
+pub(super) struct ExportQuery {
+    #[serde(default)]
+    pub format: ExportFormat,
+    pub from: Option<f64>,
+    pub to: Option<f64>,
+}
+
+// the policy of a new experiment, along with the optional times at which it starts and ends
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(try_from = "FlatCreatePayload")]
+pub(super) struct CreatePayload {
+    #[serde(flatten)]
+    pub policy: PolicyType,
+    #[serde(flatten)]
+    pub schedule: Schedule,
+    // rewards can be sent for a raw arm id, bypassing draw tickets
+    #[serde(default)]
+    pub raw_updates: bool,
+}
+
+// flattened fields cannot deny unknown ones, so the keys left over by the policy and the schedule
+// are collected to be rejected
+#[derive(Deserialize)]
+struct FlatCreatePayload {
+    #[serde(flatten)]
+    policy: PolicyType,
+    #[serde(flatten)]
+    schedule: Schedule,
+    #[serde(default)]
+    raw_updates: bool,
+    #[serde(flatten)]
+    unknown: BTreeMap<String, Value>,
+}
+
+impl TryFrom<FlatCreatePayload> for CreatePayload {
+    type Error = String;
+
+    fn try_from(payload: FlatCreatePayload) -> Result<Self, Self::Error> {
+        if let Some(key) = payload.unknown.keys().next() {
+            return Err(format!("unknown field `{key}`"));
+        }
+        Ok(Self {
+            policy: payload.policy,
+            schedule: payload.schedule,
+            raw_updates: payload.raw_updates,
+        })
+    }
+}
+
+// draft experiments serve no draw until they are started
+#[derive(Debug, Deserialize)]
+pub(super) struct CreateQuery {
+    #[serde(default)]
+    pub draft: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub(super) struct PausePayload {
+    pub fallback_arm: usize,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub(super) struct ConcludePayload {
+    pub winner: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(super) struct StatsQuery {
+    pub level: Option<f64>,
+}
+
+// time range of the audit events to return, in seconds since the epoch
+#[derive(Debug, Deserialize)]
+pub(super) struct AuditQuery {
+    pub from: Option<f64>,
+    pub to: Option<f64>,
+}
+
+// evaluation against the interactions logged by an experiment
+#[derive(Debug, Deserialize)]
+pub(super) struct EvaluateExperimentPayload {
+    pub policy: PolicyType,
+    pub level: Option<f64>,
 }
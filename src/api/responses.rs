@@ -1,6 +1,11 @@
 use crate::actors::accountant::{Accountant, LogResponse};
+use crate::actors::experiment::TicketedDraw;
+use crate::api::audit::AuditEvent;
 use crate::errors::{ApiError, ServiceError};
-use crate::policies::{DrawResult, PolicyType};
+use crate::evaluation::{Conclusion, PrunedArm, PruningRule, StoppingRule};
+use crate::metrics::METRICS;
+use crate::policies::PolicyType;
+use crate::storage::{ExperimentStatus, QuarantinedState, Schedule, SnapshotInfo};
 
 use actix::Addr;
 use actix_web::{
@@ -13,7 +18,7 @@ use serde::Serialize;
 use std::{
     collections::HashMap,
     fmt::Debug,
-    time::{SystemTime, UNIX_EPOCH},
+    time::{Instant, SystemTime, UNIX_EPOCH},
 };
 use tracing::info;
 use uuid::Uuid;
@@ -27,9 +32,17 @@ pub async fn log_response(
     let request_id = Uuid::new_v4();
 
     let accountant = request.app_data::<Data<Addr<Accountant>>>().cloned();
+    let started_at = Instant::now();
     let response = next.call(request).await?;
     let status = response.status();
 
+    // unknown paths are grouped together so that they cannot flood the metrics with series
+    let route = response
+        .request()
+        .match_pattern()
+        .unwrap_or_else(|| "unmatched".to_string());
+    METRICS.observe_request(&route, &method, status.as_u16(), started_at.elapsed());
+
     info!(
         method = %method,
         path = %path,
@@ -77,6 +90,43 @@ pub struct ListExperimentsResponse {
     pub experiments: HashMap<Uuid, PolicyType>,
 }
 
+#[derive(Debug, Serialize)]
+pub(super) struct ListQuarantinedStatesResponse {
+    pub states: Vec<QuarantinedState>,
+}
+
+#[derive(Debug, Serialize)]
+pub(super) struct AuditTrailResponse {
+    pub events: Vec<AuditEvent>,
+}
+
+#[derive(Debug, Serialize)]
+pub(super) struct ExperimentStatusResponse {
+    pub status: ExperimentStatus,
+    pub created_at: f64,
+    pub started_at: Option<f64>,
+    pub fallback_arm: Option<usize>,
+    pub schedule: Schedule,
+    pub conclusion: Option<Conclusion>,
+}
+
+#[derive(Debug, Serialize)]
+pub(super) struct StoppingRuleResponse {
+    pub stopping_rule: Option<StoppingRule>,
+    pub conclusion: Option<Conclusion>,
+}
+
+#[derive(Debug, Serialize)]
+pub(super) struct PruningRuleResponse {
+    pub pruning_rule: Option<PruningRule>,
+    pub pruned_arms: HashMap<usize, PrunedArm>,
+}
+
+#[derive(Debug, Serialize)]
+pub(super) struct ListSnapshotsResponse {
+    pub snapshots: Vec<SnapshotInfo>,
+}
+
 #[derive(Debug, Serialize)]
 pub(super) struct CreateExperimentResponse {
     pub experiment_id: Uuid,
@@ -89,15 +139,19 @@ pub(super) struct AddExperimentArmResponse {
 
 #[derive(Debug, Serialize)]
 pub(super) struct DrawResponse {
+    pub ticket: Uuid,
     pub timestamp: f64,
     pub arm_id: usize,
+    pub propensity: Option<f64>,
 }
 
-impl From<DrawResult> for DrawResponse {
-    fn from(draw_result: DrawResult) -> Self {
+impl From<TicketedDraw> for DrawResponse {
+    fn from(draw: TicketedDraw) -> Self {
         Self {
-            timestamp: draw_result.timestamp,
-            arm_id: draw_result.arm_id,
+            ticket: draw.ticket,
+            timestamp: draw.result.timestamp,
+            arm_id: draw.result.arm_id,
+            propensity: draw.result.propensity,
         }
     }
 }
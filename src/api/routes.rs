@@ -1,27 +1,57 @@
 use tokio::sync::RwLock;
 
-use super::requests::{AddArmPayload, UpdateBatchPayload, UpdatePayload};
+use super::audit::{record_audit, AuditAction};
+use super::requests::{
+    AddArmPayload, AuditQuery, ConcludePayload, CreatePayload, CreateQuery, DrawPayload,
+    EvaluateExperimentPayload, EvaluatePayload, ExportQuery, PausePayload, StatsQuery,
+    UpdateBatchPayload, UpdatePayload,
+};
 use super::responses::{
-    AddExperimentArmResponse, CreateExperimentResponse, DrawResponse, ListExperimentsResponse,
+    AddExperimentArmResponse, AuditTrailResponse, CreateExperimentResponse, DrawResponse,
+    ExperimentStatusResponse, ListExperimentsResponse, ListQuarantinedStatesResponse,
+    ListSnapshotsResponse, PruningRuleResponse, StoppingRuleResponse,
 };
 
+use crate::actors::accountant::{Accountant, GetAuditTrail};
+use crate::actors::experiment::Transition;
 use crate::api::requests::ResetArmPayload;
-use crate::errors::ApiError;
-use crate::policies::PolicyType;
+use crate::errors::{ApiError, ServiceError};
+use crate::evaluation::{analyze, evaluate, PruningRule, StoppingRule, ANALYSIS_SAMPLES};
+use crate::metrics::METRICS;
+use crate::policies::{get_timestamp, DEFAULT_LEVEL};
 use crate::repository::Repository;
 
+use actix::Addr;
 use actix_web::{
-    delete, get, post, put,
-    web::{Data, Json, Path},
-    HttpResponse, Responder, Result,
+    delete, get, post, put, rt,
+    web::{Bytes, Data, Json, Path, Query},
+    HttpRequest, HttpResponse, Responder, Result,
 };
+use futures_util::stream;
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
 use uuid::Uuid;
 
+// exported chunks read ahead of a slow client
+const EXPORT_BUFFER_SIZE: usize = 4;
+
 #[get("ping")]
 async fn ping() -> Result<impl Responder> {
     Ok(HttpResponse::Ok().finish())
 }
 
+#[get("/metrics")]
+async fn metrics() -> Result<impl Responder> {
+    let body = METRICS
+        .render()
+        .map_err(ServiceError::from)
+        .map_err(ApiError::from)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(body))
+}
+
 #[get("list")]
 async fn list(repository: Data<RwLock<Repository>>) -> Result<impl Responder> {
     let experiments = repository
@@ -35,8 +65,28 @@ async fn list(repository: Data<RwLock<Repository>>) -> Result<impl Responder> {
 }
 
 #[delete("clear")]
-async fn clear(repository: Data<RwLock<Repository>>) -> Result<impl Responder> {
-    repository.write().await.clear();
+async fn clear(
+    repository: Data<RwLock<Repository>>,
+    accountant: Data<Addr<Accountant>>,
+    request: HttpRequest,
+) -> Result<impl Responder> {
+    // the stats are collected once the repository is released
+    let deleted = repository.write().await.clear();
+
+    deleted
+        .await
+        .into_iter()
+        .for_each(|(experiment_id, before)| {
+            record_audit(
+                &accountant,
+                &request,
+                experiment_id,
+                AuditAction::Clear,
+                Value::Null,
+                before,
+                None,
+            )
+        });
 
     Ok(HttpResponse::Ok())
 }
@@ -44,13 +94,39 @@ async fn clear(repository: Data<RwLock<Repository>>) -> Result<impl Responder> {
 #[post("create")]
 async fn create(
     repository: Data<RwLock<Repository>>,
-    policy_type: Json<PolicyType>,
+    accountant: Data<Addr<Accountant>>,
+    request: HttpRequest,
+    query: Query<CreateQuery>,
+    payload: Json<CreatePayload>,
 ) -> Result<impl Responder> {
-    let policy_type = policy_type.into_inner();
-    let experiment_id = repository
-        .write()
-        .await
-        .create_experiment(None, policy_type.into_inner());
+    let payload = payload.into_inner();
+    payload
+        .schedule
+        .validate(get_timestamp())
+        .map_err(ApiError::InvalidPayload)?;
+    payload
+        .policy
+        .validate()
+        .map_err(ApiError::InvalidPayload)?;
+    let audit_payload = json!(payload);
+    let policy = payload.policy.into_inner();
+    let after = Some(policy.stats_at(DEFAULT_LEVEL));
+    let mut repository = repository.write().await;
+    let experiment_id = if query.into_inner().draft {
+        repository.create_draft_experiment(policy, payload.schedule, payload.raw_updates)
+    } else {
+        repository.create_scheduled_experiment(policy, payload.schedule, payload.raw_updates)
+    };
+
+    record_audit(
+        &accountant,
+        &request,
+        experiment_id,
+        AuditAction::Create,
+        audit_payload,
+        None,
+        after,
+    );
 
     Ok(Json(CreateExperimentResponse { experiment_id }))
 }
@@ -73,22 +149,38 @@ async fn ping_experiment(
 }
 
 #[put("{experiment_id}/reset")]
-async fn reset(repository: Data<RwLock<Repository>>, path: Path<String>) -> Result<impl Responder> {
+async fn reset(
+    repository: Data<RwLock<Repository>>,
+    accountant: Data<Addr<Accountant>>,
+    request: HttpRequest,
+    path: Path<String>,
+) -> Result<impl Responder> {
     let experiment_id = Uuid::try_parse(&path.into_inner()).map_err(ApiError::from)?;
-    let response = repository
+    let audited = repository
         .read()
         .await
         .reset_experiment(experiment_id, None, None, None)
         .await
-        .map(|()| HttpResponse::Ok())
         .map_err(ApiError::from)?;
 
-    Ok(response)
+    record_audit(
+        &accountant,
+        &request,
+        experiment_id,
+        AuditAction::Reset,
+        Value::Null,
+        audited.before,
+        audited.after,
+    );
+
+    Ok(HttpResponse::Ok())
 }
 
 #[put("{experiment_id}/{arm_id}/reset")]
 async fn reset_arm(
     repository: Data<RwLock<Repository>>,
+    accountant: Data<Addr<Accountant>>,
+    request: HttpRequest,
     path: Path<(String, usize)>,
     payload: Json<ResetArmPayload>,
 ) -> Result<impl Responder> {
@@ -98,36 +190,60 @@ async fn reset_arm(
         cumulative_reward,
         count,
     } = payload.into_inner();
-    let response = repository
+    let audited = repository
         .read()
         .await
         .reset_experiment(experiment_id, Some(arm_id), cumulative_reward, count)
         .await
-        .map(|()| HttpResponse::Ok())
         .map_err(ApiError::from)?;
 
-    Ok(response)
+    record_audit(
+        &accountant,
+        &request,
+        experiment_id,
+        AuditAction::ResetArm,
+        json!({"arm_id": arm_id, "cumulative_reward": cumulative_reward, "count": count}),
+        audited.before,
+        audited.after,
+    );
+
+    Ok(HttpResponse::Ok())
 }
 
 #[delete("{experiment_id}/delete")]
 async fn delete_experiment(
     repository: Data<RwLock<Repository>>,
+    accountant: Data<Addr<Accountant>>,
+    request: HttpRequest,
     path: Path<String>,
 ) -> Result<impl Responder> {
     let experiment_id = Uuid::try_parse(&path.into_inner()).map_err(ApiError::from)?;
-    let response = repository
+    // the stats are collected once the repository is released
+    let deleted = repository
         .write()
         .await
         .delete_experiment(experiment_id)
-        .map(|()| HttpResponse::Ok())
         .map_err(ApiError::from)?;
+    let before = deleted.await;
 
-    Ok(response)
+    record_audit(
+        &accountant,
+        &request,
+        experiment_id,
+        AuditAction::Delete,
+        Value::Null,
+        before,
+        None,
+    );
+
+    Ok(HttpResponse::Ok())
 }
 
 #[post("{experiment_id}/add_arm")]
 async fn add_arm(
     repository: Data<RwLock<Repository>>,
+    accountant: Data<Addr<Accountant>>,
+    request: HttpRequest,
     path: Path<String>,
     payload: Json<AddArmPayload>,
 ) -> Result<impl Responder> {
@@ -136,77 +252,149 @@ async fn add_arm(
         initial_reward,
         initial_count,
     } = payload.into_inner();
-    let arm_id = repository
+    let audited = repository
         .read()
         .await
         .add_experiment_arm(experiment_id, initial_reward, initial_count)
         .await
         .map_err(ApiError::from)?;
 
-    Ok(Json(AddExperimentArmResponse { arm_id }))
+    record_audit(
+        &accountant,
+        &request,
+        experiment_id,
+        AuditAction::AddArm,
+        json!({"initial_reward": initial_reward, "initial_count": initial_count}),
+        audited.before,
+        audited.after,
+    );
+
+    Ok(Json(AddExperimentArmResponse {
+        arm_id: audited.value,
+    }))
 }
 
 #[put("{experiment_id}/{arm_id}/disable")]
 async fn disable_arm(
     repository: Data<RwLock<Repository>>,
+    accountant: Data<Addr<Accountant>>,
+    request: HttpRequest,
     path: Path<(String, usize)>,
 ) -> Result<impl Responder> {
     let (experiment_id, arm_id) = path.into_inner();
     let experiment_id = Uuid::try_parse(&experiment_id).map_err(ApiError::from)?;
-    let response = repository
+    let audited = repository
         .read()
         .await
         .disable_experiment_arm(experiment_id, arm_id)
         .await
-        .map(|()| HttpResponse::Ok())
         .map_err(ApiError::from)?;
 
-    Ok(response)
+    record_audit(
+        &accountant,
+        &request,
+        experiment_id,
+        AuditAction::DisableArm,
+        json!({"arm_id": arm_id}),
+        audited.before,
+        audited.after,
+    );
+
+    Ok(HttpResponse::Ok())
 }
 
 #[put("{experiment_id}/{arm_id}/enable")]
 async fn enable_arm(
     repository: Data<RwLock<Repository>>,
+    accountant: Data<Addr<Accountant>>,
+    request: HttpRequest,
     path: Path<(String, usize)>,
 ) -> Result<impl Responder> {
     let (experiment_id, arm_id) = path.into_inner();
     let experiment_id = Uuid::try_parse(&experiment_id).map_err(ApiError::from)?;
-    let response = repository
+    let audited = repository
         .read()
         .await
         .enable_experiment_arm(experiment_id, arm_id)
         .await
-        .map(|()| HttpResponse::Ok())
         .map_err(ApiError::from)?;
 
-    Ok(response)
+    record_audit(
+        &accountant,
+        &request,
+        experiment_id,
+        AuditAction::EnableArm,
+        json!({"arm_id": arm_id}),
+        audited.before,
+        audited.after,
+    );
+
+    Ok(HttpResponse::Ok())
 }
 
 #[delete("{experiment_id}/{arm_id}")]
 async fn delete_arm(
     repository: Data<RwLock<Repository>>,
+    accountant: Data<Addr<Accountant>>,
+    request: HttpRequest,
     path: Path<(String, usize)>,
 ) -> Result<impl Responder> {
     let (experiment_id, arm_id) = path.into_inner();
     let experiment_id = Uuid::try_parse(&experiment_id).map_err(ApiError::from)?;
-    let response = repository
+    let audited = repository
         .read()
         .await
         .delete_experiment_arm(experiment_id, arm_id)
         .await
-        .map(|()| HttpResponse::Ok())
         .map_err(ApiError::from)?;
 
-    Ok(response)
+    record_audit(
+        &accountant,
+        &request,
+        experiment_id,
+        AuditAction::DeleteArm,
+        json!({"arm_id": arm_id}),
+        audited.before,
+        audited.after,
+    );
+
+    Ok(HttpResponse::Ok())
 }
 
+// draws without context, for which a request without body is enough
 #[get("{experiment_id}/draw")]
 async fn draw(repository: Data<RwLock<Repository>>, path: Path<String>) -> Result<impl Responder> {
-    let experiment_id = Uuid::try_parse(&path.into_inner()).map_err(ApiError::from)?;
+    serve_draw(repository, path.into_inner(), None).await
+}
+
+// contextual policies take their context from the body, which a GET request may lose on its way
+#[post("{experiment_id}/draw")]
+async fn draw_with_context(
+    repository: Data<RwLock<Repository>>,
+    path: Path<String>,
+    body: Bytes,
+) -> Result<impl Responder> {
+    // the payload is optional as only contextual policies need it, but a malformed one is rejected
+    let context = if body.is_empty() {
+        None
+    } else {
+        serde_json::from_slice::<DrawPayload>(&body)
+            .map_err(|_| ApiError::InvalidPayload("malformed draw payload"))?
+            .context
+    };
+    serve_draw(repository, path.into_inner(), context).await
+}
+
+async fn serve_draw(
+    repository: Data<RwLock<Repository>>,
+    experiment_id: String,
+    context: Option<Vec<f64>>,
+) -> Result<Json<DrawResponse>> {
+    let experiment_id = Uuid::try_parse(&experiment_id).map_err(ApiError::from)?;
     let draw_result = repository
         .read()
         .await
-        .draw_experiment(experiment_id)
+        .draw_experiment(experiment_id, context)
         .await
         .map_err(ApiError::from)?;
 
@@ -222,14 +410,36 @@ async fn update(
     let experiment_id = Uuid::try_parse(&path.into_inner()).map_err(ApiError::from)?;
     let UpdatePayload {
         timestamp,
+        ticket,
         arm_id,
         reward,
+        context,
     } = payload.into_inner();
-    let response = repository
-        .read()
-        .await
-        .update_experiment(experiment_id, timestamp, arm_id, reward)
-        .await
+    let repository = repository.read().await;
+    let result = match (ticket, arm_id) {
+        (Some(_), Some(_)) => {
+            return Err(ApiError::InvalidPayload("ticket and arm_id are mutually exclusive").into())
+        }
+        (Some(_), None) if context.is_some() => {
+            return Err(
+                ApiError::InvalidPayload("context is taken from the draw of the ticket").into(),
+            )
+        }
+        (Some(ticket), None) => {
+            repository
+                .redeem_experiment_ticket(experiment_id, ticket, timestamp, reward)
+                .await
+        }
+        (None, Some(arm_id)) => {
+            repository
+                .update_experiment(experiment_id, timestamp, arm_id, reward, context)
+                .await
+        }
+        (None, None) => {
+            return Err(ApiError::InvalidPayload("either ticket or arm_id is required").into())
+        }
+    };
+    let response = result
         .map(|()| HttpResponse::Ok())
         .map_err(ApiError::from)?;
 
@@ -243,34 +453,690 @@ async fn update_batch(
     payload: Json<UpdateBatchPayload>,
 ) -> Result<impl Responder> {
     let experiment_id = Uuid::try_parse(&path.into_inner()).map_err(ApiError::from)?;
-    let updates = payload
-        .into_inner()
-        .updates
-        .into_iter()
-        .map(Into::into)
-        .collect();
+    let UpdateBatchPayload {
+        updates,
+        skip_invalid,
+    } = payload.into_inner();
+    let updates = updates.into_iter().map(Into::into).collect();
 
     let response = repository
         .read()
         .await
-        .batch_update_experiment(experiment_id, updates)
+        .batch_update_experiment(experiment_id, updates, skip_invalid)
         .await
-        .map(|()| HttpResponse::Ok())
+        .map(Json)
         .map_err(ApiError::from)?;
 
     Ok(response)
 }
 
 #[get("{experiment_id}/stats")]
-async fn stats(repository: Data<RwLock<Repository>>, path: Path<String>) -> Result<impl Responder> {
+async fn stats(
+    repository: Data<RwLock<Repository>>,
+    path: Path<String>,
+    query: Query<StatsQuery>,
+) -> Result<impl Responder> {
     let experiment_id = Uuid::try_parse(&path.into_inner()).map_err(ApiError::from)?;
+    let level = query.into_inner().level.unwrap_or(DEFAULT_LEVEL);
+    if !(level > 0.0 && level < 1.0) {
+        return Err(ApiError::InvalidPayload("level must be in (0, 1)").into());
+    }
     let response = repository
         .read()
         .await
-        .get_experiment_stats(experiment_id)
+        .get_experiment_stats_at(experiment_id, level)
         .await
         .map(Json)
         .map_err(ApiError::from)?;
 
     Ok(response)
 }
+
+#[get("{experiment_id}/status")]
+async fn experiment_status(
+    repository: Data<RwLock<Repository>>,
+    path: Path<String>,
+) -> Result<impl Responder> {
+    let experiment_id = Uuid::try_parse(&path.into_inner()).map_err(ApiError::from)?;
+    let metadata = repository
+        .read()
+        .await
+        .get_experiment_metadata(experiment_id)
+        .await
+        .map_err(ApiError::from)?;
+
+    Ok(Json(ExperimentStatusResponse {
+        status: metadata.status,
+        created_at: metadata.created_at,
+        started_at: metadata.started_at,
+        fallback_arm: metadata.fallback_arm,
+        schedule: metadata.schedule,
+        conclusion: metadata.conclusion,
+    }))
+}
+
+// shared by the routes moving an experiment through its lifecycle
+async fn change_status(
+    repository: Data<RwLock<Repository>>,
+    accountant: Data<Addr<Accountant>>,
+    request: HttpRequest,
+    experiment_id: String,
+    transition: Transition,
+    action: AuditAction,
+    payload: Value,
+) -> Result<HttpResponse> {
+    let experiment_id = Uuid::try_parse(&experiment_id).map_err(ApiError::from)?;
+    let audited = repository
+        .read()
+        .await
+        .change_experiment_status(experiment_id, transition)
+        .await
+        .map_err(ApiError::from)?;
+
+    record_audit(
+        &accountant,
+        &request,
+        experiment_id,
+        action,
+        payload,
+        audited.before,
+        audited.after,
+    );
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[put("{experiment_id}/start")]
+async fn start(
+    repository: Data<RwLock<Repository>>,
+    accountant: Data<Addr<Accountant>>,
+    request: HttpRequest,
+    path: Path<String>,
+) -> Result<impl Responder> {
+    change_status(
+        repository,
+        accountant,
+        request,
+        path.into_inner(),
+        Transition::Start,
+        AuditAction::Start,
+        Value::Null,
+    )
+    .await
+}
+
+#[put("{experiment_id}/pause")]
+async fn pause(
+    repository: Data<RwLock<Repository>>,
+    accountant: Data<Addr<Accountant>>,
+    request: HttpRequest,
+    path: Path<String>,
+    payload: Json<PausePayload>,
+) -> Result<impl Responder> {
+    let PausePayload { fallback_arm } = payload.into_inner();
+    change_status(
+        repository,
+        accountant,
+        request,
+        path.into_inner(),
+        Transition::Pause { fallback_arm },
+        AuditAction::Pause,
+        json!({"fallback_arm": fallback_arm}),
+    )
+    .await
+}
+
+#[put("{experiment_id}/resume")]
+async fn resume(
+    repository: Data<RwLock<Repository>>,
+    accountant: Data<Addr<Accountant>>,
+    request: HttpRequest,
+    path: Path<String>,
+) -> Result<impl Responder> {
+    change_status(
+        repository,
+        accountant,
+        request,
+        path.into_inner(),
+        Transition::Resume,
+        AuditAction::Resume,
+        Value::Null,
+    )
+    .await
+}
+
+// the winner is optional, the leader of a fresh analysis being declared otherwise
+#[put("{experiment_id}/conclude")]
+async fn conclude(
+    repository: Data<RwLock<Repository>>,
+    accountant: Data<Addr<Accountant>>,
+    request: HttpRequest,
+    path: Path<String>,
+    payload: Option<Json<ConcludePayload>>,
+) -> Result<impl Responder> {
+    let ConcludePayload { winner } = payload.map(Json::into_inner).unwrap_or_default();
+    change_status(
+        repository,
+        accountant,
+        request,
+        path.into_inner(),
+        Transition::Conclude { winner },
+        AuditAction::Conclude,
+        json!({"winner": winner}),
+    )
+    .await
+}
+
+#[put("{experiment_id}/archive")]
+async fn archive(
+    repository: Data<RwLock<Repository>>,
+    accountant: Data<Addr<Accountant>>,
+    request: HttpRequest,
+    path: Path<String>,
+) -> Result<impl Responder> {
+    change_status(
+        repository,
+        accountant,
+        request,
+        path.into_inner(),
+        Transition::Archive,
+        AuditAction::Archive,
+        Value::Null,
+    )
+    .await
+}
+
+#[get("{experiment_id}/stopping_rule")]
+async fn get_stopping_rule(
+    repository: Data<RwLock<Repository>>,
+    path: Path<String>,
+) -> Result<impl Responder> {
+    let experiment_id = Uuid::try_parse(&path.into_inner()).map_err(ApiError::from)?;
+    let metadata = repository
+        .read()
+        .await
+        .get_experiment_metadata(experiment_id)
+        .await
+        .map_err(ApiError::from)?;
+
+    Ok(Json(StoppingRuleResponse {
+        stopping_rule: metadata.stopping_rule,
+        conclusion: metadata.conclusion,
+    }))
+}
+
+// an empty rule removes the current one
+#[put("{experiment_id}/stopping_rule")]
+async fn set_stopping_rule(
+    repository: Data<RwLock<Repository>>,
+    accountant: Data<Addr<Accountant>>,
+    request: HttpRequest,
+    path: Path<String>,
+    payload: Json<StoppingRule>,
+) -> Result<impl Responder> {
+    let experiment_id = Uuid::try_parse(&path.into_inner()).map_err(ApiError::from)?;
+    let stopping_rule = payload.into_inner();
+    stopping_rule.validate().map_err(ApiError::InvalidPayload)?;
+    let audited = repository
+        .read()
+        .await
+        .set_experiment_stopping_rule(experiment_id, stopping_rule.clone())
+        .await
+        .map_err(ApiError::from)?;
+
+    record_audit(
+        &accountant,
+        &request,
+        experiment_id,
+        AuditAction::SetStoppingRule,
+        json!(stopping_rule),
+        audited.before,
+        audited.after,
+    );
+
+    Ok(HttpResponse::Ok())
+}
+
+#[get("{experiment_id}/pruning_rule")]
+async fn get_pruning_rule(
+    repository: Data<RwLock<Repository>>,
+    path: Path<String>,
+) -> Result<impl Responder> {
+    let experiment_id = Uuid::try_parse(&path.into_inner()).map_err(ApiError::from)?;
+    let metadata = repository
+        .read()
+        .await
+        .get_experiment_metadata(experiment_id)
+        .await
+        .map_err(ApiError::from)?;
+
+    Ok(Json(PruningRuleResponse {
+        pruning_rule: metadata.pruning_rule,
+        pruned_arms: metadata.pruned_arms,
+    }))
+}
+
+// a null rule removes the current one
+#[put("{experiment_id}/pruning_rule")]
+async fn set_pruning_rule(
+    repository: Data<RwLock<Repository>>,
+    accountant: Data<Addr<Accountant>>,
+    request: HttpRequest,
+    path: Path<String>,
+    payload: Json<Option<PruningRule>>,
+) -> Result<impl Responder> {
+    let experiment_id = Uuid::try_parse(&path.into_inner()).map_err(ApiError::from)?;
+    let pruning_rule = payload.into_inner();
+    if let Some(pruning_rule) = &pruning_rule {
+        pruning_rule.validate().map_err(ApiError::InvalidPayload)?;
+    }
+    let audited = repository
+        .read()
+        .await
+        .set_experiment_pruning_rule(experiment_id, pruning_rule.clone())
+        .await
+        .map_err(ApiError::from)?;
+
+    record_audit(
+        &accountant,
+        &request,
+        experiment_id,
+        AuditAction::SetPruningRule,
+        json!(pruning_rule),
+        audited.before,
+        audited.after,
+    );
+
+    Ok(HttpResponse::Ok())
+}
+
+#[get("{experiment_id}/snapshots")]
+async fn list_snapshots(
+    repository: Data<RwLock<Repository>>,
+    path: Path<String>,
+) -> Result<impl Responder> {
+    let experiment_id = Uuid::try_parse(&path.into_inner()).map_err(ApiError::from)?;
+    let snapshots = repository
+        .read()
+        .await
+        .list_experiment_snapshots(experiment_id)
+        .await
+        .map_err(ApiError::from)?;
+
+    Ok(Json(ListSnapshotsResponse { snapshots }))
+}
+
+#[put("{experiment_id}/snapshots/{snapshot_id}/restore")]
+async fn restore_snapshot(
+    repository: Data<RwLock<Repository>>,
+    accountant: Data<Addr<Accountant>>,
+    request: HttpRequest,
+    path: Path<(String, u64)>,
+) -> Result<impl Responder> {
+    let (experiment_id, snapshot_id) = path.into_inner();
+    let experiment_id = Uuid::try_parse(&experiment_id).map_err(ApiError::from)?;
+    let audited = repository
+        .read()
+        .await
+        .restore_experiment_snapshot(experiment_id, snapshot_id)
+        .await
+        .map_err(ApiError::from)?;
+
+    record_audit(
+        &accountant,
+        &request,
+        experiment_id,
+        AuditAction::RestoreSnapshot,
+        json!({"snapshot_id": snapshot_id}),
+        audited.before,
+        audited.after,
+    );
+
+    Ok(HttpResponse::Ok())
+}
+
+#[post("evaluate")]
+async fn evaluate_logs(payload: Json<EvaluatePayload>) -> Result<impl Responder> {
+    let EvaluatePayload {
+        policy,
+        logs,
+        level,
+    } = payload.into_inner();
+    policy.validate().map_err(ApiError::InvalidPayload)?;
+    let evaluation =
+        evaluate(policy, &logs, level.unwrap_or(DEFAULT_LEVEL)).map_err(ApiError::from)?;
+
+    Ok(Json(evaluation))
+}
+
+#[post("{experiment_id}/evaluate")]
+async fn evaluate_experiment(
+    repository: Data<RwLock<Repository>>,
+    path: Path<String>,
+    payload: Json<EvaluateExperimentPayload>,
+) -> Result<impl Responder> {
+    let experiment_id = Uuid::try_parse(&path.into_inner()).map_err(ApiError::from)?;
+    let EvaluateExperimentPayload { policy, level } = payload.into_inner();
+    policy.validate().map_err(ApiError::InvalidPayload)?;
+    let logs = repository
+        .read()
+        .await
+        .get_experiment_interactions(experiment_id)
+        .await
+        .map_err(ApiError::from)?;
+    let evaluation =
+        evaluate(policy, &logs, level.unwrap_or(DEFAULT_LEVEL)).map_err(ApiError::from)?;
+
+    Ok(Json(evaluation))
+}
+
+// posteriors are sampled outside of the experiment, which keeps serving draws in the meantime
+#[get("{experiment_id}/analysis")]
+async fn analysis(
+    repository: Data<RwLock<Repository>>,
+    path: Path<String>,
+) -> Result<impl Responder> {
+    let experiment_id = Uuid::try_parse(&path.into_inner()).map_err(ApiError::from)?;
+    let posteriors = repository
+        .read()
+        .await
+        .get_experiment_posteriors(experiment_id)
+        .await
+        .map_err(ApiError::from)?;
+    let analysis =
+        analyze(&posteriors, ANALYSIS_SAMPLES, &mut rand::rng()).map_err(ApiError::from)?;
+
+    Ok(Json(analysis))
+}
+
+// records are streamed in chunks as they are read on the blocking thread pool, which stops reading
+// once the client disconnects, so that large logs are never held in memory
+#[get("{experiment_id}/interactions")]
+async fn export_interactions(
+    repository: Data<RwLock<Repository>>,
+    path: Path<String>,
+    query: Query<ExportQuery>,
+) -> Result<impl Responder> {
+    let experiment_id = Uuid::try_parse(&path.into_inner()).map_err(ApiError::from)?;
+    let ExportQuery { format, from, to } = query.into_inner();
+    let export = repository
+        .read()
+        .await
+        .export_experiment_interactions(experiment_id, format, from, to)
+        .await
+        .map_err(ApiError::from)?;
+
+    let (sender, receiver) = mpsc::channel(EXPORT_BUFFER_SIZE);
+    rt::task::spawn_blocking(move || {
+        for chunk in export {
+            if sender.blocking_send(chunk.map(Bytes::from)).is_err() {
+                break;
+            }
+        }
+    });
+    let chunks = stream::unfold(receiver, |mut receiver| async move {
+        receiver.recv().await.map(|chunk| (chunk, receiver))
+    });
+
+    Ok(HttpResponse::Ok()
+        .content_type(format.content_type())
+        .streaming(chunks))
+}
+
+#[get("quarantine")]
+async fn list_quarantined(repository: Data<RwLock<Repository>>) -> Result<impl Responder> {
+    let states = repository
+        .read()
+        .await
+        .list_quarantined_states()
+        .await
+        .map_err(ApiError::from)?;
+
+    Ok(Json(ListQuarantinedStatesResponse { states }))
+}
+
+// the trail of deleted experiments remains available
+#[get("{experiment_id}/audit")]
+async fn audit_trail(
+    accountant: Data<Addr<Accountant>>,
+    path: Path<String>,
+    query: Query<AuditQuery>,
+) -> Result<impl Responder> {
+    let experiment_id = Uuid::try_parse(&path.into_inner()).map_err(ApiError::from)?;
+    let AuditQuery { from, to } = query.into_inner();
+    let events = accountant
+        .send(GetAuditTrail {
+            experiment_id,
+            from,
+            to,
+        })
+        .await
+        .map_err(|err| ServiceError::mailbox("Accountant", err))
+        .map_err(ApiError::from)?
+        .map_err(ServiceError::from)
+        .map_err(ApiError::from)?;
+
+    Ok(Json(AuditTrailResponse { events }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actors::state_store::StateStore;
+    use crate::config::{
+        AccountantConfig, ExperimentConfig, LateUpdates, LogSinkType, StateBackendType,
+        StateStoreConfig,
+    };
+    use crate::policies::PolicyType;
+
+    use actix::Actor;
+    use actix_web::{http::StatusCode, test, App};
+    use std::fs;
+    use std::path::PathBuf;
+
+    struct TestContext {
+        repository: Data<RwLock<Repository>>,
+        accountant: Data<Addr<Accountant>>,
+        state_dir: PathBuf,
+    }
+
+    impl TestContext {
+        fn new() -> Self {
+            let state_dir = std::env::temp_dir().join(format!("routes-{}", Uuid::new_v4()));
+            let state_store = StateStore::new(StateStoreConfig {
+                backend: StateBackendType::File,
+                dir: state_dir.clone(),
+                history_size: 5,
+                history_every: 0,
+            })
+            .expect("state store should open")
+            .start();
+            let experiment_config = ExperimentConfig {
+                save_every: 86_400,
+                evaluate_every: 1,
+                ticket_ttl: 3_600,
+                ticket_capacity: 100,
+                event_log_dir: state_dir.join("events"),
+                interaction_record_dir: Some(state_dir.join("interactions")),
+                interaction_record_size: 10_000,
+                late_updates: LateUpdates::Reject,
+            };
+
+            let accountant = Accountant::new(AccountantConfig {
+                sink: LogSinkType::Jsonl,
+                dir: state_dir.join("logs"),
+                batch_size: 100,
+                flush_every: 5,
+                rotation_size: 0,
+                retention: 0,
+            })
+            .expect("accountant should open")
+            .start();
+
+            Self {
+                repository: Data::new(RwLock::new(Repository::new(experiment_config, state_store))),
+                accountant: Data::new(accountant),
+                state_dir,
+            }
+        }
+
+        async fn create_experiment(&self, policy_type: PolicyType) -> Uuid {
+            let mut repository = self.repository.write().await;
+            let experiment_id = repository.create_experiment(None, policy_type.into_inner(), true);
+            repository
+                .add_experiment_arm(experiment_id, None, None)
+                .await
+                .expect("arm creation should succeed");
+            experiment_id
+        }
+    }
+
+    impl Drop for TestContext {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.state_dir);
+        }
+    }
+
+    #[actix_web::test]
+    async fn draw_with_and_without_context() {
+        let ctx = TestContext::new();
+        let experiment_id = ctx
+            .create_experiment(PolicyType::Ucb {
+                alpha: 1.0,
+                seed: None,
+            })
+            .await;
+        let contextual_id = ctx
+            .create_experiment(PolicyType::LinUcb {
+                dim: 2,
+                alpha: 1.0,
+                lambda: 1.0,
+                seed: None,
+            })
+            .await;
+        let app = test::init_service(
+            App::new()
+                .app_data(ctx.repository.clone())
+                .service(draw)
+                .service(draw_with_context),
+        )
+        .await;
+
+        let request = test::TestRequest::get()
+            .uri(&format!("/{experiment_id}/draw"))
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // an empty body is a draw without context, which contextual policies reject
+        for (experiment_id, status) in [
+            (experiment_id, StatusCode::OK),
+            (contextual_id, StatusCode::BAD_REQUEST),
+        ] {
+            let request = test::TestRequest::post()
+                .uri(&format!("/{experiment_id}/draw"))
+                .to_request();
+            let response = test::call_service(&app, request).await;
+            assert_eq!(response.status(), status);
+        }
+
+        let request = test::TestRequest::post()
+            .uri(&format!("/{contextual_id}/draw"))
+            .set_payload("{\"context\": [0.1,")
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body: Value = test::read_body_json(response).await;
+        assert_eq!(body["kind"], "InvalidPayload");
+
+        let request = test::TestRequest::post()
+            .uri(&format!("/{contextual_id}/draw"))
+            .set_json(json!({"context": [0.1, 0.5]}))
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: Value = test::read_body_json(response).await;
+        assert_eq!(body["arm_id"], 0);
+
+        let request = test::TestRequest::post()
+            .uri(&format!("/{contextual_id}/draw"))
+            .set_json(json!({"context": [1e200, 0.5]}))
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[actix_web::test]
+    async fn export_interactions_in_chunks() {
+        let ctx = TestContext::new();
+        let experiment_id = ctx
+            .create_experiment(PolicyType::Ucb {
+                alpha: 1.0,
+                seed: None,
+            })
+            .await;
+        for _ in 0..1200 {
+            ctx.repository
+                .read()
+                .await
+                .draw_experiment(experiment_id, None)
+                .await
+                .expect("draw should succeed");
+        }
+        let app = test::init_service(
+            App::new()
+                .app_data(ctx.repository.clone())
+                .service(export_interactions),
+        )
+        .await;
+
+        let request = test::TestRequest::get()
+            .uri(&format!("/{experiment_id}/interactions?format=csv"))
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = test::read_body(response).await;
+        let body = std::str::from_utf8(&body).expect("export should be text");
+        assert_eq!(body.lines().count(), 1201);
+        assert!(body.starts_with("kind,timestamp"));
+
+        let request = test::TestRequest::get()
+            .uri(&format!("/{}/interactions", Uuid::new_v4()))
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn create_rejects_unknown_fields() {
+        let ctx = TestContext::new();
+        let app = test::init_service(
+            App::new()
+                .app_data(ctx.repository.clone())
+                .app_data(ctx.accountant.clone())
+                .service(create),
+        )
+        .await;
+        let policy = json!({"epsilon": 0.1, "epsilon_decay": null, "seed": null});
+
+        let request = test::TestRequest::post()
+            .uri("/create")
+            .set_json(json!({"EpsilonGreedy": policy, "ends_at": null, "raw_updates": true}))
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        for payload in [
+            json!({"EpsilonGreedy": policy, "bogus": 1}),
+            json!({"EpsilonGreedy": policy, "raw_update": true}),
+        ] {
+            let request = test::TestRequest::post()
+                .uri("/create")
+                .set_json(payload)
+                .to_request();
+            let response = test::call_service(&app, request).await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        }
+        assert_eq!(ctx.repository.read().await.iter_experiments().count(), 1);
+    }
+}
@@ -0,0 +1,377 @@
+use rust_bandits::policies::{get_timestamp, rng::MaybeSeededRng, Policy, PolicyType};
+
+use rand::Rng;
+use rand_distr::{Distribution, Normal};
+use serde::{Deserialize, Serialize};
+use std::{env, fs::File, io::BufReader, process::ExitCode};
+
+// keeps the policy and environment random streams apart when they are derived from the same seed
+const POLICY_SEED_MASK: u64 = 0x9E37_79B9_7F4A_7C15;
+
+#[derive(Debug, Deserialize)]
+struct Segment {
+    steps: usize,
+    probabilities: Vec<f64>,
+}
+
+#[derive(Debug, Deserialize)]
+enum Environment {
+    Bernoulli { probabilities: Vec<f64> },
+    Gaussian { means: Vec<f64>, std_devs: Vec<f64> },
+    // Bernoulli arms whose probabilities change after each segment, the last one lasting forever
+    PiecewiseBernoulli { segments: Vec<Segment> },
+}
+
+impl Environment {
+    fn validate(&self) -> Result<(), String> {
+        let num_arms = self.num_arms();
+        if num_arms == 0 {
+            return Err("environment needs at least one arm".to_string());
+        }
+        match self {
+            Self::Bernoulli { probabilities } => validate_probabilities(probabilities),
+            Self::Gaussian { means, std_devs } => {
+                if std_devs.len() != num_arms {
+                    return Err("every Gaussian arm needs a mean and a std_dev".to_string());
+                }
+                if !means.iter().all(|mean| mean.is_finite()) {
+                    return Err("Gaussian means must be finite".to_string());
+                }
+                if !std_devs.iter().all(|sd| sd.is_finite() && *sd >= 0.0) {
+                    return Err("Gaussian std_devs must be finite and non-negative".to_string());
+                }
+                Ok(())
+            }
+            Self::PiecewiseBernoulli { segments } => segments.iter().try_for_each(|segment| {
+                if segment.probabilities.len() != num_arms {
+                    return Err("every segment needs the same number of arms".to_string());
+                }
+                validate_probabilities(&segment.probabilities)
+            }),
+        }
+    }
+
+    fn num_arms(&self) -> usize {
+        match self {
+            Self::Bernoulli { probabilities } => probabilities.len(),
+            Self::Gaussian { means, .. } => means.len(),
+            Self::PiecewiseBernoulli { segments } => segments
+                .first()
+                .map_or(0, |segment| segment.probabilities.len()),
+        }
+    }
+
+    // expected reward of every arm at a given step
+    fn means(&self, step: usize) -> &[f64] {
+        match self {
+            Self::Bernoulli { probabilities } => probabilities,
+            Self::Gaussian { means, .. } => means,
+            Self::PiecewiseBernoulli { segments } => {
+                let mut end = 0;
+                segments
+                    .iter()
+                    .find(|segment| {
+                        end += segment.steps;
+                        step < end
+                    })
+                    .or(segments.last())
+                    .map(|segment| segment.probabilities.as_slice())
+                    .unwrap_or_default()
+            }
+        }
+    }
+
+    fn sample<R: Rng + ?Sized>(
+        &self,
+        arm_id: usize,
+        step: usize,
+        rng: &mut R,
+    ) -> Result<f64, String> {
+        let mean = self.means(step)[arm_id];
+        match self {
+            Self::Bernoulli { .. } | Self::PiecewiseBernoulli { .. } => {
+                if rng.random::<f64>() < mean {
+                    Ok(1.0)
+                } else {
+                    Ok(0.0)
+                }
+            }
+            Self::Gaussian { std_devs, .. } => Normal::new(mean, std_devs[arm_id])
+                .map(|normal| normal.sample(rng))
+                .map_err(|err| format!("arm {arm_id}: {err}")),
+        }
+    }
+}
+
+// quote fields holding a separator, a quote or a line break, doubling the quotes they contain
+fn escape_csv(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn validate_probabilities(probabilities: &[f64]) -> Result<(), String> {
+    if probabilities.iter().all(|p| (0.0..=1.0).contains(p)) {
+        Ok(())
+    } else {
+        Err("Bernoulli probabilities must be in [0, 1]".to_string())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct PolicySpec {
+    name: String,
+    policy: PolicyType,
+}
+
+fn default_step_seconds() -> f64 {
+    1.0
+}
+
+fn default_report_every() -> usize {
+    1
+}
+
+#[derive(Debug, Deserialize)]
+struct Scenario {
+    environment: Environment,
+    policies: Vec<PolicySpec>,
+    horizon: usize,
+    replications: usize,
+    seed: Option<u64>,
+    // simulated time between two draws, which matters for policies with a halflife
+    #[serde(default = "default_step_seconds")]
+    step_seconds: f64,
+    #[serde(default = "default_report_every")]
+    report_every: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct Record<'a> {
+    policy: &'a str,
+    step: usize,
+    cumulative_regret: f64,
+    best_arm_rate: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum OutputFormat {
+    Csv,
+    Json,
+}
+
+// cumulative regret and best arm selection rate per step, averaged over replications
+fn simulate(scenario: &Scenario, spec: &PolicySpec) -> Result<Vec<(f64, f64)>, String> {
+    spec.policy
+        .validate()
+        .map_err(|err| format!("{}: {err}", spec.name))?;
+    let num_arms = scenario.environment.num_arms();
+    let context = spec.policy.context_dim().map(|dim| vec![1.0; dim]);
+    let mut curves = vec![(0.0, 0.0); scenario.horizon];
+
+    for replication in 0..scenario.replications {
+        let seed = scenario
+            .seed
+            .map(|seed| seed.wrapping_add(replication as u64));
+        let mut env_rng = MaybeSeededRng::new(seed);
+        // policies keep their own seed when the scenario is not seeded
+        let mut policy: Box<dyn Policy + Send> = seed
+            .map_or_else(
+                || spec.policy.clone(),
+                |seed| spec.policy.clone().with_seed(Some(seed ^ POLICY_SEED_MASK)),
+            )
+            .into_inner();
+
+        let start = get_timestamp();
+        (0..num_arms).for_each(|_| {
+            policy.add_arm(0.0, 0);
+        });
+
+        let mut cumulative_regret = 0.0;
+        for (step, curve) in curves.iter_mut().enumerate() {
+            let timestamp = start + step as f64 * scenario.step_seconds;
+
+            let arm_id = policy
+                .draw_at(timestamp, context.as_deref())
+                .map_err(|err| format!("{}: {err}", spec.name))?
+                .arm_id;
+            let reward = scenario
+                .environment
+                .sample(arm_id, step, env_rng.rng_mut())?;
+            policy
+                .update(timestamp, arm_id, reward, context.as_deref())
+                .map_err(|err| format!("{}: {err}", spec.name))?;
+
+            let means = scenario.environment.means(step);
+            let best = means.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+            cumulative_regret += best - means[arm_id];
+            curve.0 += cumulative_regret;
+            if means[arm_id] == best {
+                curve.1 += 1.0;
+            }
+        }
+    }
+
+    let replications = scenario.replications.max(1) as f64;
+    Ok(curves
+        .into_iter()
+        .map(|(regret, best)| (regret / replications, best / replications))
+        .collect())
+}
+
+fn run(path: &str, format: OutputFormat) -> Result<(), String> {
+    let file = File::open(path).map_err(|err| format!("cannot open {path}: {err}"))?;
+    let scenario: Scenario = serde_json::from_reader(BufReader::new(file))
+        .map_err(|err| format!("invalid scenario {path}: {err}"))?;
+    scenario.environment.validate()?;
+
+    let curves = scenario
+        .policies
+        .iter()
+        .map(|spec| simulate(&scenario, spec).map(|curve| (spec.name.as_str(), curve)))
+        .collect::<Result<Vec<_>, String>>()?;
+
+    let records = curves
+        .iter()
+        .flat_map(|(name, curve)| {
+            curve
+                .iter()
+                .enumerate()
+                .filter(|(step, _)| (step + 1) % scenario.report_every.max(1) == 0)
+                .map(|(step, &(cumulative_regret, best_arm_rate))| Record {
+                    policy: name,
+                    step: step + 1,
+                    cumulative_regret,
+                    best_arm_rate,
+                })
+        })
+        .collect::<Vec<Record>>();
+
+    match format {
+        OutputFormat::Csv => {
+            println!("policy,step,cumulative_regret,best_arm_rate");
+            records.iter().for_each(|record| {
+                println!(
+                    "{},{},{},{}",
+                    escape_csv(record.policy),
+                    record.step,
+                    record.cumulative_regret,
+                    record.best_arm_rate
+                )
+            });
+        }
+        OutputFormat::Json => {
+            let output = serde_json::to_string_pretty(&records).map_err(|err| err.to_string())?;
+            println!("{output}");
+        }
+    }
+
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let args = env::args().skip(1).collect::<Vec<String>>();
+    let (path, format) = match args.as_slice() {
+        [path] => (path, OutputFormat::Csv),
+        [path, flag, format] if flag == "--format" => match format.as_str() {
+            "csv" => (path, OutputFormat::Csv),
+            "json" => (path, OutputFormat::Json),
+            _ => {
+                eprintln!("Unknown output format {format}, expected csv or json");
+                return ExitCode::FAILURE;
+            }
+        },
+        _ => {
+            eprintln!("Usage: simulate <scenario.json> [--format csv|json]");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match run(path, format) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("Simulation failed: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_scenario(environment: Environment) -> Scenario {
+        Scenario {
+            environment,
+            policies: Vec::new(),
+            horizon: 200,
+            replications: 2,
+            seed: Some(1234),
+            step_seconds: 1.0,
+            report_every: 1,
+        }
+    }
+
+    #[test]
+    fn piecewise_means() {
+        let environment = Environment::PiecewiseBernoulli {
+            segments: vec![
+                Segment {
+                    steps: 10,
+                    probabilities: vec![0.1, 0.9],
+                },
+                Segment {
+                    steps: 10,
+                    probabilities: vec![0.9, 0.1],
+                },
+            ],
+        };
+        assert!(environment.validate().is_ok());
+        assert_eq!(environment.means(9), &[0.1, 0.9]);
+        assert_eq!(environment.means(10), &[0.9, 0.1]);
+        assert_eq!(environment.means(100), &[0.9, 0.1]);
+    }
+
+    #[test]
+    fn simulate_regret() {
+        let scenario = make_scenario(Environment::Bernoulli {
+            probabilities: vec![0.1, 0.9],
+        });
+        let spec = PolicySpec {
+            name: "ucb".to_string(),
+            policy: PolicyType::Ucb {
+                alpha: 1.0,
+                seed: None,
+            },
+        };
+
+        let curve = simulate(&scenario, &spec).unwrap();
+        assert_eq!(curve.len(), scenario.horizon);
+        assert!(curve.windows(2).all(|w| w[0].0 <= w[1].0));
+        assert!(curve.last().unwrap().1 > 0.5);
+    }
+
+    #[test]
+    fn invalid_std_devs() {
+        let environment = Environment::Gaussian {
+            means: vec![0.0, 1.0],
+            std_devs: vec![1.0, -1.0],
+        };
+        assert!(environment.validate().is_err());
+
+        let environment = Environment::Gaussian {
+            means: vec![0.0, 1.0],
+            std_devs: vec![1.0, f64::NAN],
+        };
+        assert!(environment.validate().is_err());
+    }
+
+    #[test]
+    fn escape_policy_names() {
+        assert_eq!(escape_csv("ucb"), "ucb");
+        assert_eq!(escape_csv("eps, 0.1"), "\"eps, 0.1\"");
+        assert_eq!(escape_csv("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+}
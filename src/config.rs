@@ -2,24 +2,150 @@ use config::{Config, ConfigError, Environment, File};
 use serde::Deserialize;
 use std::path::PathBuf;
 
+fn default_shutdown_deadline() -> u64 {
+    10
+}
+
 #[derive(Debug, Deserialize)]
 pub struct ServerConfig {
     pub log_level: String,
     pub host: String,
     pub port: u16,
+    // seconds given to experiments to store their state on shutdown
+    #[serde(default = "default_shutdown_deadline")]
+    pub shutdown_deadline: u64,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogSinkType {
+    #[default]
+    Jsonl,
+    Sqlite,
+}
+
+fn default_log_dir() -> PathBuf {
+    PathBuf::from("./request_logs")
+}
+
+fn default_batch_size() -> usize {
+    100
+}
+
+fn default_flush_every() -> u64 {
+    5
+}
+
+fn default_rotation_size() -> u64 {
+    10485760
+}
+
+fn default_retention() -> u64 {
+    2592000
 }
 
 #[derive(Debug, Deserialize)]
-pub struct AccountantConfig {}
+pub struct AccountantConfig {
+    #[serde(default)]
+    pub sink: LogSinkType,
+    #[serde(default = "default_log_dir")]
+    pub dir: PathBuf,
+    // records are buffered and written once there are batch_size of them, or every flush_every
+    // seconds
+    #[serde(default = "default_batch_size")]
+    pub batch_size: usize,
+    #[serde(default = "default_flush_every")]
+    pub flush_every: u64,
+    // bytes after which the JSONL file is rotated, 0 disabling rotation
+    #[serde(default = "default_rotation_size")]
+    pub rotation_size: u64,
+    // seconds after which records are deleted, 0 keeping them forever
+    #[serde(default = "default_retention")]
+    pub retention: u64,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum StateBackendType {
+    #[default]
+    File,
+    Sqlite,
+}
+
+fn default_history_size() -> usize {
+    10
+}
+
+fn default_history_every() -> u64 {
+    600
+}
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct StateStoreConfig {
+    #[serde(default)]
+    pub backend: StateBackendType,
     pub dir: PathBuf,
+    // number of past snapshots kept per experiment, 0 disabling historization
+    #[serde(default = "default_history_size")]
+    pub history_size: usize,
+    // minimum seconds between two snapshots of an experiment, which are only taken once it logged
+    // new events
+    #[serde(default = "default_history_every")]
+    pub history_every: u64,
 }
 
-#[derive(Debug, Deserialize)]
+// What becomes of the rewards received once the schedule of an experiment has ended
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LateUpdates {
+    #[default]
+    Reject,
+    // kept in the interaction record, but not learned from
+    Record,
+}
+
+fn default_ticket_ttl() -> u64 {
+    3600
+}
+
+fn default_ticket_capacity() -> usize {
+    100000
+}
+
+fn default_event_log_dir() -> PathBuf {
+    PathBuf::from("./state_store/events")
+}
+
+fn default_interaction_record_size() -> usize {
+    100000
+}
+
+fn default_evaluate_every() -> u64 {
+    60
+}
+
+#[derive(Debug, Deserialize, Clone)]
 pub struct ExperimentConfig {
     pub save_every: u64,
+    // seconds between two evaluations of the stopping rule of an experiment
+    #[serde(default = "default_evaluate_every")]
+    pub evaluate_every: u64,
+    #[serde(default = "default_ticket_ttl")]
+    pub ticket_ttl: u64,
+    // draw tickets kept per experiment, the oldest being evicted beyond it, 0 disabling the limit.
+    // Tickets are only held in memory and are lost when the experiment restarts.
+    #[serde(default = "default_ticket_capacity")]
+    pub ticket_capacity: usize,
+    #[serde(default = "default_event_log_dir")]
+    pub event_log_dir: PathBuf,
+    // draws and rewards are recorded for export and off-policy evaluation only when a directory is
+    // set, each file holding at most interaction_record_size records
+    #[serde(default)]
+    pub interaction_record_dir: Option<PathBuf>,
+    #[serde(default = "default_interaction_record_size")]
+    pub interaction_record_size: usize,
+    #[serde(default)]
+    pub late_updates: LateUpdates,
 }
 
 #[derive(Debug, Deserialize)]
@@ -40,3 +166,44 @@ impl AppConfig {
         builder.try_deserialize()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::FileFormat;
+
+    // a configuration written before any of the optional settings existed still loads
+    #[test]
+    fn load_minimal_config() {
+        let config = Config::builder()
+            .add_source(File::from_str(
+                r#"
+                [server]
+                log_level = "info"
+                host = "127.0.0.1"
+                port = "8080"
+
+                [accountant]
+
+                [state_store]
+                dir = "./state_store"
+
+                [experiment]
+                save_every = "60"
+                "#,
+                FileFormat::Toml,
+            ))
+            .build()
+            .and_then(|config| config.try_deserialize::<AppConfig>())
+            .expect("minimal configuration should load");
+
+        assert_eq!(config.server.shutdown_deadline, 10);
+        assert_eq!(config.accountant.dir, PathBuf::from("./request_logs"));
+        assert_eq!(config.accountant.batch_size, 100);
+        assert_eq!(config.experiment.ticket_ttl, 3600);
+        assert_eq!(
+            config.experiment.event_log_dir,
+            PathBuf::from("./state_store/events")
+        );
+    }
+}
@@ -1,5 +1,13 @@
+use crate::metrics::METRICS;
+use crate::policies::RewardDomain;
+use crate::storage::ExperimentStatus;
+
 use actix::MailboxError;
-use actix_web::{error::ResponseError, http::StatusCode, HttpResponse};
+use actix_web::{
+    error::{BlockingError, ResponseError},
+    http::StatusCode,
+    HttpResponse,
+};
 use serde::Serialize;
 use thiserror::Error;
 use uuid::Uuid;
@@ -14,6 +22,16 @@ pub enum PolicyError {
     InactiveArm(usize),
     #[error("Sampling error: {0}")]
     SamplingError(String),
+    #[error("Policy requires a context vector")]
+    MissingContext,
+    #[error("Context has dimension {got}, expected {expected}")]
+    InvalidContextDimension { expected: usize, got: usize },
+    #[error("Invalid context value {0}, expected a finite value")]
+    InvalidContextValue(f64),
+    #[error("Invalid reward {reward}, expected {domain}")]
+    InvalidReward { reward: f64, domain: RewardDomain },
+    #[error("Numerical error: {0}")]
+    NumericalError(String),
 }
 
 #[derive(Debug, Error)]
@@ -22,12 +40,54 @@ pub enum ExperimentError {
     NoPolicy,
     #[error("Policy error: {0}")]
     PolicyError(#[from] PolicyError),
+    #[error("Unknown draw ticket {0}")]
+    UnknownTicket(Uuid),
+    #[error("Draw ticket {0} has expired")]
+    ExpiredTicket(Uuid),
+    #[error("Draw ticket {0} has already been redeemed")]
+    DuplicateTicket(Uuid),
+    #[error("Experiment only accepts rewards through draw tickets")]
+    RawUpdatesDisabled,
+    #[error("Snapshot {0} not found")]
+    SnapshotNotFound(u64),
+    #[error("Experiment is {status}, it cannot {action}")]
+    InvalidStatus {
+        status: ExperimentStatus,
+        action: &'static str,
+    },
+    #[error("Experiment cannot go from {from} to {to}")]
+    InvalidTransition {
+        from: ExperimentStatus,
+        to: ExperimentStatus,
+    },
+    #[error("Arm {0} is served regardless of the policy in the current status")]
+    ServedArm(usize),
+    #[error("Evaluation error: {0}")]
+    Evaluation(#[from] EvaluationError),
+    #[error("Persistence error: {0}")]
+    Persistence(#[from] PersistenceError),
+    #[error("State store unavailable: {0}")]
+    StateStoreUnavailable(#[from] MailboxError),
+}
+
+#[derive(Debug, Error)]
+pub enum EvaluationError {
+    #[error("No logged interactions to evaluate")]
+    EmptyLog,
+    #[error("Logged interaction {index} has propensity {propensity}, expected a value in (0, 1]")]
+    InvalidPropensity { index: usize, propensity: f64 },
+    #[error("Invalid confidence level {0}, expected a value in (0, 1)")]
+    InvalidLevel(f64),
+    #[error("Policy error: {0}")]
+    PolicyError(#[from] PolicyError),
 }
 
 #[derive(Debug, Error)]
 pub enum RepositoryError {
     #[error("Experiment {0} not found")]
     ExperimentNotFound(Uuid),
+    #[error("Interactions are not recorded")]
+    InteractionsNotRecorded,
     #[error("Experiment error: {0}")]
     Experiment(#[from] ExperimentError),
 }
@@ -38,6 +98,25 @@ pub enum PersistenceError {
     Io(#[from] std::io::Error),
     #[error("Failed to serialize state store: {0}")]
     Serialization(#[from] serde_json::Error),
+    #[error("Database error in state store: {0}")]
+    Database(#[from] rusqlite::Error),
+    #[error("State checksum mismatch, expected {expected:08x} but got {actual:08x}")]
+    ChecksumMismatch { expected: u32, actual: u32 },
+    #[error("Unreadable event log entry on line {line}: {source}")]
+    CorruptEvent {
+        line: usize,
+        source: serde_json::Error,
+    },
+}
+
+impl PersistenceError {
+    // the stored state is unreadable and retrying will not help
+    pub fn is_corruption(&self) -> bool {
+        matches!(
+            self,
+            Self::Serialization(_) | Self::ChecksumMismatch { .. } | Self::CorruptEvent { .. }
+        )
+    }
 }
 
 #[derive(Debug, Error)]
@@ -54,14 +133,30 @@ pub enum ServiceError {
     Persistence(#[from] PersistenceError),
     #[error("No accountant defined")]
     Accountant,
+    #[error("Metrics error: {0}")]
+    Metrics(#[from] prometheus::Error),
+    #[error("Blocking task failed: {0}")]
+    Blocking(#[from] BlockingError),
+}
+
+impl ServiceError {
+    // undelivered messages are counted, as they reveal overloaded or crashed actors
+    pub fn mailbox(actor: &'static str, source: MailboxError) -> Self {
+        METRICS.mailbox_error(actor);
+        Self::Mailbox { actor, source }
+    }
 }
 
 #[derive(Debug, Error)]
 pub enum ApiError {
     #[error("Invalid UUID: {0}")]
     InvalidUuid(#[from] uuid::Error),
+    #[error("Invalid payload: {0}")]
+    InvalidPayload(&'static str),
     #[error("Service error: {0}")]
     Service(#[from] ServiceError),
+    #[error("Evaluation error: {0}")]
+    Evaluation(#[from] EvaluationError),
 }
 
 #[derive(Debug, Serialize)]
@@ -75,12 +170,16 @@ impl ApiError {
     fn kind(&self) -> &'static str {
         match self {
             Self::InvalidUuid(_) => "InvalidUuid",
+            Self::InvalidPayload(_) => "InvalidPayload",
             Self::Service(err) => match err {
                 ServiceError::Mailbox { .. } => "MailboxError",
                 ServiceError::Repository(_) => "RepositoryError",
                 ServiceError::Persistence(_) => "PersistenceError",
                 ServiceError::Accountant => "AccountantError",
+                ServiceError::Metrics(_) => "MetricsError",
+                ServiceError::Blocking(_) => "BlockingError",
             },
+            Self::Evaluation(_) => "EvaluationError",
         }
     }
 }
@@ -88,17 +187,42 @@ impl ApiError {
 impl ResponseError for ApiError {
     fn status_code(&self) -> StatusCode {
         match self {
-            Self::InvalidUuid(_) => StatusCode::BAD_REQUEST,
+            Self::InvalidUuid(_) | Self::InvalidPayload(_) => StatusCode::BAD_REQUEST,
             Self::Service(service_err) => match service_err {
-                ServiceError::Mailbox { .. } | ServiceError::Accountant => {
-                    StatusCode::SERVICE_UNAVAILABLE
-                }
+                ServiceError::Mailbox { .. }
+                | ServiceError::Accountant
+                | ServiceError::Blocking(_) => StatusCode::SERVICE_UNAVAILABLE,
                 ServiceError::Repository(repo_err) => match repo_err {
-                    RepositoryError::ExperimentNotFound(_) => StatusCode::NOT_FOUND,
+                    RepositoryError::ExperimentNotFound(_)
+                    | RepositoryError::InteractionsNotRecorded
+                    | RepositoryError::Experiment(ExperimentError::SnapshotNotFound(_)) => {
+                        StatusCode::NOT_FOUND
+                    }
+                    RepositoryError::Experiment(ExperimentError::PolicyError(
+                        PolicyError::InvalidReward { .. } | PolicyError::InvalidContextValue(_),
+                    )) => StatusCode::UNPROCESSABLE_ENTITY,
+                    RepositoryError::Experiment(ExperimentError::ExpiredTicket(_)) => {
+                        StatusCode::GONE
+                    }
+                    RepositoryError::Experiment(
+                        ExperimentError::DuplicateTicket(_)
+                        | ExperimentError::InvalidStatus { .. }
+                        | ExperimentError::InvalidTransition { .. }
+                        | ExperimentError::ServedArm(_),
+                    ) => StatusCode::CONFLICT,
+                    RepositoryError::Experiment(ExperimentError::Persistence(_)) => {
+                        StatusCode::INTERNAL_SERVER_ERROR
+                    }
+                    RepositoryError::Experiment(ExperimentError::StateStoreUnavailable(_)) => {
+                        StatusCode::SERVICE_UNAVAILABLE
+                    }
                     RepositoryError::Experiment(_) => StatusCode::BAD_REQUEST,
                 },
-                ServiceError::Persistence(_) => StatusCode::INTERNAL_SERVER_ERROR,
+                ServiceError::Persistence(_) | ServiceError::Metrics(_) => {
+                    StatusCode::INTERNAL_SERVER_ERROR
+                }
             },
+            Self::Evaluation(_) => StatusCode::UNPROCESSABLE_ENTITY,
         }
     }
 
@@ -0,0 +1,140 @@
+use crate::errors::{EvaluationError, PolicyError};
+use crate::policies::ArmPosterior;
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+// number of joint draws from the arm posteriors used by the analysis
+pub const ANALYSIS_SAMPLES: usize = 10_000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArmAnalysis {
+    pub probability_best: f64,
+    pub expected_loss: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExperimentAnalysis {
+    pub samples: usize,
+    pub arms: HashMap<usize, ArmAnalysis>,
+}
+
+// Draw the mean reward of every arm from its posterior at once, many times over. The probability
+// of an arm being the best is the share of draws where it has the highest mean, and its expected
+// loss is the average shortfall of its mean against the highest one, i.e. the reward given up by
+// settling on it.
+pub fn analyze<R: Rng + ?Sized>(
+    posteriors: &HashMap<usize, ArmPosterior>,
+    samples: usize,
+    rng: &mut R,
+) -> Result<ExperimentAnalysis, EvaluationError> {
+    if posteriors.is_empty() {
+        return Err(PolicyError::NoArmsAvailable.into());
+    }
+    let samplers = posteriors
+        .iter()
+        .map(|(&arm_id, posterior)| posterior.sampler().map(|sampler| (arm_id, sampler)))
+        .collect::<Result<Vec<_>, PolicyError>>()?;
+
+    let mut wins = vec![0usize; samplers.len()];
+    let mut losses = vec![0.0; samplers.len()];
+    let mut draws = vec![0.0; samplers.len()];
+    for _ in 0..samples {
+        for (draw, (_, sampler)) in draws.iter_mut().zip(&samplers) {
+            *draw = sampler.sample(rng);
+        }
+        let (best, best_draw) = draws.iter().copied().enumerate().fold(
+            (0, f64::NEG_INFINITY),
+            |(best, best_draw), (index, draw)| {
+                if draw > best_draw {
+                    (index, draw)
+                } else {
+                    (best, best_draw)
+                }
+            },
+        );
+        wins[best] += 1;
+        for (loss, draw) in losses.iter_mut().zip(&draws) {
+            *loss += best_draw - draw;
+        }
+    }
+
+    let n = samples.max(1) as f64;
+    Ok(ExperimentAnalysis {
+        samples,
+        arms: samplers
+            .iter()
+            .enumerate()
+            .map(|(index, (arm_id, _))| {
+                (
+                    *arm_id,
+                    ArmAnalysis {
+                        probability_best: wins[index] as f64 / n,
+                        expected_loss: losses[index] / n,
+                    },
+                )
+            })
+            .collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    #[test]
+    fn analyze_clear_winner() {
+        let posteriors = HashMap::from([
+            (
+                0,
+                ArmPosterior::Beta {
+                    alpha: 11.0,
+                    beta: 91.0,
+                },
+            ),
+            (
+                1,
+                ArmPosterior::Beta {
+                    alpha: 31.0,
+                    beta: 71.0,
+                },
+            ),
+        ]);
+        let mut rng = ChaCha8Rng::seed_from_u64(1234);
+        let analysis = analyze(&posteriors, ANALYSIS_SAMPLES, &mut rng).unwrap();
+
+        let (loser, winner) = (&analysis.arms[&0], &analysis.arms[&1]);
+        assert!(winner.probability_best > 0.99);
+        assert!((loser.probability_best + winner.probability_best - 1.0).abs() < 1e-9);
+        assert!(winner.expected_loss < 1e-3);
+        // the loser gives up about the difference between the means
+        assert!((loser.expected_loss - 0.2).abs() < 0.02);
+    }
+
+    #[test]
+    fn analyze_identical_arms() {
+        let posterior = ArmPosterior::Normal {
+            mean: 5.0,
+            std_dev: 1.0,
+        };
+        let posteriors = HashMap::from([(0, posterior), (1, posterior), (2, posterior)]);
+        let mut rng = ChaCha8Rng::seed_from_u64(1234);
+        let analysis = analyze(&posteriors, ANALYSIS_SAMPLES, &mut rng).unwrap();
+
+        for arm in analysis.arms.values() {
+            assert!((arm.probability_best - 1.0 / 3.0).abs() < 0.03);
+        }
+    }
+
+    #[test]
+    fn analyze_without_arms() {
+        let mut rng = ChaCha8Rng::seed_from_u64(1234);
+        assert!(matches!(
+            analyze(&HashMap::new(), ANALYSIS_SAMPLES, &mut rng),
+            Err(EvaluationError::PolicyError(PolicyError::NoArmsAvailable))
+        ));
+    }
+}
@@ -0,0 +1,9 @@
+mod analysis;
+mod off_policy;
+mod pruning;
+mod stopping;
+
+pub use analysis::{analyze, ExperimentAnalysis, ANALYSIS_SAMPLES};
+pub use off_policy::{evaluate, LoggedInteraction};
+pub use pruning::{PrunedArm, PruningRule};
+pub use stopping::{leader, Conclusion, StoppingReason, StoppingRule};
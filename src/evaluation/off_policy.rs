@@ -0,0 +1,297 @@
+use crate::errors::EvaluationError;
+use crate::policies::{normal_quantile, PolicyType};
+use crate::storage::{InteractionKind, InteractionRecord};
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+// A reward observed for an arm served by a logging policy, along with the probability it had to be served
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoggedInteraction {
+    pub timestamp: f64,
+    pub arm_id: usize,
+    pub propensity: f64,
+    pub reward: f64,
+    pub context: Option<Vec<f64>>,
+}
+
+impl LoggedInteraction {
+    // Join the rewards recorded by an experiment to the draws sharing their ticket. Draws whose
+    // propensity is unknown, or that were never rewarded, cannot be used for off-policy evaluation.
+    pub fn from_records<I>(records: I) -> Vec<Self>
+    where
+        I: IntoIterator<Item = InteractionRecord>,
+    {
+        let mut draws = HashMap::<Uuid, InteractionRecord>::new();
+        let mut interactions = Vec::new();
+        for record in records {
+            match (record.kind, record.ticket) {
+                (InteractionKind::Draw, Some(ticket)) if record.propensity.is_some() => {
+                    draws.insert(ticket, record);
+                }
+                (InteractionKind::Update, Some(ticket)) => {
+                    let Some(draw) = draws.remove(&ticket) else {
+                        continue;
+                    };
+                    if let (Some(propensity), Some(reward)) = (draw.propensity, record.reward) {
+                        interactions.push(Self {
+                            timestamp: record.timestamp,
+                            arm_id: draw.arm_id,
+                            propensity,
+                            reward,
+                            context: draw.context,
+                        });
+                    }
+                }
+                _ => (),
+            }
+        }
+        interactions
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Estimate {
+    pub value: f64,
+    pub std_error: f64,
+    pub lower: f64,
+    pub upper: f64,
+}
+
+impl Estimate {
+    // normal approximation of the sampling distribution of the estimator
+    fn new(value: f64, std_error: f64, z: f64) -> Self {
+        Self {
+            value,
+            std_error,
+            lower: value - z * std_error,
+            upper: value + z * std_error,
+        }
+    }
+
+    fn from_samples(samples: &[f64], z: f64) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+
+        let n = samples.len() as f64;
+        let mean = samples.iter().sum::<f64>() / n;
+        let std_error = if samples.len() > 1 {
+            let variance = samples.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1.0);
+            (variance / n).sqrt()
+        } else {
+            0.0
+        };
+
+        Some(Self::new(mean, std_error, z))
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct OffPolicyEvaluation {
+    pub level: f64,
+    pub events: usize,
+    pub matches: usize,
+    pub ips: Estimate,
+    pub snips: Option<Estimate>,
+    pub replay: Option<Estimate>,
+}
+
+// Replay the logs through a fresh instance of the candidate policy, which only learns from the events
+// where it would have served the same arm as the logging policy. The importance weight of such an
+// event is 1 / propensity and 0 otherwise, which keeps the IPS estimate unbiased for randomized
+// candidates without having to know their full action distribution. Logged propensities are taken as
+// exact, the bias of those estimated by Monte Carlo carrying over to the estimates.
+pub fn evaluate(
+    policy_type: PolicyType,
+    logs: &[LoggedInteraction],
+    level: f64,
+) -> Result<OffPolicyEvaluation, EvaluationError> {
+    if !(level > 0.0 && level < 1.0) {
+        return Err(EvaluationError::InvalidLevel(level));
+    }
+    if logs.is_empty() {
+        return Err(EvaluationError::EmptyLog);
+    }
+    if let Some((index, interaction)) = logs
+        .iter()
+        .enumerate()
+        .find(|(_, interaction)| !(interaction.propensity > 0.0 && interaction.propensity <= 1.0))
+    {
+        return Err(EvaluationError::InvalidPropensity {
+            index,
+            propensity: interaction.propensity,
+        });
+    }
+
+    // the candidate gets its own arms, mapped to the ones found in the logs
+    let mut policy = policy_type.into_inner();
+    let mut logged_arm_ids = logs
+        .iter()
+        .map(|interaction| interaction.arm_id)
+        .collect::<Vec<usize>>();
+    logged_arm_ids.sort_unstable();
+    logged_arm_ids.dedup();
+    let arm_ids = logged_arm_ids
+        .into_iter()
+        .map(|logged_arm_id| (policy.add_arm(0.0, 0), logged_arm_id))
+        .collect::<HashMap<usize, usize>>();
+
+    let mut logs = logs.iter().collect::<Vec<&LoggedInteraction>>();
+    logs.sort_by(|a, b| a.timestamp.total_cmp(&b.timestamp));
+
+    let mut weighted_rewards = Vec::with_capacity(logs.len());
+    let mut weights = Vec::with_capacity(logs.len());
+    let mut matched_rewards = Vec::new();
+    for interaction in logs {
+        // the candidate draws as of the logged interaction, so that its halflife plays out as logged
+        let draw = policy.draw_at(interaction.timestamp, interaction.context.as_deref())?;
+        let weight = if arm_ids[&draw.arm_id] == interaction.arm_id {
+            policy.update(
+                interaction.timestamp,
+                draw.arm_id,
+                interaction.reward,
+                interaction.context.as_deref(),
+            )?;
+            matched_rewards.push(interaction.reward);
+            1.0 / interaction.propensity
+        } else {
+            0.0
+        };
+        weighted_rewards.push(weight * interaction.reward);
+        weights.push(weight);
+    }
+
+    let z = normal_quantile(0.5 + level / 2.0);
+    let ips = Estimate::from_samples(&weighted_rewards, z).ok_or(EvaluationError::EmptyLog)?;
+
+    // self-normalized IPS, with its delta method standard error
+    let total_weight = weights.iter().sum::<f64>();
+    let snips = (total_weight > 0.0).then(|| {
+        let value = weighted_rewards.iter().sum::<f64>() / total_weight;
+        let std_error = weights
+            .iter()
+            .zip(&weighted_rewards)
+            .map(|(w, wr)| (wr - w * value).powi(2))
+            .sum::<f64>()
+            .sqrt()
+            / total_weight;
+        Estimate::new(value, std_error, z)
+    });
+
+    Ok(OffPolicyEvaluation {
+        level,
+        events: weights.len(),
+        matches: matched_rewards.len(),
+        ips,
+        snips,
+        replay: Estimate::from_samples(&matched_rewards, z),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LEVEL: f64 = 0.95;
+    const DEFAULT_SEED: Option<u64> = Some(1234);
+
+    fn make_policy_type() -> PolicyType {
+        PolicyType::EpsilonGreedy {
+            epsilon: 0.0,
+            epsilon_decay: None,
+            seed: DEFAULT_SEED,
+        }
+    }
+
+    // uniformly random logging policy over two arms, the first one always paying off
+    fn make_logs(size: usize) -> Vec<LoggedInteraction> {
+        (0..size)
+            .map(|i| LoggedInteraction {
+                timestamp: i as f64,
+                arm_id: i % 2,
+                propensity: 0.5,
+                reward: if i % 2 == 0 { 1.0 } else { 0.0 },
+                context: None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn evaluate_greedy_candidate() {
+        let evaluation = evaluate(make_policy_type(), &make_logs(400), LEVEL).unwrap();
+        assert_eq!(evaluation.events, 400);
+        assert!(evaluation.matches > 0);
+
+        let replay = evaluation.replay.unwrap();
+        assert!(replay.value > 0.9);
+        let snips = evaluation.snips.unwrap();
+        assert!(snips.value > 0.9);
+        assert!(snips.lower <= snips.value && snips.value <= snips.upper);
+        let ips = evaluation.ips;
+        assert!((ips.value - 1.0).abs() < 0.1);
+        assert!(ips.lower <= ips.value && ips.value <= ips.upper);
+    }
+
+    #[test]
+    fn evaluate_invalid_logs() {
+        assert!(matches!(
+            evaluate(make_policy_type(), &[], LEVEL),
+            Err(EvaluationError::EmptyLog)
+        ));
+
+        let mut logs = make_logs(4);
+        logs[2].propensity = 0.0;
+        assert!(matches!(
+            evaluate(make_policy_type(), &logs, LEVEL),
+            Err(EvaluationError::InvalidPropensity { index: 2, .. })
+        ));
+
+        assert!(matches!(
+            evaluate(make_policy_type(), &make_logs(4), 1.0),
+            Err(EvaluationError::InvalidLevel(_))
+        ));
+    }
+
+    #[test]
+    fn join_recorded_interactions() {
+        let ticket = Uuid::new_v4();
+        let draw = |ticket: Option<Uuid>, propensity: Option<f64>| InteractionRecord {
+            kind: InteractionKind::Draw,
+            timestamp: 1.0,
+            arm_id: 1,
+            ticket,
+            propensity,
+            reward: None,
+            context: Some(vec![0.5]),
+        };
+        let update = |ticket: Option<Uuid>| InteractionRecord {
+            kind: InteractionKind::Update,
+            timestamp: 2.0,
+            arm_id: 1,
+            ticket,
+            propensity: None,
+            reward: Some(1.0),
+            context: None,
+        };
+        let unknown_propensity = Uuid::new_v4();
+        let records = vec![
+            draw(Some(ticket), Some(0.25)),
+            draw(Some(unknown_propensity), None),
+            draw(Some(Uuid::new_v4()), Some(0.5)),
+            update(Some(unknown_propensity)),
+            update(None),
+            update(Some(ticket)),
+            update(Some(ticket)),
+        ];
+
+        let interactions = LoggedInteraction::from_records(records);
+        assert_eq!(interactions.len(), 1);
+        assert_eq!(interactions[0].timestamp, 2.0);
+        assert_eq!(interactions[0].arm_id, 1);
+        assert_eq!(interactions[0].propensity, 0.25);
+        assert_eq!(interactions[0].reward, 1.0);
+        assert_eq!(interactions[0].context, Some(vec![0.5]));
+    }
+}
@@ -0,0 +1,132 @@
+use crate::policies::{PolicyStats, DEFAULT_LEVEL};
+
+use serde::{Deserialize, Serialize};
+
+fn default_level() -> f64 {
+    DEFAULT_LEVEL
+}
+
+// Successive elimination: an arm is dominated once the upper bound of its interval falls below the
+// lower bound of another arm, both having been pulled at least min_pulls times
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PruningRule {
+    pub min_pulls: u64,
+    #[serde(default = "default_level")]
+    pub level: f64,
+}
+
+// Why an arm was disabled by the pruning rule, at the time it was
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PrunedArm {
+    pub pruned_at: f64,
+    pub level: f64,
+    pub upper: f64,
+    pub dominated_by: usize,
+    pub dominating_lower: f64,
+}
+
+impl PruningRule {
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if !(self.level > 0.0 && self.level < 1.0) {
+            return Err("level must be in (0, 1)");
+        }
+        Ok(())
+    }
+
+    // arms are only compared on the intervals of the given level, so that policies which do not
+    // report one (contextual ones) never have any arm pruned
+    pub fn dominated_arms(&self, policy_stats: &PolicyStats, now: f64) -> Vec<(usize, PrunedArm)> {
+        let mut eligible = policy_stats
+            .arms
+            .iter()
+            .filter(|(_, arm)| arm.is_active && arm.pulls >= self.min_pulls)
+            .filter_map(|(&arm_id, arm)| arm.interval.map(|interval| (arm_id, interval)))
+            .collect::<Vec<_>>();
+        eligible.sort_unstable_by_key(|(arm_id, _)| *arm_id);
+
+        let Some(&(best, best_interval)) = eligible
+            .iter()
+            .max_by(|(_, a), (_, b)| a.lower.total_cmp(&b.lower))
+        else {
+            return Vec::new();
+        };
+        eligible
+            .into_iter()
+            .filter(|(arm_id, interval)| *arm_id != best && interval.upper < best_interval.lower)
+            .map(|(arm_id, interval)| {
+                (
+                    arm_id,
+                    PrunedArm {
+                        pruned_at: now,
+                        level: self.level,
+                        upper: interval.upper,
+                        dominated_by: best,
+                        dominating_lower: best_interval.lower,
+                    },
+                )
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::policies::{ArmStats, Interval};
+    use std::collections::HashMap;
+
+    fn arm(pulls: u64, lower: f64, upper: f64) -> ArmStats {
+        ArmStats {
+            pulls,
+            mean_reward: (lower + upper) / 2.0,
+            is_active: true,
+            is_pruned: false,
+            interval: Some(Interval { lower, upper }),
+        }
+    }
+
+    #[test]
+    fn prune_dominated_arms() {
+        let policy_stats = PolicyStats {
+            level: DEFAULT_LEVEL,
+            arms: HashMap::from([
+                (0, arm(100, 0.05, 0.15)),
+                (1, arm(100, 0.4, 0.6)),
+                (2, arm(100, 0.3, 0.5)),
+                // dominated, but not pulled enough to be judged
+                (3, arm(5, 0.0, 0.2)),
+            ]),
+        };
+        let rule = PruningRule {
+            min_pulls: 50,
+            level: DEFAULT_LEVEL,
+        };
+
+        let dominated = rule.dominated_arms(&policy_stats, 1000.0);
+        assert_eq!(dominated.len(), 1);
+        let (arm_id, pruned_arm) = &dominated[0];
+        assert_eq!(*arm_id, 0);
+        assert_eq!(pruned_arm.dominated_by, 1);
+        assert_eq!(pruned_arm.upper, 0.15);
+        assert_eq!(pruned_arm.dominating_lower, 0.4);
+
+        let rule = PruningRule {
+            min_pulls: 200,
+            level: DEFAULT_LEVEL,
+        };
+        assert!(rule.dominated_arms(&policy_stats, 1000.0).is_empty());
+    }
+
+    #[test]
+    fn validate_level() {
+        let rule = PruningRule {
+            min_pulls: 10,
+            level: 1.0,
+        };
+        assert!(rule.validate().is_err());
+        assert!(serde_json::from_str::<PruningRule>(r#"{"min_pulls": 10}"#)
+            .unwrap()
+            .validate()
+            .is_ok());
+    }
+}
@@ -0,0 +1,259 @@
+use super::analysis::{ArmAnalysis, ExperimentAnalysis};
+
+use serde::{Deserialize, Serialize};
+
+// pulls below which posteriors are too uncertain to conclude on
+const DEFAULT_MIN_PULLS: u64 = 30;
+
+fn default_min_pulls() -> u64 {
+    DEFAULT_MIN_PULLS
+}
+
+// Conditions under which an experiment is concluded, the first one met ending it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StoppingRule {
+    // probability of the leading arm being the best
+    pub probability_best: Option<f64>,
+    // expected loss of the leading arm, under which settling on it costs too little to go on
+    pub expected_loss: Option<f64>,
+    // seconds since the experiment started
+    pub max_duration: Option<u64>,
+    // rewards received over all arms
+    pub max_samples: Option<u64>,
+    // pulls every active arm needs before the probability_best and expected_loss thresholds are
+    // checked, as for the pruning rule
+    #[serde(default = "default_min_pulls")]
+    pub min_pulls: u64,
+}
+
+impl Default for StoppingRule {
+    fn default() -> Self {
+        Self {
+            probability_best: None,
+            expected_loss: None,
+            max_duration: None,
+            max_samples: None,
+            min_pulls: DEFAULT_MIN_PULLS,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StoppingReason {
+    ProbabilityBest,
+    ExpectedLoss,
+    MaxDuration,
+    MaxSamples,
+    // concluded through the API rather than by the rule
+    Manual,
+}
+
+// Decision taken when a stopping rule fired, along with the evidence it was taken on
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Conclusion {
+    pub winner: usize,
+    pub reason: StoppingReason,
+    pub concluded_at: f64,
+    pub elapsed: f64,
+    pub samples: u64,
+    pub analysis: ExperimentAnalysis,
+}
+
+// the arm most likely to be the best, the one with the lowest expected loss and then the lowest id
+// breaking ties
+pub fn leader(analysis: &ExperimentAnalysis) -> Option<(usize, &ArmAnalysis)> {
+    analysis
+        .arms
+        .iter()
+        .max_by(|(a_id, a), (b_id, b)| {
+            a.probability_best
+                .total_cmp(&b.probability_best)
+                .then_with(|| b.expected_loss.total_cmp(&a.expected_loss))
+                .then_with(|| b_id.cmp(a_id))
+        })
+        .map(|(&arm_id, arm)| (arm_id, arm))
+}
+
+impl StoppingRule {
+    // a rule without any condition never fires, whatever its min_pulls
+    pub fn is_empty(&self) -> bool {
+        self.probability_best.is_none()
+            && self.expected_loss.is_none()
+            && self.max_duration.is_none()
+            && self.max_samples.is_none()
+    }
+
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if self
+            .probability_best
+            .is_some_and(|threshold| !(threshold > 0.0 && threshold <= 1.0))
+        {
+            return Err("probability_best must be in (0, 1]");
+        }
+        if self
+            .expected_loss
+            .is_some_and(|threshold| !(threshold >= 0.0 && threshold.is_finite()))
+        {
+            return Err("expected_loss must be a non-negative number");
+        }
+        Ok(())
+    }
+
+    // the leader is declared the winner whichever condition is met, the analysis only being
+    // trusted once the least pulled active arm reached min_pulls
+    pub fn check(
+        &self,
+        analysis: ExperimentAnalysis,
+        min_arm_pulls: u64,
+        elapsed: f64,
+        samples: u64,
+        now: f64,
+    ) -> Option<Conclusion> {
+        let (winner, leader) = leader(&analysis)?;
+        let is_trusted = min_arm_pulls >= self.min_pulls;
+
+        let reason = if is_trusted
+            && self
+                .probability_best
+                .is_some_and(|threshold| leader.probability_best >= threshold)
+        {
+            StoppingReason::ProbabilityBest
+        } else if is_trusted
+            && self
+                .expected_loss
+                .is_some_and(|threshold| leader.expected_loss <= threshold)
+        {
+            StoppingReason::ExpectedLoss
+        } else if self
+            .max_duration
+            .is_some_and(|max_duration| elapsed >= max_duration as f64)
+        {
+            StoppingReason::MaxDuration
+        } else if self
+            .max_samples
+            .is_some_and(|max_samples| samples >= max_samples)
+        {
+            StoppingReason::MaxSamples
+        } else {
+            return None;
+        };
+
+        Some(Conclusion {
+            winner,
+            reason,
+            concluded_at: now,
+            elapsed,
+            samples,
+            analysis,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn analysis() -> ExperimentAnalysis {
+        ExperimentAnalysis {
+            samples: 100,
+            arms: HashMap::from([
+                (
+                    0,
+                    ArmAnalysis {
+                        probability_best: 0.1,
+                        expected_loss: 0.2,
+                    },
+                ),
+                (
+                    1,
+                    ArmAnalysis {
+                        probability_best: 0.9,
+                        expected_loss: 0.01,
+                    },
+                ),
+            ]),
+        }
+    }
+
+    #[test]
+    fn check_thresholds() {
+        let rule = StoppingRule {
+            probability_best: Some(0.95),
+            expected_loss: Some(0.05),
+            ..Default::default()
+        };
+        let conclusion = rule.check(analysis(), 100, 10.0, 50, 1000.0).unwrap();
+        assert_eq!(conclusion.winner, 1);
+        assert_eq!(conclusion.reason, StoppingReason::ExpectedLoss);
+        assert_eq!(conclusion.samples, 50);
+
+        let rule = StoppingRule {
+            probability_best: Some(0.95),
+            expected_loss: Some(0.001),
+            ..Default::default()
+        };
+        assert!(rule.check(analysis(), 100, 10.0, 50, 1000.0).is_none());
+
+        // thresholds are not checked until every arm was pulled enough
+        let rule = StoppingRule {
+            probability_best: Some(0.5),
+            expected_loss: Some(0.05),
+            max_samples: Some(100),
+            min_pulls: 20,
+            ..Default::default()
+        };
+        assert!(rule.check(analysis(), 19, 10.0, 50, 1000.0).is_none());
+        let conclusion = rule.check(analysis(), 19, 10.0, 100, 1000.0).unwrap();
+        assert_eq!(conclusion.reason, StoppingReason::MaxSamples);
+        let conclusion = rule.check(analysis(), 20, 10.0, 50, 1000.0).unwrap();
+        assert_eq!(conclusion.reason, StoppingReason::ProbabilityBest);
+        assert_eq!(
+            serde_json::from_str::<StoppingRule>(r#"{"probability_best": 0.95}"#)
+                .unwrap()
+                .min_pulls,
+            DEFAULT_MIN_PULLS
+        );
+    }
+
+    #[test]
+    fn check_limits() {
+        let rule = StoppingRule {
+            max_duration: Some(60),
+            max_samples: Some(100),
+            ..Default::default()
+        };
+        assert!(rule.check(analysis(), 100, 59.0, 99, 1000.0).is_none());
+        let conclusion = rule.check(analysis(), 100, 60.0, 99, 1000.0).unwrap();
+        assert_eq!(conclusion.reason, StoppingReason::MaxDuration);
+        let conclusion = rule.check(analysis(), 100, 10.0, 100, 1000.0).unwrap();
+        assert_eq!(conclusion.reason, StoppingReason::MaxSamples);
+        assert_eq!(conclusion.winner, 1);
+
+        assert!(StoppingRule::default().is_empty());
+        assert!(StoppingRule::default()
+            .check(analysis(), 100, 1e9, u64::MAX, 1000.0)
+            .is_none());
+    }
+
+    #[test]
+    fn validate_thresholds() {
+        let rule = StoppingRule {
+            probability_best: Some(1.5),
+            ..Default::default()
+        };
+        assert!(rule.validate().is_err());
+        let rule = StoppingRule {
+            expected_loss: Some(-0.1),
+            ..Default::default()
+        };
+        assert!(rule.validate().is_err());
+        let rule = StoppingRule {
+            probability_best: Some(0.95),
+            expected_loss: Some(0.0),
+            ..Default::default()
+        };
+        assert!(rule.validate().is_ok());
+    }
+}
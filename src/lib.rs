@@ -0,0 +1,9 @@
+pub mod actors;
+pub mod api;
+pub mod config;
+pub mod errors;
+pub mod evaluation;
+pub mod metrics;
+pub mod policies;
+pub mod repository;
+pub mod storage;
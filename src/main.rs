@@ -1,29 +1,28 @@
-mod actors;
-mod api;
-mod config;
-mod errors;
-mod policies;
-mod repository;
-
-use crate::api::routes::{disable_arm, enable_arm, ping_experiment, reset_arm};
-use crate::repository::Repository;
+use rust_bandits::actors::{
+    accountant::{Accountant, FlushLogs},
+    state_store::StateStore,
+};
+use rust_bandits::api::responses::log_response;
+use rust_bandits::api::routes::{
+    add_arm, analysis, archive, audit_trail, clear, conclude, create, delete_arm,
+    delete_experiment, disable_arm, draw, draw_with_context, enable_arm, evaluate_experiment,
+    evaluate_logs, experiment_status, export_interactions, get_pruning_rule, get_stopping_rule,
+    list, list_quarantined, list_snapshots, metrics, pause, ping, ping_experiment, reset,
+    reset_arm, restore_snapshot, resume, set_pruning_rule, set_stopping_rule, start, stats, update,
+    update_batch,
+};
+use rust_bandits::config::AppConfig;
+use rust_bandits::repository::Repository;
 
-use actix::prelude::*;
+use actix::{clock::timeout, prelude::*};
 use actix_web::{
     middleware::from_fn,
     web::{scope, Data},
     App, HttpServer,
 };
-use actors::{accountant::Accountant, state_store::StateStore};
-use api::responses::log_response;
-use api::routes::{
-    add_arm, clear, create, delete_arm, delete_experiment, draw, list, ping, reset, stats, update,
-    update_batch,
-};
-use config::AppConfig;
-use std::io::Error;
+use std::{io::Error, time::Duration};
 use tokio::sync::RwLock;
-use tracing::warn;
+use tracing::{info, warn};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
 
 #[actix_web::main]
@@ -39,8 +38,14 @@ async fn main() -> Result<(), Error> {
         .with(tracing_subscriber::fmt::layer())
         .init();
 
-    let accountant = Data::new(Accountant::new(config.accountant).start());
-    let state_store = StateStore::new(config.state_store).start();
+    let accountant = Data::new(
+        Accountant::new(config.accountant)
+            .expect("Failed to open request log sink")
+            .start(),
+    );
+    let state_store = StateStore::new(config.state_store)
+        .expect("Failed to open state store")
+        .start();
     let repository = Data::new(RwLock::new(Repository::new(
         config.experiment,
         state_store.clone(),
@@ -57,34 +62,85 @@ async fn main() -> Result<(), Error> {
         }
     }
 
+    let server_accountant = accountant.clone();
+    let server_repository = repository.clone();
     HttpServer::new(move || {
         App::new()
-            .app_data(accountant.clone())
-            .app_data(repository.clone())
+            .app_data(server_accountant.clone())
+            .app_data(server_repository.clone())
             .service(ping)
+            .service(metrics)
             .service(
-                scope("/v1").service(
-                    scope("/experiments")
-                        .wrap(from_fn(log_response))
-                        .service(list)
-                        .service(clear)
-                        .service(create)
-                        .service(ping_experiment)
-                        .service(reset)
-                        .service(delete_experiment)
-                        .service(add_arm)
-                        .service(disable_arm)
-                        .service(enable_arm)
-                        .service(reset_arm)
-                        .service(delete_arm)
-                        .service(draw)
-                        .service(update)
-                        .service(update_batch)
-                        .service(stats),
-                ),
+                scope("/v1")
+                    .service(
+                        scope("/admin")
+                            .wrap(from_fn(log_response))
+                            .service(list_quarantined),
+                    )
+                    .service(
+                        scope("/experiments")
+                            .wrap(from_fn(log_response))
+                            .service(list)
+                            .service(clear)
+                            .service(create)
+                            .service(ping_experiment)
+                            .service(reset)
+                            .service(delete_experiment)
+                            .service(add_arm)
+                            .service(disable_arm)
+                            .service(enable_arm)
+                            .service(reset_arm)
+                            .service(delete_arm)
+                            .service(draw)
+                            .service(draw_with_context)
+                            .service(update)
+                            .service(update_batch)
+                            .service(stats)
+                            .service(analysis)
+                            .service(experiment_status)
+                            .service(start)
+                            .service(pause)
+                            .service(resume)
+                            .service(conclude)
+                            .service(archive)
+                            .service(get_stopping_rule)
+                            .service(set_stopping_rule)
+                            .service(get_pruning_rule)
+                            .service(set_pruning_rule)
+                            .service(list_snapshots)
+                            .service(restore_snapshot)
+                            .service(audit_trail)
+                            .service(export_interactions)
+                            .service(evaluate_logs)
+                            .service(evaluate_experiment),
+                    ),
             )
     })
     .bind((config.server.host, config.server.port))?
     .run()
+    .await?;
+
+    // the server only returns once it stopped accepting requests and served the pending ones, so
+    // experiments are left untouched while they store their state
+    info!("Flushing experiments to the state store");
+    let deadline = Duration::from_secs(config.server.shutdown_deadline);
+    match timeout(deadline, async {
+        let failures = repository.read().await.flush_experiments().await;
+        if let Err(err) = accountant.send(FlushLogs).await {
+            warn!(error = %err, "Failed to flush request logs");
+        }
+        failures
+    })
     .await
+    {
+        Ok(failures) => failures.iter().for_each(|(experiment_id, err)| {
+            warn!(error = %err, id = %experiment_id, "Failed to flush experiment");
+        }),
+        Err(_) => warn!(
+            deadline = config.server.shutdown_deadline,
+            "Shutdown deadline elapsed before every experiment was flushed"
+        ),
+    }
+
+    Ok(())
 }
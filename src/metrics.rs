@@ -0,0 +1,212 @@
+use prometheus::{
+    Encoder, GaugeVec, Histogram, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, Opts,
+    Registry, TextEncoder,
+};
+use std::sync::LazyLock;
+use std::time::Duration;
+use uuid::Uuid;
+
+// Metrics of the service, recorded by the components where the events happen and exposed in the
+// Prometheus text format by the /metrics route
+pub struct Metrics {
+    registry: Registry,
+    http_requests: IntCounterVec,
+    http_request_duration: HistogramVec,
+    mailbox_errors: IntCounterVec,
+    experiment_restarts: IntCounterVec,
+    state_store_write_duration: Histogram,
+    state_store_write_failures: IntCounter,
+    arm_draws: IntCounterVec,
+    arm_updates: IntCounterVec,
+    arm_rewards: GaugeVec,
+    arm_mean_reward: GaugeVec,
+}
+
+pub static METRICS: LazyLock<Metrics> =
+    LazyLock::new(|| Metrics::new().expect("metrics should be valid"));
+
+impl Metrics {
+    fn new() -> Result<Self, prometheus::Error> {
+        let registry = Registry::new_custom(Some("bandits".to_string()), None)?;
+        let http_requests = IntCounterVec::new(
+            Opts::new("http_requests_total", "Requests served per route"),
+            &["route", "method", "status"],
+        )?;
+        let http_request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "http_request_duration_seconds",
+                "Time taken to serve requests per route",
+            ),
+            &["route", "method"],
+        )?;
+        let mailbox_errors = IntCounterVec::new(
+            Opts::new(
+                "mailbox_errors_total",
+                "Messages that could not be delivered per actor",
+            ),
+            &["actor"],
+        )?;
+        let experiment_restarts = IntCounterVec::new(
+            Opts::new(
+                "experiment_restarts_total",
+                "Restarts of experiments by their supervisor",
+            ),
+            &["experiment_id"],
+        )?;
+        let state_store_write_duration = Histogram::with_opts(HistogramOpts::new(
+            "state_store_write_duration_seconds",
+            "Time taken to write experiment states",
+        ))?;
+        let state_store_write_failures = IntCounter::new(
+            "state_store_write_failures_total",
+            "Experiment states that could not be written",
+        )?;
+        let arm_draws = IntCounterVec::new(
+            Opts::new("experiment_draws_total", "Draws served per arm"),
+            &["experiment_id", "arm_id"],
+        )?;
+        let arm_updates = IntCounterVec::new(
+            Opts::new("experiment_updates_total", "Rewards received per arm"),
+            &["experiment_id", "arm_id"],
+        )?;
+        // rewards may be negative, so that their sum is not a counter
+        let arm_rewards = GaugeVec::new(
+            Opts::new(
+                "experiment_rewards_sum",
+                "Sum of the rewards received per arm",
+            ),
+            &["experiment_id", "arm_id"],
+        )?;
+        let arm_mean_reward = GaugeVec::new(
+            Opts::new(
+                "experiment_mean_reward",
+                "Mean of the rewards received per arm",
+            ),
+            &["experiment_id", "arm_id"],
+        )?;
+
+        registry.register(Box::new(http_requests.clone()))?;
+        registry.register(Box::new(http_request_duration.clone()))?;
+        registry.register(Box::new(mailbox_errors.clone()))?;
+        registry.register(Box::new(experiment_restarts.clone()))?;
+        registry.register(Box::new(state_store_write_duration.clone()))?;
+        registry.register(Box::new(state_store_write_failures.clone()))?;
+        registry.register(Box::new(arm_draws.clone()))?;
+        registry.register(Box::new(arm_updates.clone()))?;
+        registry.register(Box::new(arm_rewards.clone()))?;
+        registry.register(Box::new(arm_mean_reward.clone()))?;
+
+        Ok(Self {
+            registry,
+            http_requests,
+            http_request_duration,
+            mailbox_errors,
+            experiment_restarts,
+            state_store_write_duration,
+            state_store_write_failures,
+            arm_draws,
+            arm_updates,
+            arm_rewards,
+            arm_mean_reward,
+        })
+    }
+
+    // routes are identified by their pattern so that ids do not end up in labels
+    pub fn observe_request(&self, route: &str, method: &str, status: u16, duration: Duration) {
+        self.http_requests
+            .with_label_values(&[route, method, &status.to_string()])
+            .inc();
+        self.http_request_duration
+            .with_label_values(&[route, method])
+            .observe(duration.as_secs_f64());
+    }
+
+    pub fn mailbox_error(&self, actor: &str) {
+        self.mailbox_errors.with_label_values(&[actor]).inc();
+    }
+
+    pub fn experiment_restarted(&self, experiment_id: Uuid) {
+        self.experiment_restarts
+            .with_label_values(&[&experiment_id.to_string()])
+            .inc();
+    }
+
+    pub fn state_store_write(&self, duration: Duration, succeeded: bool) {
+        self.state_store_write_duration
+            .observe(duration.as_secs_f64());
+        if !succeeded {
+            self.state_store_write_failures.inc();
+        }
+    }
+
+    pub fn arm_drawn(&self, experiment_id: Uuid, arm_id: usize) {
+        self.arm_draws
+            .with_label_values(&[&experiment_id.to_string(), &arm_id.to_string()])
+            .inc();
+    }
+
+    // the mean reward is derived from the counters, so that scrapes do not reach the experiments
+    pub fn arm_updated(&self, experiment_id: Uuid, arm_id: usize, reward: f64) {
+        let (experiment_id, arm_id) = (experiment_id.to_string(), arm_id.to_string());
+        let labels = [experiment_id.as_str(), arm_id.as_str()];
+        let updates = self.arm_updates.with_label_values(&labels);
+        updates.inc();
+        let rewards = self.arm_rewards.with_label_values(&labels);
+        rewards.add(reward);
+        self.arm_mean_reward
+            .with_label_values(&labels)
+            .set(rewards.get() / updates.get() as f64);
+    }
+
+    // series of deleted experiments are dropped rather than reported forever
+    pub fn remove_experiment(&self, experiment_id: Uuid, arm_ids: impl Iterator<Item = usize>) {
+        let experiment_id = experiment_id.to_string();
+        for arm_id in arm_ids {
+            let labels = [experiment_id.as_str(), &arm_id.to_string()];
+            let _ = self.arm_draws.remove_label_values(&labels);
+            let _ = self.arm_updates.remove_label_values(&labels);
+            let _ = self.arm_rewards.remove_label_values(&labels);
+            let _ = self.arm_mean_reward.remove_label_values(&labels);
+        }
+        let _ = self
+            .experiment_restarts
+            .remove_label_values(&[&experiment_id]);
+    }
+
+    pub fn render(&self) -> Result<String, prometheus::Error> {
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        Ok(String::from_utf8_lossy(&buffer).into_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_experiment_metrics() {
+        let experiment_id = Uuid::new_v4();
+        METRICS.arm_drawn(experiment_id, 0);
+        METRICS.arm_drawn(experiment_id, 0);
+        METRICS.arm_updated(experiment_id, 0, 1.0);
+        METRICS.arm_updated(experiment_id, 0, 0.0);
+
+        let rendered = METRICS.render().unwrap();
+        assert!(rendered.contains(&format!(
+            "bandits_experiment_draws_total{{arm_id=\"0\",experiment_id=\"{experiment_id}\"}} 2"
+        )));
+        assert!(rendered.contains(&format!(
+            "bandits_experiment_updates_total{{arm_id=\"0\",experiment_id=\"{experiment_id}\"}} 2"
+        )));
+        assert!(rendered.contains(&format!(
+            "bandits_experiment_mean_reward{{arm_id=\"0\",experiment_id=\"{experiment_id}\"}} 0.5"
+        )));
+
+        METRICS.remove_experiment(experiment_id, [0].into_iter());
+        assert!(!METRICS
+            .render()
+            .unwrap()
+            .contains(&experiment_id.to_string()));
+    }
+}
@@ -1,6 +1,7 @@
+use super::intervals::RewardMoments;
 use super::policy::{
-    get_timestamp, ArmStats, BatchUpdateElement, CloneBoxedPolicy, DrawResult, Policy, PolicyStats,
-    PolicyType,
+    ArmStats, BatchUpdateElement, CloneBoxedPolicy, DrawResult, Policy, PolicyStats, PolicyType,
+    RewardDomain,
 };
 use super::rng::MaybeSeededRng;
 
@@ -8,14 +9,15 @@ use crate::errors::PolicyError;
 
 use rand::{seq::IteratorRandom, Rng};
 use serde::{Deserialize, Serialize};
-use std::cmp::Ordering;
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 struct EpsilonGreedyArm {
     reward: f64,
     count: u64,
     is_active: bool,
+    #[serde(default)]
+    moments: RewardMoments,
 }
 
 impl EpsilonGreedyArm {
@@ -24,12 +26,14 @@ impl EpsilonGreedyArm {
             reward: initial_reward,
             count: initial_count,
             is_active: true,
+            moments: RewardMoments::new(initial_reward),
         }
     }
 
     fn reset(&mut self, cumulative_reward: Option<f64>, count: Option<u64>) {
         self.reward = cumulative_reward.unwrap_or_default();
         self.count = count.unwrap_or_default();
+        self.moments = RewardMoments::new(self.reward);
     }
 
     fn sample<R: Rng + ?Sized>(&self, _: &mut R) -> f64 {
@@ -39,13 +43,16 @@ impl EpsilonGreedyArm {
     fn update(&mut self, reward: f64, _: f64) {
         self.count += 1;
         self.reward += (reward - self.reward) / (self.count as f64);
+        self.moments.push(reward);
     }
 
-    fn stats(&self) -> ArmStats {
+    fn stats(&self, level: f64) -> ArmStats {
         ArmStats {
             pulls: self.count,
             mean_reward: self.reward,
             is_active: self.is_active,
+            is_pruned: false,
+            interval: self.moments.interval(self.reward, self.count, level),
         }
     }
 }
@@ -59,7 +66,7 @@ pub enum DecayType {
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct EpsilonGreedy {
-    arms: HashMap<usize, EpsilonGreedyArm>,
+    arms: BTreeMap<usize, EpsilonGreedyArm>,
     epsilon: f64,
     epsilon_decay: Option<DecayType>,
     active_pull_count: u64,
@@ -70,7 +77,7 @@ pub struct EpsilonGreedy {
 impl EpsilonGreedy {
     pub fn new(epsilon: f64, epsilon_decay: Option<DecayType>, seed: Option<u64>) -> Self {
         Self {
-            arms: HashMap::new(),
+            arms: BTreeMap::new(),
             epsilon,
             epsilon_decay,
             active_pull_count: 0,
@@ -112,6 +119,14 @@ impl Policy for EpsilonGreedy {
         }
     }
 
+    fn reward_domain(&self) -> RewardDomain {
+        RewardDomain::Unbounded
+    }
+
+    fn reward_moments(&self, arm_id: usize) -> Option<&RewardMoments> {
+        self.arms.get(&arm_id).map(|arm| &arm.moments)
+    }
+
     fn reset(
         &mut self,
         arm_id: Option<usize>,
@@ -176,29 +191,65 @@ impl Policy for EpsilonGreedy {
         Ok(())
     }
 
-    fn draw(&mut self) -> Result<DrawResult, PolicyError> {
-        let timestamp = get_timestamp();
-        let arm_iterator = self.arms.iter().filter(|(_, arm)| arm.is_active);
+    fn draw_at(&mut self, timestamp: f64, _: Option<&[f64]>) -> Result<DrawResult, PolicyError> {
         let epsilon = self.epsilon_with_decay();
+        let explore = self.rng.rng_mut().random::<f64>() < epsilon;
 
-        // either sample a random arm or return the one with the highest reward so far
-        let arm_id = if self.rng.rng_mut().random::<f64>() < epsilon {
-            arm_iterator
-                .map(|(&arm_id, _)| arm_id)
-                .choose(&mut self.rng.rng_mut())
-                .ok_or(PolicyError::NoArmsAvailable)
+        let active_arm_ids = self
+            .arms
+            .iter()
+            .filter(|(_, arm)| arm.is_active)
+            .map(|(&arm_id, _)| arm_id)
+            .collect::<Vec<usize>>();
+        let rewards = active_arm_ids
+            .iter()
+            .map(|arm_id| (*arm_id, self.arms[arm_id].sample(self.rng.rng_mut())))
+            .collect::<Vec<(usize, f64)>>();
+        let best_reward = rewards
+            .iter()
+            .map(|&(_, reward)| reward)
+            .fold(f64::NEG_INFINITY, f64::max);
+        let greedy_arm_ids = rewards
+            .into_iter()
+            .filter(|&(_, reward)| reward >= best_reward)
+            .map(|(arm_id, _)| arm_id)
+            .collect::<Vec<usize>>();
+
+        // either sample a random arm or return one with the highest reward so far, ties being broken
+        // at random
+        let candidate_arm_ids = if explore {
+            &active_arm_ids
         } else {
-            arm_iterator
-                .map(|(arm_id, arm)| (arm_id, arm.sample(self.rng.rng_mut())))
-                .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(Ordering::Equal))
-                .map(|(&arm_id, _)| arm_id)
-                .ok_or(PolicyError::NoArmsAvailable)
-        }?;
+            &greedy_arm_ids
+        };
+        let arm_id = candidate_arm_ids
+            .iter()
+            .copied()
+            .choose(self.rng.rng_mut())
+            .ok_or(PolicyError::NoArmsAvailable)?;
+
+        // any arm can be explored uniformly, and the greedy ones are also selected when exploiting
+        let epsilon = epsilon.clamp(0.0, 1.0);
+        let mut propensity = epsilon / (active_arm_ids.len() as f64);
+        if greedy_arm_ids.contains(&arm_id) {
+            propensity += (1.0 - epsilon) / (greedy_arm_ids.len() as f64);
+        }
 
-        Ok(DrawResult { timestamp, arm_id })
+        Ok(DrawResult {
+            timestamp,
+            arm_id,
+            propensity: Some(propensity),
+        })
     }
 
-    fn update(&mut self, timestamp: f64, arm_id: usize, reward: f64) -> Result<(), PolicyError> {
+    fn update(
+        &mut self,
+        timestamp: f64,
+        arm_id: usize,
+        reward: f64,
+        _: Option<&[f64]>,
+    ) -> Result<(), PolicyError> {
+        self.reward_domain().validate(reward)?;
         // update the arm statistics
         let arm = self
             .arms
@@ -215,27 +266,31 @@ impl Policy for EpsilonGreedy {
     }
 
     fn update_batch(&mut self, updates: &[BatchUpdateElement]) -> Result<(), PolicyError> {
+        self.reward_domain().validate_batch(updates)?;
         updates.iter().try_for_each(
-            |&BatchUpdateElement {
+            |BatchUpdateElement {
                  timestamp,
                  arm_id,
                  reward,
-             }| self.update(timestamp, arm_id, reward),
+                 context,
+             }| self.update(*timestamp, *arm_id, *reward, context.as_deref()),
         )
     }
 
-    fn stats(&self) -> PolicyStats {
+    fn stats_at(&self, level: f64) -> PolicyStats {
         PolicyStats {
+            level,
             arms: self
                 .arms
                 .iter()
-                .map(|(&id, arm)| (id, arm.stats()))
+                .map(|(&id, arm)| (id, arm.stats(level)))
                 .collect(),
         }
     }
 }
 
 #[cfg(test)]
+#[allow(clippy::len_zero, clippy::needless_borrow, clippy::option_map_unit_fn)]
 mod tests {
     use super::*;
 
@@ -297,7 +352,10 @@ mod tests {
     fn draw() {
         let mut policy = make_policy();
         let arm_id = policy.add_arm(0.0, 0);
-        let result = policy.draw().ok().map(|DrawResult { arm_id, .. }| arm_id);
+        let result = policy
+            .draw(None)
+            .ok()
+            .map(|DrawResult { arm_id, .. }| arm_id);
         assert_eq!(result, Some(arm_id));
     }
 
@@ -308,14 +366,50 @@ mod tests {
         let _ = policy.add_arm(0.0, 0);
 
         policy.arms.get_mut(&arm_1).map(|arm| arm.reward = 1.0);
-        let result = policy.draw().ok().map(|DrawResult { arm_id, .. }| arm_id);
+        let result = policy
+            .draw(None)
+            .ok()
+            .map(|DrawResult { arm_id, .. }| arm_id);
         assert_eq!(result, Some(arm_1));
     }
 
+    #[test]
+    fn draw_propensity() {
+        let mut policy = make_policy();
+        let arm_1 = policy.add_arm(1.0, 1);
+        let _ = policy.add_arm(0.0, 1);
+
+        for _ in 0..20 {
+            let DrawResult {
+                arm_id, propensity, ..
+            } = policy.draw(None).unwrap();
+            let expected = if arm_id == arm_1 {
+                EPSILON / 2.0 + 1.0 - EPSILON
+            } else {
+                EPSILON / 2.0
+            };
+            assert!((propensity.unwrap() - expected).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn draw_tied_arms() {
+        let mut policy = EpsilonGreedy::new(0.0, None, DEFAULT_SEED);
+        let arm_1 = policy.add_arm(0.0, 0);
+        let arm_2 = policy.add_arm(0.0, 0);
+
+        let arm_ids = (0..20)
+            .map(|_| policy.draw(None).unwrap())
+            .inspect(|result| assert_eq!(result.propensity, Some(0.5)))
+            .map(|result| result.arm_id)
+            .collect::<Vec<usize>>();
+        assert!(arm_ids.contains(&arm_1) && arm_ids.contains(&arm_2));
+    }
+
     #[test]
     fn draw_empty() {
         let mut policy = make_policy();
-        assert!(policy.draw().is_err());
+        assert!(policy.draw(None).is_err());
     }
 
     #[test]
@@ -326,9 +420,9 @@ mod tests {
 
         let DrawResult {
             timestamp, arm_id, ..
-        } = policy.draw().unwrap();
+        } = policy.draw(None).unwrap();
 
-        assert!(policy.update(timestamp + 1.0, arm_id, 1.0).is_ok());
+        assert!(policy.update(timestamp + 1.0, arm_id, 1.0, None).is_ok());
         assert_eq!(policy.arms.get(&arm_id).map(|arm| arm.reward), Some(1.0));
     }
 
@@ -339,7 +433,7 @@ mod tests {
         let _ = policy.add_arm(0.0, 0);
 
         let draws = (0..3)
-            .map(|_| policy.draw().unwrap())
+            .map(|_| policy.draw(None).unwrap())
             .collect::<Vec<DrawResult>>();
         let updates = draws
             .iter()
@@ -347,6 +441,7 @@ mod tests {
                 timestamp: draw.timestamp + 1.0,
                 arm_id: draw.arm_id,
                 reward: 1.0,
+                context: None,
             })
             .collect::<Vec<BatchUpdateElement>>();
 
@@ -365,7 +460,7 @@ mod tests {
         let _ = policy.add_arm(0.0, 0);
 
         let draws = (0..3)
-            .map(|_| policy.draw().unwrap())
+            .map(|_| policy.draw(None).unwrap())
             .collect::<Vec<DrawResult>>();
         let updates = draws
             .iter()
@@ -373,6 +468,7 @@ mod tests {
                 timestamp: draw.timestamp + 1.0,
                 arm_id: draw.arm_id,
                 reward: 1.0,
+                context: None,
             })
             .collect::<Vec<BatchUpdateElement>>();
 
@@ -392,7 +488,7 @@ mod tests {
         let _ = policy.add_arm(0.0, 0);
 
         let draws = (0..3)
-            .map(|_| policy.draw().unwrap())
+            .map(|_| policy.draw(None).unwrap())
             .collect::<Vec<DrawResult>>();
         let updates = draws
             .iter()
@@ -400,6 +496,7 @@ mod tests {
                 timestamp: draw.timestamp + 1.0,
                 arm_id: draw.arm_id,
                 reward: 1.0,
+                context: None,
             })
             .collect::<Vec<BatchUpdateElement>>();
 
@@ -416,7 +513,7 @@ mod tests {
         let _ = policy.add_arm(0.0, 0);
 
         let draws = (0..3)
-            .map(|_| policy.draw().unwrap())
+            .map(|_| policy.draw(None).unwrap())
             .collect::<Vec<DrawResult>>();
         let updates = draws
             .iter()
@@ -424,6 +521,7 @@ mod tests {
                 timestamp: draw.timestamp + 1.0,
                 arm_id: draw.arm_id,
                 reward: 1.0,
+                context: None,
             })
             .collect::<Vec<BatchUpdateElement>>();
 
@@ -446,7 +544,7 @@ mod tests {
         let _ = policy.add_arm(0.0, 0);
 
         let draws = (0..3)
-            .map(|_| policy.draw().unwrap())
+            .map(|_| policy.draw(None).unwrap())
             .collect::<Vec<DrawResult>>();
         let updates = draws
             .iter()
@@ -454,6 +552,7 @@ mod tests {
                 timestamp: draw.timestamp + 1.0,
                 arm_id: draw.arm_id,
                 reward: 1.0,
+                context: None,
             })
             .collect::<Vec<BatchUpdateElement>>();
 
@@ -462,7 +561,7 @@ mod tests {
         assert!((policy.epsilon_with_decay() - 0.07).abs() < 1e-6);
 
         let draws = (0..10)
-            .map(|_| policy.draw().unwrap())
+            .map(|_| policy.draw(None).unwrap())
             .collect::<Vec<DrawResult>>();
         let updates = draws
             .iter()
@@ -470,6 +569,7 @@ mod tests {
                 timestamp: draw.timestamp + 1.0,
                 arm_id: draw.arm_id,
                 reward: 1.0,
+                context: None,
             })
             .collect::<Vec<BatchUpdateElement>>();
 
@@ -477,4 +577,25 @@ mod tests {
 
         assert_eq!(policy.epsilon_with_decay(), 0.01);
     }
+
+    #[test]
+    fn stats_interval() {
+        let mut policy = make_policy();
+        let binary_arm = policy.add_arm(0.0, 0);
+        let unbounded_arm = policy.add_arm(0.0, 0);
+        for (reward_1, reward_2) in [(1.0, 10.0), (0.0, 20.0), (1.0, 30.0)] {
+            policy.update(0.0, binary_arm, reward_1, None).unwrap();
+            policy.update(0.0, unbounded_arm, reward_2, None).unwrap();
+        }
+
+        let stats = policy.stats();
+        let interval = stats.arms[&binary_arm].interval.unwrap();
+        assert!(interval.lower >= 0.0 && interval.upper <= 1.0);
+        // rewards outside [0, 1] get a normal interval from their variance
+        let interval = stats.arms[&unbounded_arm].interval.unwrap();
+        assert!(interval.lower < 20.0 && interval.upper > 20.0);
+        assert!(
+            (interval.upper - interval.lower - 2.0 * 1.959964 * 10.0 / 3.0_f64.sqrt()).abs() < 1e-3
+        );
+    }
 }
@@ -0,0 +1,507 @@
+use super::intervals::Interval;
+use super::policy::{
+    estimate_propensity, get_timestamp, halflife_decay, ArmStats, BatchUpdateElement,
+    CloneBoxedPolicy, DrawResult, Policy, PolicyStats, PolicyType, RewardDomain,
+};
+use super::posterior::ArmPosterior;
+use super::rng::MaybeSeededRng;
+
+use crate::errors::PolicyError;
+
+use rand::Rng;
+use rand_distr::{Distribution, Gamma, Normal};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+
+const EPS: f64 = 1e-6;
+
+// Normal-Inverse-Gamma prior over the unknown mean and variance of the rewards:
+// variance ~ InvGamma(shape, rate) and mean | variance ~ N(mean, variance / count)
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct NormalInverseGamma {
+    pub mean: f64,
+    pub count: f64,
+    pub shape: f64,
+    pub rate: f64,
+}
+
+impl Default for NormalInverseGamma {
+    fn default() -> Self {
+        Self {
+            mean: 0.0,
+            count: 1.0,
+            shape: 1.0,
+            rate: 1.0,
+        }
+    }
+}
+
+impl NormalInverseGamma {
+    // posterior after observing count rewards with the given mean, the within-sample variance being unknown
+    fn observe(&self, mean_reward: f64, count: u64) -> Self {
+        let n = count as f64;
+        let posterior_count = self.count + n;
+        Self {
+            mean: (self.count * self.mean + n * mean_reward) / posterior_count,
+            count: posterior_count,
+            shape: self.shape + n / 2.0,
+            rate: self.rate
+                + self.count * n * (mean_reward - self.mean).powi(2) / (2.0 * posterior_count),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct GaussianThompsonSamplingArm {
+    posterior: NormalInverseGamma,
+    count: u64,
+    halflife_seconds: Option<f64>,
+    last_ts: f64,
+    is_active: bool,
+}
+
+impl GaussianThompsonSamplingArm {
+    fn new(
+        prior: &NormalInverseGamma,
+        initial_reward: f64,
+        initial_count: u64,
+        halflife_seconds: Option<f64>,
+    ) -> Self {
+        Self {
+            posterior: prior.observe(initial_reward, initial_count),
+            count: initial_count,
+            halflife_seconds,
+            last_ts: get_timestamp(),
+            is_active: true,
+        }
+    }
+
+    fn reset(
+        &mut self,
+        prior: &NormalInverseGamma,
+        cumulative_reward: Option<f64>,
+        count: Option<u64>,
+    ) {
+        if let (Some(cumulative_reward), Some(count)) = (cumulative_reward, count) {
+            self.posterior = prior.observe(cumulative_reward, count);
+            self.count = count;
+        } else {
+            self.posterior = *prior;
+            self.count = 0;
+        }
+        self.last_ts = get_timestamp();
+    }
+
+    // shrink the pseudo-counts so that the mean becomes less certain, its location is unchanged
+    fn apply_discount(&mut self, timestamp: f64) {
+        let decay = halflife_decay(self.halflife_seconds, timestamp - self.last_ts);
+        self.posterior.count = (self.posterior.count * decay).max(EPS);
+        self.posterior.shape = (self.posterior.shape * decay).max(EPS);
+        self.posterior.rate = (self.posterior.rate * decay).max(EPS);
+        self.last_ts = timestamp;
+    }
+
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Result<f64, PolicyError> {
+        let NormalInverseGamma {
+            mean,
+            count,
+            shape,
+            rate,
+        } = self.posterior;
+
+        // variance ~ InvGamma(shape, rate) is the inverse of a Gamma(shape, 1 / rate) sample
+        let precision = Gamma::new(shape, 1.0 / rate)
+            .map_err(|e| PolicyError::SamplingError(e.to_string()))?
+            .sample(rng);
+        let s = Normal::new(mean, (1.0 / (precision * count)).sqrt())
+            .map_err(|e| PolicyError::SamplingError(e.to_string()))?
+            .sample(rng);
+
+        Ok(s)
+    }
+
+    fn update(&mut self, reward: f64, timestamp: f64) {
+        self.apply_discount(timestamp);
+        self.posterior = self.posterior.observe(reward, 1);
+        self.count += 1;
+    }
+
+    // the marginal posterior of the mean is a Student t with 2 * shape degrees of freedom
+    fn mean_posterior(&self) -> ArmPosterior {
+        let NormalInverseGamma {
+            mean,
+            count,
+            shape,
+            rate,
+        } = self.posterior;
+        ArmPosterior::StudentT {
+            location: mean,
+            scale: (rate / (shape * count)).sqrt(),
+            dof: 2.0 * shape,
+        }
+    }
+
+    fn stats(&self, level: f64) -> ArmStats {
+        let interval = match self.mean_posterior() {
+            ArmPosterior::StudentT {
+                location,
+                scale,
+                dof,
+            } => Some(Interval::student_t(location, scale, dof, level)),
+            _ => None,
+        };
+        ArmStats {
+            pulls: self.count,
+            mean_reward: self.posterior.mean,
+            is_active: self.is_active,
+            is_pruned: false,
+            interval,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GaussianThompsonSampling {
+    prior: NormalInverseGamma,
+    halflife_seconds: Option<f64>,
+    #[serde(default)]
+    propensity_samples: usize,
+    arms: BTreeMap<usize, GaussianThompsonSamplingArm>,
+    rng: MaybeSeededRng,
+    next_arm_id: usize,
+}
+
+impl GaussianThompsonSampling {
+    // Thompson Sampling for real-valued rewards with unknown mean and variance, optionally decayed using halflife
+    pub fn new(
+        prior: Option<NormalInverseGamma>,
+        halflife_seconds: Option<f64>,
+        propensity_samples: usize,
+        seed: Option<u64>,
+    ) -> Self {
+        Self {
+            prior: prior.unwrap_or_default(),
+            halflife_seconds,
+            propensity_samples,
+            arms: BTreeMap::new(),
+            rng: MaybeSeededRng::new(seed),
+            next_arm_id: 0,
+        }
+    }
+
+    // sample a mean from the posterior of each arm and select the arm with the best statistic
+    fn sample_best_arm(&mut self) -> Option<usize> {
+        self.arms
+            .iter()
+            .filter(|(_, arm)| arm.is_active)
+            .filter_map(|(arm_id, arm)| {
+                arm.sample(self.rng.rng_mut())
+                    .map_or(None, |sample| Some((arm_id, sample)))
+            })
+            .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(Ordering::Equal))
+            .map(|(&arm_id, _)| arm_id)
+    }
+}
+
+impl CloneBoxedPolicy for GaussianThompsonSampling {
+    fn clone_box(&self) -> Box<dyn Policy + Send> {
+        Box::new(self.clone())
+    }
+}
+
+#[typetag::serde]
+impl Policy for GaussianThompsonSampling {
+    fn policy_type(&self) -> PolicyType {
+        PolicyType::GaussianThompsonSampling {
+            prior: Some(self.prior),
+            halflife_seconds: self.halflife_seconds,
+            propensity_samples: self.propensity_samples,
+            seed: self.rng.seed,
+        }
+    }
+
+    fn reward_domain(&self) -> RewardDomain {
+        RewardDomain::Unbounded
+    }
+
+    // draws discount the evidence of every arm and advance a seeded generator
+    fn draw_changes_state(&self) -> bool {
+        self.halflife_seconds.is_some() || self.rng.seed.is_some()
+    }
+
+    fn reset(
+        &mut self,
+        arm_id: Option<usize>,
+        cumulative_reward: Option<f64>,
+        count: Option<u64>,
+    ) -> Result<(), PolicyError> {
+        if let Some(arm_id) = arm_id {
+            self.arms
+                .get_mut(&arm_id)
+                .map(|arm| arm.reset(&self.prior, cumulative_reward, count))
+                .ok_or(PolicyError::ArmNotFound(arm_id))?;
+        } else {
+            self.arms
+                .values_mut()
+                .for_each(|arm| arm.reset(&self.prior, None, None));
+        }
+        Ok(())
+    }
+
+    fn add_arm(&mut self, initial_reward: f64, initial_count: u64) -> usize {
+        let arm_id = self.next_arm_id;
+        self.arms.insert(
+            arm_id,
+            GaussianThompsonSamplingArm::new(
+                &self.prior,
+                initial_reward,
+                initial_count,
+                self.halflife_seconds,
+            ),
+        );
+        self.next_arm_id += 1;
+
+        arm_id
+    }
+
+    fn disable_arm(&mut self, arm_id: usize) -> Result<(), PolicyError> {
+        self.arms
+            .get_mut(&arm_id)
+            .map(|arm| arm.is_active = false)
+            .ok_or(PolicyError::ArmNotFound(arm_id))
+    }
+
+    fn enable_arm(&mut self, arm_id: usize) -> Result<(), PolicyError> {
+        self.arms
+            .get_mut(&arm_id)
+            .map(|arm| arm.is_active = true)
+            .ok_or(PolicyError::ArmNotFound(arm_id))
+    }
+
+    fn delete_arm(&mut self, arm_id: usize) -> Result<(), PolicyError> {
+        self.arms
+            .remove(&arm_id)
+            .ok_or(PolicyError::ArmNotFound(arm_id))?;
+        Ok(())
+    }
+
+    fn draw_at(&mut self, timestamp: f64, _: Option<&[f64]>) -> Result<DrawResult, PolicyError> {
+        // apply discount to all arms
+        self.arms
+            .values_mut()
+            .filter(|arm| arm.is_active)
+            .for_each(|arm| arm.apply_discount(timestamp));
+
+        let arm_id = self.sample_best_arm().ok_or(PolicyError::NoArmsAvailable)?;
+        let propensity =
+            estimate_propensity(arm_id, self.propensity_samples, || self.sample_best_arm());
+
+        Ok(DrawResult {
+            timestamp,
+            arm_id,
+            propensity,
+        })
+    }
+
+    fn update(
+        &mut self,
+        timestamp: f64,
+        arm_id: usize,
+        reward: f64,
+        _: Option<&[f64]>,
+    ) -> Result<(), PolicyError> {
+        self.reward_domain().validate(reward)?;
+        // update the arm statistics
+        let arm = self
+            .arms
+            .get_mut(&arm_id)
+            .ok_or(PolicyError::ArmNotFound(arm_id))?;
+
+        if !arm.is_active {
+            return Err(PolicyError::InactiveArm(arm_id));
+        }
+
+        arm.update(reward, timestamp);
+        Ok(())
+    }
+
+    fn update_batch(&mut self, updates: &[BatchUpdateElement]) -> Result<(), PolicyError> {
+        self.reward_domain().validate_batch(updates)?;
+        updates.iter().try_for_each(
+            |BatchUpdateElement {
+                 timestamp,
+                 arm_id,
+                 reward,
+                 context,
+             }| self.update(*timestamp, *arm_id, *reward, context.as_deref()),
+        )
+    }
+
+    fn stats_at(&self, level: f64) -> PolicyStats {
+        PolicyStats {
+            level,
+            arms: self
+                .arms
+                .iter()
+                .map(|(&id, arm)| (id, arm.stats(level)))
+                .collect(),
+        }
+    }
+
+    fn posteriors(&self) -> HashMap<usize, ArmPosterior> {
+        self.arms
+            .iter()
+            .filter(|(_, arm)| arm.is_active)
+            .map(|(&id, arm)| (id, arm.mean_posterior()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEFAULT_SEED: Option<u64> = Some(1234);
+
+    fn make_policy() -> GaussianThompsonSampling {
+        GaussianThompsonSampling::new(None, None, 0, DEFAULT_SEED)
+    }
+
+    fn make_arm(halflife_seconds: Option<f64>) -> GaussianThompsonSamplingArm {
+        GaussianThompsonSamplingArm {
+            posterior: NormalInverseGamma::default(),
+            count: 0,
+            halflife_seconds,
+            last_ts: 0.0,
+            is_active: true,
+        }
+    }
+
+    #[test]
+    fn create_arm() {
+        let mut policy = make_policy();
+        assert!(policy.arms.is_empty());
+
+        let arm_id = policy.add_arm(0.0, 0);
+        assert!(policy.arms.contains_key(&arm_id))
+    }
+
+    #[test]
+    fn disable_arm() {
+        let mut policy = make_policy();
+        let arm_id = policy.add_arm(0.0, 0);
+
+        assert!(policy.disable_arm(arm_id).is_ok());
+        assert_eq!(
+            policy.arms.iter().filter(|(_, arm)| arm.is_active).count(),
+            0
+        );
+    }
+
+    #[test]
+    fn delete_arm() {
+        let mut policy = make_policy();
+        let arm_id = policy.add_arm(0.0, 0);
+        assert!(policy.delete_arm(arm_id).is_ok());
+        assert!(!policy.arms.contains_key(&arm_id));
+        assert!(policy.delete_arm(arm_id).is_err());
+    }
+
+    #[test]
+    fn draw() {
+        let mut policy = make_policy();
+        let arm_id = policy.add_arm(0.0, 0);
+        let result = policy
+            .draw(None)
+            .ok()
+            .map(|DrawResult { arm_id, .. }| arm_id);
+        assert_eq!(result, Some(arm_id));
+    }
+
+    #[test]
+    fn draw_best() {
+        let mut policy = make_policy();
+        let arm_1 = policy.add_arm(250.0, 100);
+        let _ = policy.add_arm(20.0, 100);
+
+        let result = policy
+            .draw(None)
+            .ok()
+            .map(|DrawResult { arm_id, .. }| arm_id);
+        assert_eq!(result, Some(arm_1));
+    }
+
+    #[test]
+    fn draw_empty() {
+        let mut policy = make_policy();
+        assert!(policy.draw(None).is_err());
+    }
+
+    #[test]
+    fn update_unbounded_rewards() {
+        let mut arm = make_arm(None);
+
+        arm.update(120.0, 0.0);
+        arm.update(-40.0, 0.0);
+        assert_eq!(arm.count, 2);
+        assert!((arm.posterior.mean - 80.0 / 3.0).abs() < EPS);
+        assert!((arm.posterior.count - 3.0).abs() < EPS);
+        assert!((arm.posterior.shape - 2.0).abs() < EPS);
+        // 1 + 120^2 / 4 + 2 * (-40 - 60)^2 / 6
+        assert!((arm.posterior.rate - (1.0 + 3600.0 + 20000.0 / 6.0)).abs() < EPS);
+        assert!(arm.sample(&mut rand::rng()).is_ok());
+    }
+
+    #[test]
+    fn update_non_finite_reward() {
+        let mut policy = make_policy();
+        let arm_id = policy.add_arm(0.0, 0);
+
+        for reward in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+            assert!(matches!(
+                policy.update(0.0, arm_id, reward, None),
+                Err(PolicyError::InvalidReward { .. })
+            ));
+        }
+        assert!(policy.update(0.0, arm_id, -1500.0, None).is_ok());
+    }
+
+    #[test]
+    fn update_batch() {
+        let mut policy = make_policy();
+        let arm_id = policy.add_arm(0.0, 0);
+
+        let updates = (0..3)
+            .map(|i| BatchUpdateElement {
+                timestamp: i as f64,
+                arm_id,
+                reward: 35.5,
+                context: None,
+            })
+            .collect::<Vec<BatchUpdateElement>>();
+
+        assert!(policy.update_batch(&updates).is_ok());
+        assert_eq!(policy.arms.get(&arm_id).unwrap().count, 3);
+    }
+
+    #[test]
+    fn no_discount() {
+        let mut arm = make_arm(None);
+        arm.apply_discount(1.0); // dt = 1s
+        assert!((arm.posterior.count - 1.0).abs() < EPS);
+        assert!((arm.posterior.shape - 1.0).abs() < EPS);
+        assert!((arm.posterior.rate - 1.0).abs() < EPS);
+    }
+
+    #[test]
+    fn discount() {
+        let mut arm = make_arm(Some(60.0));
+        arm.posterior.mean = 3.0;
+        arm.apply_discount(60.0); // dt = 60s
+        assert!((arm.posterior.mean - 3.0).abs() < EPS);
+        assert!((arm.posterior.count - 0.5).abs() < EPS);
+        assert!((arm.posterior.shape - 0.5).abs() < EPS);
+        assert!((arm.posterior.rate - 0.5).abs() < EPS);
+    }
+}
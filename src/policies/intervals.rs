@@ -0,0 +1,355 @@
+use serde::{Deserialize, Serialize};
+
+// level of the intervals reported when none is requested
+pub const DEFAULT_LEVEL: f64 = 0.95;
+
+// Range expected to hold the mean reward of an arm with a given probability
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Interval {
+    pub lower: f64,
+    pub upper: f64,
+}
+
+impl Interval {
+    // equal-tailed credible interval of a Beta(alpha, beta) posterior
+    pub fn beta(alpha: f64, beta: f64, level: f64) -> Self {
+        let tail = (1.0 - level) / 2.0;
+        Self {
+            lower: beta_quantile(alpha, beta, tail),
+            upper: beta_quantile(alpha, beta, 1.0 - tail),
+        }
+    }
+
+    // Wilson score interval of a proportion observed over count trials, which unlike the normal
+    // interval stays within [0, 1] and behaves with few trials or proportions close to 0 or 1
+    pub fn wilson(proportion: f64, count: u64, level: f64) -> Option<Self> {
+        if count == 0 || !(0.0..=1.0).contains(&proportion) {
+            return None;
+        }
+        let n = count as f64;
+        let z = normal_quantile(0.5 + level / 2.0);
+        let z2 = z * z;
+        let center = (proportion + z2 / (2.0 * n)) / (1.0 + z2 / n);
+        let half_width =
+            z / (1.0 + z2 / n) * (proportion * (1.0 - proportion) / n + z2 / (4.0 * n * n)).sqrt();
+
+        Some(Self {
+            lower: (center - half_width).max(0.0),
+            upper: (center + half_width).min(1.0),
+        })
+    }
+
+    // normal interval of a mean estimated with the given standard error
+    pub fn normal(mean: f64, std_error: f64, level: f64) -> Self {
+        let z = normal_quantile(0.5 + level / 2.0);
+        Self {
+            lower: mean - z * std_error,
+            upper: mean + z * std_error,
+        }
+    }
+
+    // interval of a location-scale Student t distribution
+    pub fn student_t(location: f64, scale: f64, dof: f64, level: f64) -> Self {
+        let t = student_t_quantile(dof, 0.5 + level / 2.0);
+        Self {
+            lower: location - t * scale,
+            upper: location + t * scale,
+        }
+    }
+}
+
+// Running mean and variance of the rewards received by an arm, using Welford's algorithm, along
+// with whether they were all 0 or 1. The rewards an arm starts from are only known by their mean,
+// which counts as binary when it lies within [0, 1], so they are left out of the variance.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RewardMoments {
+    count: u64,
+    mean: f64,
+    squares: f64,
+    is_binary: bool,
+}
+
+impl Default for RewardMoments {
+    fn default() -> Self {
+        Self::new(0.0)
+    }
+}
+
+impl RewardMoments {
+    pub fn new(initial_mean: f64) -> Self {
+        Self {
+            count: 0,
+            mean: 0.0,
+            squares: 0.0,
+            is_binary: (0.0..=1.0).contains(&initial_mean),
+        }
+    }
+
+    pub fn push(&mut self, reward: f64) {
+        self.count += 1;
+        let delta = reward - self.mean;
+        self.mean += delta / self.count as f64;
+        self.squares += delta * (reward - self.mean);
+        self.is_binary &= reward == 0.0 || reward == 1.0;
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn is_binary(&self) -> bool {
+        self.is_binary
+    }
+
+    // unbiased sample standard deviation, once two rewards were received
+    pub fn std_dev(&self) -> Option<f64> {
+        (self.count > 1).then(|| (self.squares / (self.count - 1) as f64).sqrt())
+    }
+
+    // Wilson interval of binary rewards, normal interval of the mean of count rewards otherwise
+    pub fn interval(&self, mean: f64, count: u64, level: f64) -> Option<Interval> {
+        if self.is_binary {
+            return Interval::wilson(mean, count, level);
+        }
+        let std_dev = self.std_dev().filter(|_| count > 0)?;
+        Some(Interval::normal(
+            mean,
+            std_dev / (count as f64).sqrt(),
+            level,
+        ))
+    }
+}
+
+// inverse of the standard normal cdf, using Acklam's rational approximation (relative error < 1.2e-9)
+pub fn normal_quantile(p: f64) -> f64 {
+    const A: [f64; 6] = [
+        -3.969683028665376e1,
+        2.209460984245205e2,
+        -2.759285104469687e2,
+        1.38357751867269e2,
+        -3.066479806614716e1,
+        2.506628277459239,
+    ];
+    const B: [f64; 5] = [
+        -5.447609879822406e1,
+        1.615858368580409e2,
+        -1.556989798598866e2,
+        6.680131188771972e1,
+        -1.328068155288572e1,
+    ];
+    const C: [f64; 6] = [
+        -7.784894002430293e-3,
+        -3.223964580411365e-1,
+        -2.400758277161838,
+        -2.549732539343734,
+        4.374664141464968,
+        2.938163982698783,
+    ];
+    const D: [f64; 4] = [
+        7.784695709041462e-3,
+        3.224671290700398e-1,
+        2.445134137142996,
+        3.754408661907416,
+    ];
+    const P_LOW: f64 = 0.02425;
+
+    let tail = |q: f64| {
+        (((((C[0] * q + C[1]) * q + C[2]) * q + C[3]) * q + C[4]) * q + C[5])
+            / ((((D[0] * q + D[1]) * q + D[2]) * q + D[3]) * q + 1.0)
+    };
+
+    if p < P_LOW {
+        tail((-2.0 * p.ln()).sqrt())
+    } else if p <= 1.0 - P_LOW {
+        let q = p - 0.5;
+        let r = q * q;
+        (((((A[0] * r + A[1]) * r + A[2]) * r + A[3]) * r + A[4]) * r + A[5]) * q
+            / (((((B[0] * r + B[1]) * r + B[2]) * r + B[3]) * r + B[4]) * r + 1.0)
+    } else {
+        -tail((-2.0 * (1.0 - p).ln()).sqrt())
+    }
+}
+
+// log of the gamma function, using the Lanczos approximation (g = 7, n = 9)
+fn ln_gamma(x: f64) -> f64 {
+    const G: f64 = 7.0;
+    const COEFFICIENTS: [f64; 9] = [
+        0.999_999_999_999_809_9,
+        676.520_368_121_885_1,
+        -1_259.139_216_722_402_8,
+        771.323_428_777_653_1,
+        -176.615_029_162_140_6,
+        12.507_343_278_686_905,
+        -0.138_571_095_265_720_12,
+        9.984_369_578_019_572e-6,
+        1.505_632_735_149_311_6e-7,
+    ];
+
+    if x < 0.5 {
+        // reflection formula
+        std::f64::consts::PI.ln() - (std::f64::consts::PI * x).sin().ln() - ln_gamma(1.0 - x)
+    } else {
+        let x = x - 1.0;
+        let t = x + G + 0.5;
+        let series = COEFFICIENTS
+            .iter()
+            .enumerate()
+            .skip(1)
+            .fold(COEFFICIENTS[0], |acc, (i, c)| acc + c / (x + i as f64));
+        0.5 * (2.0 * std::f64::consts::PI).ln() + (x + 0.5) * t.ln() - t + series.ln()
+    }
+}
+
+// regularized incomplete beta function I_x(a, b), evaluated with its continued fraction
+fn incomplete_beta(a: f64, b: f64, x: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    if x >= 1.0 {
+        return 1.0;
+    }
+
+    let ln_front = ln_gamma(a + b) - ln_gamma(a) - ln_gamma(b) + a * x.ln() + b * (1.0 - x).ln();
+    // the continued fraction converges quickly only on this side of the mean
+    if x < (a + 1.0) / (a + b + 2.0) {
+        ln_front.exp() * beta_continued_fraction(a, b, x) / a
+    } else {
+        1.0 - ln_front.exp() * beta_continued_fraction(b, a, 1.0 - x) / b
+    }
+}
+
+// modified Lentz's method
+fn beta_continued_fraction(a: f64, b: f64, x: f64) -> f64 {
+    const MAX_ITERATIONS: usize = 300;
+    const TOLERANCE: f64 = 1e-14;
+    const TINY: f64 = 1e-300;
+
+    let mut c = 1.0;
+    let mut d = 1.0 - (a + b) * x / (a + 1.0);
+    d = 1.0 / if d.abs() < TINY { TINY } else { d };
+    let mut fraction = d;
+
+    for m in 1..=MAX_ITERATIONS {
+        let m = m as f64;
+        for numerator in [
+            m * (b - m) * x / ((a + 2.0 * m - 1.0) * (a + 2.0 * m)),
+            -(a + m) * (a + b + m) * x / ((a + 2.0 * m) * (a + 2.0 * m + 1.0)),
+        ] {
+            d = 1.0 + numerator * d;
+            d = 1.0 / if d.abs() < TINY { TINY } else { d };
+            c = 1.0 + numerator / c;
+            c = if c.abs() < TINY { TINY } else { c };
+            fraction *= d * c;
+        }
+        if (d * c - 1.0).abs() < TOLERANCE {
+            break;
+        }
+    }
+    fraction
+}
+
+// inverse of the Beta(a, b) cdf, found by bisection since the cdf is monotonic on [0, 1]
+pub fn beta_quantile(a: f64, b: f64, p: f64) -> f64 {
+    const ITERATIONS: usize = 100;
+
+    let (mut low, mut high) = (0.0, 1.0);
+    for _ in 0..ITERATIONS {
+        let mid = (low + high) / 2.0;
+        if incomplete_beta(a, b, mid) < p {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    (low + high) / 2.0
+}
+
+// inverse of the Student t cdf with dof degrees of freedom, through its relation to the Beta cdf
+pub fn student_t_quantile(dof: f64, p: f64) -> f64 {
+    if p == 0.5 {
+        return 0.0;
+    }
+    let tail = p.min(1.0 - p);
+    let x = beta_quantile(dof / 2.0, 0.5, 2.0 * tail);
+    let t = (dof * (1.0 - x) / x).sqrt();
+    if p > 0.5 {
+        t
+    } else {
+        -t
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quantiles() {
+        assert!((normal_quantile(0.5)).abs() < 1e-9);
+        assert!((normal_quantile(0.975) - 1.959964).abs() < 1e-6);
+        assert!((normal_quantile(0.005) + 2.575829).abs() < 1e-6);
+
+        // Beta(1, 1) is uniform and Beta(2, 2) has cdf 3x^2 - 2x^3
+        assert!((beta_quantile(1.0, 1.0, 0.3) - 0.3).abs() < 1e-9);
+        assert!((beta_quantile(2.0, 2.0, 0.104) - 0.2).abs() < 1e-9);
+        assert!((beta_quantile(30.0, 70.0, 0.5) - 0.2980).abs() < 1e-3);
+
+        assert!((student_t_quantile(1.0, 0.975) - 12.7062).abs() < 1e-3);
+        assert!((student_t_quantile(10.0, 0.975) - 2.2281).abs() < 1e-3);
+        assert!((student_t_quantile(10.0, 0.025) + 2.2281).abs() < 1e-3);
+    }
+
+    #[test]
+    fn intervals() {
+        let interval = Interval::beta(31.0, 71.0, 0.95);
+        assert!(interval.lower < 30.0 / 100.0 && interval.upper > 31.0 / 102.0);
+        assert!(interval.lower > 0.2 && interval.upper < 0.4);
+
+        // reference values for 30 successes out of 100
+        let interval = Interval::wilson(0.3, 100, 0.95).unwrap();
+        assert!((interval.lower - 0.2189).abs() < 1e-3);
+        assert!((interval.upper - 0.3958).abs() < 1e-3);
+        let interval = Interval::wilson(0.0, 10, 0.95).unwrap();
+        assert_eq!(interval.lower, 0.0);
+        assert!(interval.upper > 0.0);
+        assert!(Interval::wilson(0.5, 0, 0.95).is_none());
+        assert!(Interval::wilson(2.5, 10, 0.95).is_none());
+
+        // a wider interval for a higher level
+        let interval = Interval::normal(10.0, 1.0, 0.95);
+        assert!((interval.lower - 8.04).abs() < 1e-2);
+        assert!((interval.upper - 11.96).abs() < 1e-2);
+
+        let narrow = Interval::student_t(1.0, 0.5, 10.0, 0.5);
+        let wide = Interval::student_t(1.0, 0.5, 10.0, 0.99);
+        assert!(wide.lower < narrow.lower && wide.upper > narrow.upper);
+    }
+
+    #[test]
+    fn reward_moments() {
+        let mut moments = RewardMoments::new(0.5);
+        for reward in [1.0, 0.0, 1.0] {
+            moments.push(reward);
+        }
+        assert!(moments.is_binary());
+        assert_eq!(
+            moments.interval(0.6, 10, 0.95),
+            Interval::wilson(0.6, 10, 0.95)
+        );
+
+        let mut moments = RewardMoments::new(0.0);
+        assert!(moments.interval(0.0, 0, 0.95).is_none());
+        for reward in [2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0] {
+            moments.push(reward);
+        }
+        assert!(!moments.is_binary());
+        assert!((moments.std_dev().unwrap() - 2.138).abs() < 1e-3);
+        let interval = moments.interval(5.0, 8, 0.95).unwrap();
+        assert!((interval.upper - interval.lower - 2.964).abs() < 1e-3);
+
+        // the variance is unknown until two rewards are received
+        let mut moments = RewardMoments::new(10.0);
+        moments.push(10.0);
+        assert!(moments.interval(10.0, 101, 0.95).is_none());
+    }
+}
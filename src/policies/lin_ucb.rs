@@ -0,0 +1,439 @@
+use super::linalg::{dot, Matrix};
+use super::policy::{
+    validate_context, ArmStats, BatchUpdateElement, CloneBoxedPolicy, DrawResult, Policy,
+    PolicyStats, PolicyType, RewardDomain,
+};
+use super::rng::MaybeSeededRng;
+
+use crate::errors::PolicyError;
+
+use rand::seq::IteratorRandom;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct LinUcbArm {
+    a: Matrix,
+    b: Vec<f64>,
+    reward: f64,
+    count: u64,
+    is_active: bool,
+}
+
+impl LinUcbArm {
+    // initial reward and count only seed the reported stats, the ridge regression starts from its prior
+    fn new(dim: usize, lambda: f64, initial_reward: f64, initial_count: u64) -> Self {
+        Self {
+            a: Matrix::scaled_identity(dim, lambda),
+            b: vec![0.0; dim],
+            reward: initial_reward,
+            count: initial_count,
+            is_active: true,
+        }
+    }
+
+    fn reset(
+        &mut self,
+        dim: usize,
+        lambda: f64,
+        cumulative_reward: Option<f64>,
+        count: Option<u64>,
+    ) {
+        self.a = Matrix::scaled_identity(dim, lambda);
+        self.b = vec![0.0; dim];
+        self.reward = cumulative_reward.unwrap_or_default();
+        self.count = count.unwrap_or_default();
+    }
+
+    // upper confidence bound theta^T x + alpha * sqrt(x^T A^-1 x) with theta = A^-1 b
+    fn sample(&self, context: &[f64], alpha: f64) -> Result<f64, PolicyError> {
+        let l = self.a.cholesky()?;
+        let theta = l.cholesky_solve(&self.b);
+        let z = l.cholesky_solve(context);
+
+        Ok(dot(&theta, context) + alpha * dot(context, &z).sqrt())
+    }
+
+    fn update(&mut self, reward: f64, context: &[f64]) {
+        self.a.add_outer(context);
+        self.b
+            .iter_mut()
+            .zip(context)
+            .for_each(|(b, x)| *b += reward * x);
+        self.count += 1;
+        self.reward += (reward - self.reward) / (self.count as f64);
+    }
+
+    fn stats(&self) -> ArmStats {
+        ArmStats {
+            pulls: self.count,
+            mean_reward: self.reward,
+            is_active: self.is_active,
+            is_pruned: false,
+            interval: None,
+        }
+    }
+}
+
+// Disjoint LinUCB: one ridge regression of the reward on the context per arm
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LinUcb {
+    arms: BTreeMap<usize, LinUcbArm>,
+    dim: usize,
+    alpha: f64,
+    lambda: f64,
+    rng: MaybeSeededRng,
+    next_arm_id: usize,
+}
+
+impl LinUcb {
+    pub fn new(dim: usize, alpha: f64, lambda: f64, seed: Option<u64>) -> Self {
+        Self {
+            arms: BTreeMap::new(),
+            dim,
+            alpha,
+            lambda,
+            rng: MaybeSeededRng::new(seed),
+            next_arm_id: 0,
+        }
+    }
+}
+
+impl CloneBoxedPolicy for LinUcb {
+    fn clone_box(&self) -> Box<dyn Policy + Send> {
+        Box::new(self.clone())
+    }
+}
+
+#[typetag::serde]
+impl Policy for LinUcb {
+    fn policy_type(&self) -> PolicyType {
+        PolicyType::LinUcb {
+            dim: self.dim,
+            alpha: self.alpha,
+            lambda: self.lambda,
+            seed: self.rng.seed,
+        }
+    }
+
+    fn reward_domain(&self) -> RewardDomain {
+        RewardDomain::Unbounded
+    }
+
+    fn reset(
+        &mut self,
+        arm_id: Option<usize>,
+        cumulative_reward: Option<f64>,
+        count: Option<u64>,
+    ) -> Result<(), PolicyError> {
+        if let Some(arm_id) = arm_id {
+            self.arms
+                .get_mut(&arm_id)
+                .map(|arm| arm.reset(self.dim, self.lambda, cumulative_reward, count))
+                .ok_or(PolicyError::ArmNotFound(arm_id))?;
+        } else {
+            self.arms
+                .values_mut()
+                .for_each(|arm| arm.reset(self.dim, self.lambda, None, None));
+        }
+        Ok(())
+    }
+
+    fn add_arm(&mut self, initial_reward: f64, initial_count: u64) -> usize {
+        let arm_id = self.next_arm_id;
+        self.arms.insert(
+            arm_id,
+            LinUcbArm::new(self.dim, self.lambda, initial_reward, initial_count),
+        );
+        self.next_arm_id += 1;
+
+        arm_id
+    }
+
+    fn disable_arm(&mut self, arm_id: usize) -> Result<(), PolicyError> {
+        self.arms
+            .get_mut(&arm_id)
+            .map(|arm| arm.is_active = false)
+            .ok_or(PolicyError::ArmNotFound(arm_id))
+    }
+
+    fn enable_arm(&mut self, arm_id: usize) -> Result<(), PolicyError> {
+        self.arms
+            .get_mut(&arm_id)
+            .map(|arm| arm.is_active = true)
+            .ok_or(PolicyError::ArmNotFound(arm_id))
+    }
+
+    fn delete_arm(&mut self, arm_id: usize) -> Result<(), PolicyError> {
+        self.arms
+            .remove(&arm_id)
+            .ok_or(PolicyError::ArmNotFound(arm_id))?;
+        Ok(())
+    }
+
+    fn draw_at(
+        &mut self,
+        timestamp: f64,
+        context: Option<&[f64]>,
+    ) -> Result<DrawResult, PolicyError> {
+        let context = validate_context(context, self.dim)?;
+
+        let scores = self
+            .arms
+            .iter()
+            .filter(|(_, arm)| arm.is_active)
+            .map(|(&arm_id, arm)| arm.sample(context, self.alpha).map(|score| (arm_id, score)))
+            .collect::<Result<Vec<(usize, f64)>, PolicyError>>()?;
+
+        // break ties at random, which happens when several arms have not been updated yet
+        let best_score = scores
+            .iter()
+            .map(|&(_, score)| score)
+            .fold(f64::NEG_INFINITY, f64::max);
+        let best_arm_ids = scores
+            .into_iter()
+            .filter(|&(_, score)| score >= best_score)
+            .map(|(arm_id, _)| arm_id)
+            .collect::<Vec<usize>>();
+        let arm_id = best_arm_ids
+            .iter()
+            .copied()
+            .choose(self.rng.rng_mut())
+            .ok_or(PolicyError::NoArmsAvailable)?;
+
+        Ok(DrawResult {
+            timestamp,
+            arm_id,
+            propensity: Some(1.0 / (best_arm_ids.len() as f64)),
+        })
+    }
+
+    fn update(
+        &mut self,
+        _: f64,
+        arm_id: usize,
+        reward: f64,
+        context: Option<&[f64]>,
+    ) -> Result<(), PolicyError> {
+        self.reward_domain().validate(reward)?;
+        let context = validate_context(context, self.dim)?;
+        let arm = self
+            .arms
+            .get_mut(&arm_id)
+            .ok_or(PolicyError::ArmNotFound(arm_id))?;
+
+        if !arm.is_active {
+            return Err(PolicyError::InactiveArm(arm_id));
+        }
+
+        arm.update(reward, context);
+        Ok(())
+    }
+
+    fn update_batch(&mut self, updates: &[BatchUpdateElement]) -> Result<(), PolicyError> {
+        self.reward_domain().validate_batch(updates)?;
+        updates.iter().try_for_each(
+            |BatchUpdateElement {
+                 timestamp,
+                 arm_id,
+                 reward,
+                 context,
+             }| self.update(*timestamp, *arm_id, *reward, context.as_deref()),
+        )
+    }
+
+    fn stats_at(&self, level: f64) -> PolicyStats {
+        PolicyStats {
+            level,
+            arms: self
+                .arms
+                .iter()
+                .map(|(&id, arm)| (id, arm.stats()))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIM: usize = 2;
+    const ALPHA: f64 = 0.1;
+    const LAMBDA: f64 = 1.0;
+    const DEFAULT_SEED: Option<u64> = Some(1234);
+
+    fn make_policy() -> LinUcb {
+        LinUcb::new(DIM, ALPHA, LAMBDA, DEFAULT_SEED)
+    }
+
+    #[test]
+    fn create_arm() {
+        let mut policy = make_policy();
+        assert!(policy.arms.is_empty());
+
+        let arm_id = policy.add_arm(0.0, 0);
+        assert!(policy.arms.contains_key(&arm_id))
+    }
+
+    #[test]
+    fn disable_arm() {
+        let mut policy = make_policy();
+        let arm_id = policy.add_arm(0.0, 0);
+
+        assert!(policy.disable_arm(arm_id).is_ok());
+        assert_eq!(
+            policy.arms.iter().filter(|(_, arm)| arm.is_active).count(),
+            0
+        );
+    }
+
+    #[test]
+    fn delete_arm() {
+        let mut policy = make_policy();
+        let arm_id = policy.add_arm(0.0, 0);
+        assert!(policy.delete_arm(arm_id).is_ok());
+        assert!(!policy.arms.contains_key(&arm_id));
+        assert!(policy.delete_arm(arm_id).is_err());
+    }
+
+    #[test]
+    fn draw() {
+        let mut policy = make_policy();
+        let arm_id = policy.add_arm(0.0, 0);
+        let result = policy
+            .draw(Some(&[1.0, 0.0]))
+            .ok()
+            .map(|DrawResult { arm_id, .. }| arm_id);
+        assert_eq!(result, Some(arm_id));
+    }
+
+    #[test]
+    fn draw_empty() {
+        let mut policy = make_policy();
+        assert!(policy.draw(Some(&[1.0, 0.0])).is_err());
+    }
+
+    #[test]
+    fn draw_invalid_context() {
+        let mut policy = make_policy();
+        let _ = policy.add_arm(0.0, 0);
+
+        assert!(matches!(
+            policy.draw(None),
+            Err(PolicyError::MissingContext)
+        ));
+        assert!(matches!(
+            policy.draw(Some(&[1.0])),
+            Err(PolicyError::InvalidContextDimension {
+                expected: DIM,
+                got: 1
+            })
+        ));
+    }
+
+    #[test]
+    fn reject_non_finite_context() {
+        let mut policy = make_policy();
+        let arm_id = policy.add_arm(0.0, 0);
+
+        for context in [[f64::NAN, 0.0], [f64::INFINITY, 0.0], [1e200, 0.0]] {
+            assert!(matches!(
+                policy.update(0.0, arm_id, 1.0, Some(&context)),
+                Err(PolicyError::InvalidContextValue(_))
+            ));
+            assert!(matches!(
+                policy.draw(Some(&context)),
+                Err(PolicyError::InvalidContextValue(_))
+            ));
+        }
+        // the arm is left untouched and keeps drawing
+        assert_eq!(policy.arms[&arm_id].count, 0);
+        assert!(policy.draw(Some(&[1.0, 0.0])).is_ok());
+    }
+
+    #[test]
+    fn validate_policy_type() {
+        let policy_type = |dim, lambda| PolicyType::LinUcb {
+            dim,
+            alpha: ALPHA,
+            lambda,
+            seed: None,
+        };
+        assert!(policy_type(DIM, LAMBDA).validate().is_ok());
+        assert!(policy_type(0, LAMBDA).validate().is_err());
+        assert!(policy_type(DIM, 0.0).validate().is_err());
+        assert!(policy_type(DIM, -1.0).validate().is_err());
+        assert!(policy_type(DIM, f64::NAN).validate().is_err());
+    }
+
+    #[test]
+    fn draw_best_per_context() {
+        let mut policy = make_policy();
+        let arm_1 = policy.add_arm(0.0, 0);
+        let arm_2 = policy.add_arm(0.0, 0);
+
+        // arm 1 pays off on the first feature, arm 2 on the second one
+        for _ in 0..20 {
+            policy.update(0.0, arm_1, 1.0, Some(&[1.0, 0.0])).unwrap();
+            policy.update(0.0, arm_1, 0.0, Some(&[0.0, 1.0])).unwrap();
+            policy.update(0.0, arm_2, 0.0, Some(&[1.0, 0.0])).unwrap();
+            policy.update(0.0, arm_2, 1.0, Some(&[0.0, 1.0])).unwrap();
+        }
+
+        let result = policy.draw(Some(&[1.0, 0.0])).unwrap().arm_id;
+        assert_eq!(result, arm_1);
+        let result = policy.draw(Some(&[0.0, 1.0])).unwrap().arm_id;
+        assert_eq!(result, arm_2);
+    }
+
+    #[test]
+    fn update() {
+        let mut policy = make_policy();
+        let arm_id = policy.add_arm(0.0, 0);
+
+        assert!(policy.update(0.0, arm_id, 1.0, Some(&[1.0, 2.0])).is_ok());
+        let arm = policy.arms.get(&arm_id).unwrap();
+        assert_eq!(arm.b, vec![1.0, 2.0]);
+        assert_eq!(arm.a.get(0, 1), 2.0);
+        assert_eq!(arm.a.get(1, 1), 5.0);
+        assert_eq!(arm.count, 1);
+
+        assert!(policy.update(0.0, arm_id, 1.0, None).is_err());
+    }
+
+    #[test]
+    fn update_batch() {
+        let mut policy = make_policy();
+        let arm_id = policy.add_arm(0.0, 0);
+
+        let updates = (0..3)
+            .map(|i| BatchUpdateElement {
+                timestamp: i as f64,
+                arm_id,
+                reward: 1.0,
+                context: Some(vec![1.0, 0.0]),
+            })
+            .collect::<Vec<BatchUpdateElement>>();
+
+        assert!(policy.update_batch(&updates).is_ok());
+        assert_eq!(policy.arms.get(&arm_id).unwrap().count, 3);
+    }
+
+    #[test]
+    fn persist_state() {
+        let mut policy = make_policy();
+        let arm_id = policy.add_arm(0.0, 0);
+        policy.update(0.0, arm_id, 1.0, Some(&[1.0, 2.0])).unwrap();
+
+        let boxed: Box<dyn Policy + Send> = Box::new(policy);
+        let serialized = serde_json::to_string(&boxed).unwrap();
+        let restored: Box<dyn Policy + Send> = serde_json::from_str(&serialized).unwrap();
+
+        assert!(matches!(
+            restored.policy_type(),
+            PolicyType::LinUcb { dim: DIM, .. }
+        ));
+        assert_eq!(restored.stats().arms[&arm_id].pulls, 1);
+    }
+}
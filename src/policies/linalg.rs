@@ -0,0 +1,110 @@
+use crate::errors::PolicyError;
+
+use serde::{Deserialize, Serialize};
+
+// Dense square matrix stored in row-major order, big enough for the small feature spaces of contextual policies
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(super) struct Matrix {
+    dim: usize,
+    data: Vec<f64>,
+}
+
+impl Matrix {
+    pub fn scaled_identity(dim: usize, scale: f64) -> Self {
+        let mut data = vec![0.0; dim * dim];
+        (0..dim).for_each(|i| data[i * dim + i] = scale);
+        Self { dim, data }
+    }
+
+    pub fn get(&self, row: usize, col: usize) -> f64 {
+        self.data[row * self.dim + col]
+    }
+
+    // rank-one update A += x x^T
+    pub fn add_outer(&mut self, x: &[f64]) {
+        for i in 0..self.dim {
+            for j in 0..self.dim {
+                self.data[i * self.dim + j] += x[i] * x[j];
+            }
+        }
+    }
+
+    // lower triangular L such that A = L L^T, fails if A is not positive definite
+    pub fn cholesky(&self) -> Result<Matrix, PolicyError> {
+        let n = self.dim;
+        let mut l = vec![0.0; n * n];
+        for i in 0..n {
+            for j in 0..=i {
+                let s = (0..j).map(|k| l[i * n + k] * l[j * n + k]).sum::<f64>();
+                if i == j {
+                    let d = self.get(i, i) - s;
+                    if d <= 0.0 || !d.is_finite() {
+                        return Err(PolicyError::NumericalError(
+                            "matrix is not positive definite".to_string(),
+                        ));
+                    }
+                    l[i * n + j] = d.sqrt();
+                } else {
+                    l[i * n + j] = (self.get(i, j) - s) / l[j * n + j];
+                }
+            }
+        }
+
+        Ok(Matrix { dim: n, data: l })
+    }
+
+    // solve A x = b given the cholesky factor L of A
+    pub fn cholesky_solve(&self, b: &[f64]) -> Vec<f64> {
+        self.solve_upper(&self.solve_lower(b))
+    }
+
+    // forward substitution L y = b
+    fn solve_lower(&self, b: &[f64]) -> Vec<f64> {
+        let n = self.dim;
+        let mut y = vec![0.0; n];
+        for i in 0..n {
+            let s = (0..i).map(|k| self.get(i, k) * y[k]).sum::<f64>();
+            y[i] = (b[i] - s) / self.get(i, i);
+        }
+        y
+    }
+
+    // back substitution L^T x = y
+    pub fn solve_upper(&self, y: &[f64]) -> Vec<f64> {
+        let n = self.dim;
+        let mut x = vec![0.0; n];
+        for i in (0..n).rev() {
+            let s = (i + 1..n).map(|k| self.get(k, i) * x[k]).sum::<f64>();
+            x[i] = (y[i] - s) / self.get(i, i);
+        }
+        x
+    }
+}
+
+pub(super) fn dot(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPS: f64 = 1e-9;
+
+    #[test]
+    fn cholesky_solve() {
+        let mut a = Matrix::scaled_identity(2, 1.0);
+        a.add_outer(&[1.0, 2.0]);
+        // A = [[2, 2], [2, 5]]
+        let l = a.cholesky().unwrap();
+        let x = l.cholesky_solve(&[4.0, 7.0]);
+        assert!((x[0] - 1.0).abs() < EPS);
+        assert!((x[1] - 1.0).abs() < EPS);
+    }
+
+    #[test]
+    fn cholesky_not_positive_definite() {
+        let a = Matrix::scaled_identity(2, 0.0);
+        assert!(a.cholesky().is_err());
+    }
+}
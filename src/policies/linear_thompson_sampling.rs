@@ -0,0 +1,401 @@
+use super::linalg::{dot, Matrix};
+use super::policy::{
+    estimate_propensity, validate_context, ArmStats, BatchUpdateElement, CloneBoxedPolicy,
+    DrawResult, Policy, PolicyStats, PolicyType, RewardDomain,
+};
+use super::rng::MaybeSeededRng;
+
+use crate::errors::PolicyError;
+
+use rand::Rng;
+use rand_distr::{Distribution, StandardNormal};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct LinearThompsonSamplingArm {
+    precision: Matrix,
+    b: Vec<f64>,
+    reward: f64,
+    count: u64,
+    is_active: bool,
+}
+
+impl LinearThompsonSamplingArm {
+    // initial reward and count only seed the reported stats, the posterior starts from its prior
+    fn new(dim: usize, lambda: f64, initial_reward: f64, initial_count: u64) -> Self {
+        Self {
+            precision: Matrix::scaled_identity(dim, lambda),
+            b: vec![0.0; dim],
+            reward: initial_reward,
+            count: initial_count,
+            is_active: true,
+        }
+    }
+
+    fn reset(
+        &mut self,
+        dim: usize,
+        lambda: f64,
+        cumulative_reward: Option<f64>,
+        count: Option<u64>,
+    ) {
+        self.precision = Matrix::scaled_identity(dim, lambda);
+        self.b = vec![0.0; dim];
+        self.reward = cumulative_reward.unwrap_or_default();
+        self.count = count.unwrap_or_default();
+    }
+
+    // sample theta ~ N(B^-1 b, v B^-1) and return its expected reward theta^T x
+    fn sample<R: Rng + ?Sized>(
+        &self,
+        context: &[f64],
+        noise_variance: f64,
+        rng: &mut R,
+    ) -> Result<f64, PolicyError> {
+        let l = self.precision.cholesky()?;
+        let mean = l.cholesky_solve(&self.b);
+
+        // with B = L L^T, L^-T z has covariance B^-1 when z is standard normal
+        let z = (0..mean.len())
+            .map(|_| StandardNormal.sample(rng))
+            .collect::<Vec<f64>>();
+        let scale = noise_variance.sqrt();
+        let theta = mean
+            .iter()
+            .zip(l.solve_upper(&z))
+            .map(|(m, e)| m + scale * e)
+            .collect::<Vec<f64>>();
+
+        Ok(dot(&theta, context))
+    }
+
+    fn update(&mut self, reward: f64, context: &[f64]) {
+        self.precision.add_outer(context);
+        self.b
+            .iter_mut()
+            .zip(context)
+            .for_each(|(b, x)| *b += reward * x);
+        self.count += 1;
+        self.reward += (reward - self.reward) / (self.count as f64);
+    }
+
+    fn stats(&self) -> ArmStats {
+        ArmStats {
+            pulls: self.count,
+            mean_reward: self.reward,
+            is_active: self.is_active,
+            is_pruned: false,
+            interval: None,
+        }
+    }
+}
+
+// Bayesian linear regression of the reward on the context per arm, with a Gaussian prior N(0, v / lambda I)
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LinearThompsonSampling {
+    arms: BTreeMap<usize, LinearThompsonSamplingArm>,
+    dim: usize,
+    lambda: f64,
+    noise_variance: f64,
+    #[serde(default)]
+    propensity_samples: usize,
+    rng: MaybeSeededRng,
+    next_arm_id: usize,
+}
+
+impl LinearThompsonSampling {
+    pub fn new(
+        dim: usize,
+        lambda: f64,
+        noise_variance: f64,
+        propensity_samples: usize,
+        seed: Option<u64>,
+    ) -> Self {
+        Self {
+            arms: BTreeMap::new(),
+            dim,
+            lambda,
+            noise_variance,
+            propensity_samples,
+            rng: MaybeSeededRng::new(seed),
+            next_arm_id: 0,
+        }
+    }
+
+    // sample a weight vector from each arm posterior and select the arm with the best expected reward
+    fn sample_best_arm(&mut self, context: &[f64]) -> Result<Option<usize>, PolicyError> {
+        let samples = self
+            .arms
+            .iter()
+            .filter(|(_, arm)| arm.is_active)
+            .map(|(&arm_id, arm)| {
+                arm.sample(context, self.noise_variance, self.rng.rng_mut())
+                    .map(|sample| (arm_id, sample))
+            })
+            .collect::<Result<Vec<(usize, f64)>, PolicyError>>()?;
+
+        Ok(samples
+            .into_iter()
+            .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(Ordering::Equal))
+            .map(|(arm_id, _)| arm_id))
+    }
+}
+
+impl CloneBoxedPolicy for LinearThompsonSampling {
+    fn clone_box(&self) -> Box<dyn Policy + Send> {
+        Box::new(self.clone())
+    }
+}
+
+#[typetag::serde]
+impl Policy for LinearThompsonSampling {
+    fn policy_type(&self) -> PolicyType {
+        PolicyType::LinearThompsonSampling {
+            dim: self.dim,
+            lambda: self.lambda,
+            noise_variance: self.noise_variance,
+            propensity_samples: self.propensity_samples,
+            seed: self.rng.seed,
+        }
+    }
+
+    fn reward_domain(&self) -> RewardDomain {
+        RewardDomain::Unbounded
+    }
+
+    fn reset(
+        &mut self,
+        arm_id: Option<usize>,
+        cumulative_reward: Option<f64>,
+        count: Option<u64>,
+    ) -> Result<(), PolicyError> {
+        if let Some(arm_id) = arm_id {
+            self.arms
+                .get_mut(&arm_id)
+                .map(|arm| arm.reset(self.dim, self.lambda, cumulative_reward, count))
+                .ok_or(PolicyError::ArmNotFound(arm_id))?;
+        } else {
+            self.arms
+                .values_mut()
+                .for_each(|arm| arm.reset(self.dim, self.lambda, None, None));
+        }
+        Ok(())
+    }
+
+    fn add_arm(&mut self, initial_reward: f64, initial_count: u64) -> usize {
+        let arm_id = self.next_arm_id;
+        self.arms.insert(
+            arm_id,
+            LinearThompsonSamplingArm::new(self.dim, self.lambda, initial_reward, initial_count),
+        );
+        self.next_arm_id += 1;
+
+        arm_id
+    }
+
+    fn disable_arm(&mut self, arm_id: usize) -> Result<(), PolicyError> {
+        self.arms
+            .get_mut(&arm_id)
+            .map(|arm| arm.is_active = false)
+            .ok_or(PolicyError::ArmNotFound(arm_id))
+    }
+
+    fn enable_arm(&mut self, arm_id: usize) -> Result<(), PolicyError> {
+        self.arms
+            .get_mut(&arm_id)
+            .map(|arm| arm.is_active = true)
+            .ok_or(PolicyError::ArmNotFound(arm_id))
+    }
+
+    fn delete_arm(&mut self, arm_id: usize) -> Result<(), PolicyError> {
+        self.arms
+            .remove(&arm_id)
+            .ok_or(PolicyError::ArmNotFound(arm_id))?;
+        Ok(())
+    }
+
+    fn draw_at(
+        &mut self,
+        timestamp: f64,
+        context: Option<&[f64]>,
+    ) -> Result<DrawResult, PolicyError> {
+        let context = validate_context(context, self.dim)?;
+
+        let arm_id = self
+            .sample_best_arm(context)?
+            .ok_or(PolicyError::NoArmsAvailable)?;
+        let propensity = estimate_propensity(arm_id, self.propensity_samples, || {
+            self.sample_best_arm(context).ok().flatten()
+        });
+
+        Ok(DrawResult {
+            timestamp,
+            arm_id,
+            propensity,
+        })
+    }
+
+    fn update(
+        &mut self,
+        _: f64,
+        arm_id: usize,
+        reward: f64,
+        context: Option<&[f64]>,
+    ) -> Result<(), PolicyError> {
+        self.reward_domain().validate(reward)?;
+        let context = validate_context(context, self.dim)?;
+        let arm = self
+            .arms
+            .get_mut(&arm_id)
+            .ok_or(PolicyError::ArmNotFound(arm_id))?;
+
+        if !arm.is_active {
+            return Err(PolicyError::InactiveArm(arm_id));
+        }
+
+        arm.update(reward, context);
+        Ok(())
+    }
+
+    fn update_batch(&mut self, updates: &[BatchUpdateElement]) -> Result<(), PolicyError> {
+        self.reward_domain().validate_batch(updates)?;
+        updates.iter().try_for_each(
+            |BatchUpdateElement {
+                 timestamp,
+                 arm_id,
+                 reward,
+                 context,
+             }| self.update(*timestamp, *arm_id, *reward, context.as_deref()),
+        )
+    }
+
+    fn stats_at(&self, level: f64) -> PolicyStats {
+        PolicyStats {
+            level,
+            arms: self
+                .arms
+                .iter()
+                .map(|(&id, arm)| (id, arm.stats()))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIM: usize = 2;
+    const LAMBDA: f64 = 1.0;
+    const NOISE_VARIANCE: f64 = 0.01;
+    const DEFAULT_SEED: Option<u64> = Some(1234);
+
+    fn make_policy() -> LinearThompsonSampling {
+        LinearThompsonSampling::new(DIM, LAMBDA, NOISE_VARIANCE, 0, DEFAULT_SEED)
+    }
+
+    #[test]
+    fn create_arm() {
+        let mut policy = make_policy();
+        assert!(policy.arms.is_empty());
+
+        let arm_id = policy.add_arm(0.0, 0);
+        assert!(policy.arms.contains_key(&arm_id))
+    }
+
+    #[test]
+    fn disable_arm() {
+        let mut policy = make_policy();
+        let arm_id = policy.add_arm(0.0, 0);
+
+        assert!(policy.disable_arm(arm_id).is_ok());
+        assert_eq!(
+            policy.arms.iter().filter(|(_, arm)| arm.is_active).count(),
+            0
+        );
+        assert!(matches!(
+            policy.update(0.0, arm_id, 1.0, Some(&[1.0, 0.0])),
+            Err(PolicyError::InactiveArm(_))
+        ));
+    }
+
+    #[test]
+    fn enable_arm() {
+        let mut policy = make_policy();
+        let arm_id = policy.add_arm(0.0, 0);
+
+        assert!(policy.disable_arm(arm_id).is_ok());
+        assert!(policy.enable_arm(arm_id).is_ok());
+        assert_eq!(
+            policy.arms.iter().filter(|(_, arm)| arm.is_active).count(),
+            1
+        );
+    }
+
+    #[test]
+    fn delete_arm() {
+        let mut policy = make_policy();
+        let arm_id = policy.add_arm(0.0, 0);
+        assert!(policy.delete_arm(arm_id).is_ok());
+        assert!(!policy.arms.contains_key(&arm_id));
+        assert!(policy.delete_arm(arm_id).is_err());
+    }
+
+    #[test]
+    fn draw_empty() {
+        let mut policy = make_policy();
+        assert!(policy.draw(Some(&[1.0, 0.0])).is_err());
+    }
+
+    #[test]
+    fn draw_missing_context() {
+        let mut policy = make_policy();
+        let _ = policy.add_arm(0.0, 0);
+        assert!(matches!(
+            policy.draw(None),
+            Err(PolicyError::MissingContext)
+        ));
+    }
+
+    #[test]
+    fn draw_best_per_context() {
+        let mut policy = make_policy();
+        let arm_1 = policy.add_arm(0.0, 0);
+        let arm_2 = policy.add_arm(0.0, 0);
+
+        // arm 1 pays off on the first feature, arm 2 on the second one
+        for _ in 0..50 {
+            policy.update(0.0, arm_1, 1.0, Some(&[1.0, 0.0])).unwrap();
+            policy.update(0.0, arm_1, 0.0, Some(&[0.0, 1.0])).unwrap();
+            policy.update(0.0, arm_2, 0.0, Some(&[1.0, 0.0])).unwrap();
+            policy.update(0.0, arm_2, 1.0, Some(&[0.0, 1.0])).unwrap();
+        }
+
+        let result = policy.draw(Some(&[1.0, 0.0])).unwrap().arm_id;
+        assert_eq!(result, arm_1);
+        let result = policy.draw(Some(&[0.0, 1.0])).unwrap().arm_id;
+        assert_eq!(result, arm_2);
+    }
+
+    #[test]
+    fn update_batch() {
+        let mut policy = make_policy();
+        let arm_id = policy.add_arm(0.0, 0);
+
+        let updates = (0..3)
+            .map(|i| BatchUpdateElement {
+                timestamp: i as f64,
+                arm_id,
+                reward: 1.0,
+                context: Some(vec![0.0, 1.0]),
+            })
+            .collect::<Vec<BatchUpdateElement>>();
+
+        assert!(policy.update_batch(&updates).is_ok());
+        let arm = policy.arms.get(&arm_id).unwrap();
+        assert_eq!(arm.count, 3);
+        assert_eq!(arm.b, vec![0.0, 3.0]);
+    }
+}
@@ -1,7 +1,18 @@
 pub mod epsilon_greedy;
+pub mod gaussian_thompson_sampling;
+mod intervals;
+pub mod lin_ucb;
+mod linalg;
+pub mod linear_thompson_sampling;
 mod policy;
-mod rng;
+mod posterior;
+pub mod rng;
 pub mod thompson_sampling;
 pub mod ucb;
 
-pub use policy::{BatchUpdateElement, DrawResult, Policy, PolicyStats, PolicyType};
+pub use intervals::{normal_quantile, Interval, RewardMoments, DEFAULT_LEVEL};
+pub use policy::{
+    get_timestamp, ArmStats, BatchUpdateElement, BatchUpdateReport, DrawResult, Policy,
+    PolicyStats, PolicyType, RejectedUpdate, RewardDomain,
+};
+pub use posterior::{ArmPosterior, PosteriorSampler};
@@ -1,4 +1,9 @@
 use super::epsilon_greedy::{DecayType, EpsilonGreedy};
+use super::gaussian_thompson_sampling::{GaussianThompsonSampling, NormalInverseGamma};
+use super::intervals::{Interval, RewardMoments, DEFAULT_LEVEL};
+use super::lin_ucb::LinUcb;
+use super::linear_thompson_sampling::LinearThompsonSampling;
+use super::posterior::ArmPosterior;
 use super::thompson_sampling::ThompsonSampling;
 use super::ucb::Ucb;
 
@@ -7,34 +12,119 @@ use crate::errors::PolicyError;
 use serde::{Deserialize, Serialize};
 use std::{
     collections::HashMap,
+    fmt::{self, Display, Formatter},
     time::{SystemTime, UNIX_EPOCH},
 };
 
+// the propensity is unknown for randomized policies that were not asked to estimate it
 #[derive(Debug)]
 pub struct DrawResult {
     pub timestamp: f64,
     pub arm_id: usize,
+    pub propensity: Option<f64>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BatchUpdateElement {
     pub timestamp: f64,
     pub arm_id: usize,
     pub reward: f64,
+    pub context: Option<Vec<f64>>,
 }
 
 #[derive(Debug, Serialize)]
+pub struct RejectedUpdate {
+    pub index: usize,
+    pub arm_id: usize,
+    pub reason: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct BatchUpdateReport {
+    pub applied: usize,
+    pub rejected: Vec<RejectedUpdate>,
+}
+
+// Range of rewards a policy can learn from, anything else would corrupt the arm state
+#[derive(Debug, Copy, Clone, PartialEq, Serialize)]
+pub enum RewardDomain {
+    // successes and failures, which the Beta posterior of Thompson Sampling is conjugate to
+    Binary,
+    Bounded { min: f64, max: f64 },
+    Unbounded,
+}
+
+impl RewardDomain {
+    pub fn validate(&self, reward: f64) -> Result<(), PolicyError> {
+        let is_valid = match *self {
+            Self::Binary => reward == 0.0 || reward == 1.0,
+            Self::Bounded { min, max } => (min..=max).contains(&reward),
+            Self::Unbounded => reward.is_finite(),
+        };
+
+        if is_valid {
+            Ok(())
+        } else {
+            Err(PolicyError::InvalidReward {
+                reward,
+                domain: *self,
+            })
+        }
+    }
+
+    // the mean of binary rewards is a rate rather than one of them
+    pub fn validate_mean(&self, mean_reward: f64) -> Result<(), PolicyError> {
+        match *self {
+            Self::Binary if (0.0..=1.0).contains(&mean_reward) => Ok(()),
+            Self::Binary => Err(PolicyError::InvalidReward {
+                reward: mean_reward,
+                domain: *self,
+            }),
+            _ => self.validate(mean_reward),
+        }
+    }
+
+    // check every reward of a batch before any of them is applied
+    pub fn validate_batch(&self, updates: &[BatchUpdateElement]) -> Result<(), PolicyError> {
+        updates
+            .iter()
+            .try_for_each(|update| self.validate(update.reward))
+    }
+}
+
+impl Display for RewardDomain {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Binary => write!(f, "0 or 1"),
+            Self::Bounded { min, max } => write!(f, "a value in [{min}, {max}]"),
+            Self::Unbounded => write!(f, "a finite value"),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
 pub struct ArmStats {
     pub pulls: u64,
     pub mean_reward: f64,
     pub is_active: bool,
+    // disabled by the pruning rule of its experiment rather than through the API
+    #[serde(default)]
+    pub is_pruned: bool,
+    // credible interval for bayesian policies, confidence interval otherwise, when one is known
+    pub interval: Option<Interval>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct PolicyStats {
+    #[serde(default = "default_level")]
+    pub level: f64,
     pub arms: HashMap<usize, ArmStats>,
 }
 
+fn default_level() -> f64 {
+    DEFAULT_LEVEL
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum PolicyType {
     EpsilonGreedy {
@@ -42,17 +132,88 @@ pub enum PolicyType {
         epsilon_decay: Option<DecayType>,
         seed: Option<u64>,
     },
+    // sampled policies only estimate the propensity of their draws when given a number of samples
     ThompsonSampling {
         halflife_seconds: Option<f64>,
+        #[serde(default)]
+        propensity_samples: usize,
+        seed: Option<u64>,
+    },
+    GaussianThompsonSampling {
+        prior: Option<NormalInverseGamma>,
+        halflife_seconds: Option<f64>,
+        #[serde(default)]
+        propensity_samples: usize,
         seed: Option<u64>,
     },
     Ucb {
         alpha: f64,
         seed: Option<u64>,
     },
+    LinUcb {
+        dim: usize,
+        alpha: f64,
+        lambda: f64,
+        seed: Option<u64>,
+    },
+    LinearThompsonSampling {
+        dim: usize,
+        lambda: f64,
+        noise_variance: f64,
+        #[serde(default)]
+        propensity_samples: usize,
+        seed: Option<u64>,
+    },
 }
 
 impl PolicyType {
+    pub fn with_seed(mut self, new_seed: Option<u64>) -> Self {
+        match &mut self {
+            Self::EpsilonGreedy { seed, .. }
+            | Self::ThompsonSampling { seed, .. }
+            | Self::GaussianThompsonSampling { seed, .. }
+            | Self::Ucb { seed, .. }
+            | Self::LinUcb { seed, .. }
+            | Self::LinearThompsonSampling { seed, .. } => *seed = new_seed,
+        }
+        self
+    }
+
+    pub fn seed(&self) -> Option<u64> {
+        match *self {
+            Self::EpsilonGreedy { seed, .. }
+            | Self::ThompsonSampling { seed, .. }
+            | Self::GaussianThompsonSampling { seed, .. }
+            | Self::Ucb { seed, .. }
+            | Self::LinUcb { seed, .. }
+            | Self::LinearThompsonSampling { seed, .. } => seed,
+        }
+    }
+
+    // parameters a policy could be built with but never draw from
+    pub fn validate(&self) -> Result<(), &'static str> {
+        match *self {
+            Self::LinUcb { dim, lambda, .. } | Self::LinearThompsonSampling { dim, lambda, .. } => {
+                if dim == 0 {
+                    return Err("dim must be positive");
+                }
+                if !(lambda.is_finite() && lambda > 0.0) {
+                    return Err("lambda must be positive");
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // dimension of the context vector expected by contextual policies
+    pub fn context_dim(&self) -> Option<usize> {
+        match self {
+            Self::LinUcb { dim, .. } | Self::LinearThompsonSampling { dim, .. } => Some(*dim),
+            _ => None,
+        }
+    }
+
     pub fn into_inner(self) -> Box<dyn Policy + Send> {
         match self {
             Self::EpsilonGreedy {
@@ -62,9 +223,44 @@ impl PolicyType {
             } => Box::new(EpsilonGreedy::new(epsilon, epsilon_decay, seed)),
             Self::ThompsonSampling {
                 halflife_seconds,
+                propensity_samples,
+                seed,
+            } => Box::new(ThompsonSampling::new(
+                halflife_seconds,
+                propensity_samples,
+                seed,
+            )),
+            Self::GaussianThompsonSampling {
+                prior,
+                halflife_seconds,
+                propensity_samples,
                 seed,
-            } => Box::new(ThompsonSampling::new(halflife_seconds, seed)),
+            } => Box::new(GaussianThompsonSampling::new(
+                prior,
+                halflife_seconds,
+                propensity_samples,
+                seed,
+            )),
             Self::Ucb { alpha, seed } => Box::new(Ucb::new(alpha, seed)),
+            Self::LinUcb {
+                dim,
+                alpha,
+                lambda,
+                seed,
+            } => Box::new(LinUcb::new(dim, alpha, lambda, seed)),
+            Self::LinearThompsonSampling {
+                dim,
+                lambda,
+                noise_variance,
+                propensity_samples,
+                seed,
+            } => Box::new(LinearThompsonSampling::new(
+                dim,
+                lambda,
+                noise_variance,
+                propensity_samples,
+                seed,
+            )),
         }
     }
 }
@@ -81,6 +277,7 @@ pub trait CloneBoxedPolicy {
 
 #[typetag::serde(tag = "type")]
 pub trait Policy: Send + CloneBoxedPolicy {
+    // the reward an arm is added or reset with is the mean of the count rewards it starts from
     fn reset(
         &mut self,
         arm_id: Option<usize>,
@@ -91,11 +288,81 @@ pub trait Policy: Send + CloneBoxedPolicy {
     fn disable_arm(&mut self, arm_id: usize) -> Result<(), PolicyError>;
     fn enable_arm(&mut self, arm_id: usize) -> Result<(), PolicyError>;
     fn delete_arm(&mut self, arm_id: usize) -> Result<(), PolicyError>;
-    fn draw(&mut self) -> Result<DrawResult, PolicyError>;
-    fn update(&mut self, timestamp: f64, arm_id: usize, reward: f64) -> Result<(), PolicyError>;
+    fn draw(&mut self, context: Option<&[f64]>) -> Result<DrawResult, PolicyError> {
+        self.draw_at(get_timestamp(), context)
+    }
+    // draw as of the given time, for callers replaying past events on their own clock
+    fn draw_at(
+        &mut self,
+        timestamp: f64,
+        context: Option<&[f64]>,
+    ) -> Result<DrawResult, PolicyError>;
+    fn update(
+        &mut self,
+        timestamp: f64,
+        arm_id: usize,
+        reward: f64,
+        context: Option<&[f64]>,
+    ) -> Result<(), PolicyError>;
     fn update_batch(&mut self, updates: &[BatchUpdateElement]) -> Result<(), PolicyError>;
-    fn stats(&self) -> PolicyStats;
+    // mean rewards along with their interval at the given level
+    fn stats_at(&self, level: f64) -> PolicyStats;
+    fn stats(&self) -> PolicyStats {
+        self.stats_at(DEFAULT_LEVEL)
+    }
+    // belief about the mean reward of each active arm, derived from its stats unless the policy
+    // maintains a posterior
+    fn posteriors(&self) -> HashMap<usize, ArmPosterior> {
+        self.stats()
+            .arms
+            .iter()
+            .filter(|(_, arm)| arm.is_active)
+            .map(|(&id, arm)| {
+                let posterior =
+                    ArmPosterior::from_stats(arm, self.reward_domain(), self.reward_moments(id));
+                (id, posterior)
+            })
+            .collect()
+    }
+    // moments of the rewards received by an arm, for policies that track them
+    fn reward_moments(&self, _arm_id: usize) -> Option<&RewardMoments> {
+        None
+    }
     fn policy_type(&self) -> PolicyType;
+    fn reward_domain(&self) -> RewardDomain;
+    // whether a draw changes the state of the policy, so that it has to be replayed to rebuild that
+    // state. The generator of a seeded policy is part of its state, a restored policy would otherwise
+    // repeat the draws made since its last snapshot
+    fn draw_changes_state(&self) -> bool {
+        self.policy_type().seed().is_some()
+    }
+}
+
+// Share of Monte Carlo replications in which the drawn arm comes out best, the actual draw counting
+// as one of them so that the estimate is never zero. Each replication samples every arm again, which
+// makes this as costly as samples draws and advances the generator of seeded policies, so it is only
+// done when asked for. The +1 biases the estimate towards 1 / (samples + 1) for rarely drawn arms, an
+// error which importance weights built on it inherit as they treat it as the exact propensity.
+pub fn estimate_propensity<F>(arm_id: usize, samples: usize, mut sample_best_arm: F) -> Option<f64>
+where
+    F: FnMut() -> Option<usize>,
+{
+    if samples == 0 {
+        return None;
+    }
+    let wins = (0..samples)
+        .filter(|_| sample_best_arm() == Some(arm_id))
+        .count();
+    Some((wins + 1) as f64 / (samples + 1) as f64)
+}
+
+// exponential decay weight exp(-dt * ln2 / h), after which past evidence is halved every h seconds.
+// Events older than the last one seen, like logged ones replayed on an arm added afterwards, do not
+// grow the evidence back.
+pub fn halflife_decay(halflife_seconds: Option<f64>, elapsed: f64) -> f64 {
+    halflife_seconds.map_or(1.0, |h| {
+        (-elapsed.max(0.0) * std::f64::consts::LN_2 / h).exp()
+    })
 }
 
 pub fn get_timestamp() -> f64 {
@@ -104,3 +371,19 @@ pub fn get_timestamp() -> f64 {
         .unwrap_or_default()
         .as_secs_f64()
 }
+
+// contextual policies need a feature vector matching their configured dimension. Its values are
+// squared into the design matrix of the arm, which could not be recovered from an infinite one.
+pub fn validate_context(context: Option<&[f64]>, dim: usize) -> Result<&[f64], PolicyError> {
+    let context = context.ok_or(PolicyError::MissingContext)?;
+    if context.len() != dim {
+        return Err(PolicyError::InvalidContextDimension {
+            expected: dim,
+            got: context.len(),
+        });
+    }
+    if let Some(&value) = context.iter().find(|x| !(*x * *x).is_finite()) {
+        return Err(PolicyError::InvalidContextValue(value));
+    }
+    Ok(context)
+}
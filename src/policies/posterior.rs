@@ -0,0 +1,148 @@
+use super::intervals::RewardMoments;
+use super::policy::{ArmStats, RewardDomain};
+
+use crate::errors::PolicyError;
+
+use rand::Rng;
+use rand_distr::{Beta, Distribution, Normal, StudentT};
+
+// Belief about the mean reward of an arm, which can be sampled from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArmPosterior {
+    Beta { alpha: f64, beta: f64 },
+    StudentT { location: f64, scale: f64, dof: f64 },
+    Normal { mean: f64, std_dev: f64 },
+}
+
+impl ArmPosterior {
+    // Policies that do not maintain a posterior only know the pulls and mean reward of their arms,
+    // and for some the moments of the rewards they received. Binary rewards give a Beta posterior
+    // under a uniform prior, others a Student t posterior from their observed variance, or one of
+    // unit variance until that variance is known.
+    pub fn from_stats(
+        stats: &ArmStats,
+        domain: RewardDomain,
+        moments: Option<&RewardMoments>,
+    ) -> Self {
+        let n = stats.pulls as f64;
+        if domain == RewardDomain::Binary {
+            return Self::Beta {
+                alpha: 1.0 + stats.mean_reward * n,
+                beta: 1.0 + (1.0 - stats.mean_reward) * n,
+            };
+        }
+        match moments
+            .and_then(|moments| moments.std_dev().map(|std_dev| (std_dev, moments.count())))
+        {
+            Some((std_dev, count)) => Self::StudentT {
+                location: stats.mean_reward,
+                scale: std_dev / n.sqrt(),
+                dof: (count - 1) as f64,
+            },
+            None => Self::Normal {
+                mean: stats.mean_reward,
+                std_dev: 1.0 / (n + 1.0).sqrt(),
+            },
+        }
+    }
+
+    pub fn sampler(&self) -> Result<PosteriorSampler, PolicyError> {
+        match *self {
+            Self::Beta { alpha, beta } => Beta::new(alpha, beta)
+                .map(PosteriorSampler::Beta)
+                .map_err(|e| PolicyError::SamplingError(e.to_string())),
+            Self::StudentT {
+                location,
+                scale,
+                dof,
+            } => StudentT::new(dof)
+                .map(|distribution| PosteriorSampler::StudentT {
+                    distribution,
+                    location,
+                    scale,
+                })
+                .map_err(|e| PolicyError::SamplingError(e.to_string())),
+            Self::Normal { mean, std_dev } => Normal::new(mean, std_dev)
+                .map(PosteriorSampler::Normal)
+                .map_err(|e| PolicyError::SamplingError(e.to_string())),
+        }
+    }
+}
+
+// distribution of a posterior, built once to be sampled many times
+pub enum PosteriorSampler {
+    Beta(Beta<f64>),
+    StudentT {
+        distribution: StudentT<f64>,
+        location: f64,
+        scale: f64,
+    },
+    Normal(Normal<f64>),
+}
+
+impl PosteriorSampler {
+    pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> f64 {
+        match self {
+            Self::Beta(distribution) => distribution.sample(rng),
+            Self::StudentT {
+                distribution,
+                location,
+                scale,
+            } => location + scale * distribution.sample(rng),
+            Self::Normal(distribution) => distribution.sample(rng),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_stats(pulls: u64, mean_reward: f64) -> ArmStats {
+        ArmStats {
+            pulls,
+            mean_reward,
+            is_active: true,
+            is_pruned: false,
+            interval: None,
+        }
+    }
+
+    #[test]
+    fn posterior_family_from_domain() {
+        assert_eq!(
+            ArmPosterior::from_stats(&make_stats(10, 0.3), RewardDomain::Binary, None),
+            ArmPosterior::Beta {
+                alpha: 4.0,
+                beta: 8.0
+            }
+        );
+
+        // rewards within [0, 1] are not taken as binary when the policy accepts any reward
+        let mut moments = RewardMoments::new(0.0);
+        for reward in [0.2, 0.4, 0.3, 0.5] {
+            moments.push(reward);
+        }
+        let posterior = ArmPosterior::from_stats(
+            &make_stats(4, 0.35),
+            RewardDomain::Unbounded,
+            Some(&moments),
+        );
+        let ArmPosterior::StudentT {
+            location,
+            scale,
+            dof,
+        } = posterior
+        else {
+            panic!("expected a Student t posterior, got {posterior:?}");
+        };
+        assert_eq!(location, 0.35);
+        assert!((scale - moments.std_dev().unwrap() / 2.0).abs() < 1e-12);
+        assert_eq!(dof, 3.0);
+
+        assert!(matches!(
+            ArmPosterior::from_stats(&make_stats(1, 5.0), RewardDomain::Unbounded, None),
+            ArmPosterior::Normal { mean: 5.0, .. }
+        ));
+    }
+}
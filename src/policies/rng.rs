@@ -1,27 +1,95 @@
-use rand::{rngs::SmallRng, SeedableRng};
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
 use serde::{Deserialize, Serialize};
 
+// The generator of a seeded policy is serialized along with it, so that a restored policy carries on
+// with the exact same sequence. Unseeded policies draw a fresh generator from the OS instead.
 #[derive(Debug, Serialize, Deserialize, Clone)]
-pub(super) struct MaybeSeededRng {
+#[serde(from = "SerializedRng", into = "SerializedRng")]
+pub struct MaybeSeededRng {
     pub seed: Option<u64>,
-    #[serde(skip)]
-    #[serde(default = "default_rng")]
-    rng: SmallRng,
+    rng: ChaCha8Rng,
 }
 
-fn default_rng() -> SmallRng {
-    SmallRng::from_os_rng()
+#[derive(Serialize, Deserialize)]
+struct SerializedRng {
+    seed: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    state: Option<ChaCha8Rng>,
+}
+
+impl From<SerializedRng> for MaybeSeededRng {
+    fn from(serialized: SerializedRng) -> Self {
+        match serialized {
+            SerializedRng {
+                seed: Some(seed),
+                state: Some(rng),
+            } => Self {
+                seed: Some(seed),
+                rng,
+            },
+            // states saved before generators were serialized start over from their seed
+            SerializedRng { seed, .. } => Self::new(seed),
+        }
+    }
+}
+
+impl From<MaybeSeededRng> for SerializedRng {
+    fn from(rng: MaybeSeededRng) -> Self {
+        Self {
+            seed: rng.seed,
+            state: rng.seed.map(|_| rng.rng),
+        }
+    }
 }
 
 impl MaybeSeededRng {
     pub fn new(seed: Option<u64>) -> Self {
         Self {
             seed,
-            rng: seed.map_or_else(SmallRng::from_os_rng, SmallRng::seed_from_u64),
+            rng: seed.map_or_else(ChaCha8Rng::from_os_rng, ChaCha8Rng::seed_from_u64),
         }
     }
 
-    pub fn rng_mut(&mut self) -> &mut SmallRng {
+    pub fn rng_mut(&mut self) -> &mut ChaCha8Rng {
         &mut self.rng
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn serialize_seeded_rng() {
+        let mut rng = MaybeSeededRng::new(Some(1234));
+        let _ = rng.rng_mut().random::<u64>();
+
+        let serialized = serde_json::to_string(&rng).unwrap();
+        let mut restored: MaybeSeededRng = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(restored.seed, Some(1234));
+        assert_eq!(
+            (0..10)
+                .map(|_| rng.rng_mut().random::<u64>())
+                .collect::<Vec<u64>>(),
+            (0..10)
+                .map(|_| restored.rng_mut().random::<u64>())
+                .collect::<Vec<u64>>()
+        );
+    }
+
+    #[test]
+    fn deserialize_legacy_rng() {
+        let mut restored: MaybeSeededRng = serde_json::from_str(r#"{"seed": 1234}"#).unwrap();
+        let mut rng = MaybeSeededRng::new(Some(1234));
+        assert_eq!(
+            restored.rng_mut().random::<u64>(),
+            rng.rng_mut().random::<u64>()
+        );
+
+        // unseeded generators are not worth persisting
+        let serialized = serde_json::to_string(&MaybeSeededRng::new(None)).unwrap();
+        assert_eq!(serialized, r#"{"seed":null}"#);
+    }
+}
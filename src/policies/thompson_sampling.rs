@@ -1,7 +1,9 @@
+use super::intervals::Interval;
 use super::policy::{
-    get_timestamp, ArmStats, BatchUpdateElement, CloneBoxedPolicy, DrawResult, Policy, PolicyStats,
-    PolicyType,
+    estimate_propensity, get_timestamp, halflife_decay, ArmStats, BatchUpdateElement,
+    CloneBoxedPolicy, DrawResult, Policy, PolicyStats, PolicyType, RewardDomain,
 };
+use super::posterior::ArmPosterior;
 use super::rng::MaybeSeededRng;
 
 use crate::errors::PolicyError;
@@ -11,7 +13,7 @@ use rand_distr::Beta;
 use rand_distr::Distribution;
 use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 const EPS: f64 = 1e-6;
 
@@ -28,8 +30,8 @@ struct ThompsonSamplingArm {
 impl ThompsonSamplingArm {
     fn new(initial_reward: f64, initial_count: u64, halflife_seconds: Option<f64>) -> Self {
         Self {
-            alpha: 1.0 + initial_reward,
-            beta: 1.0 + (initial_count as f64) - initial_reward,
+            alpha: 1.0 + initial_reward * initial_count as f64,
+            beta: 1.0 + (1.0 - initial_reward) * initial_count as f64,
             count: initial_count,
             halflife_seconds,
             last_ts: get_timestamp(),
@@ -38,9 +40,9 @@ impl ThompsonSamplingArm {
     }
 
     fn reset(&mut self, cumulative_reward: Option<f64>, count: Option<u64>) {
-        if let (Some(cumulative_reward), Some(count)) = (cumulative_reward, count) {
-            self.alpha = cumulative_reward + 1.0;
-            self.beta = (count as f64) - cumulative_reward + 1.0;
+        if let (Some(mean_reward), Some(count)) = (cumulative_reward, count) {
+            self.alpha = mean_reward * count as f64 + 1.0;
+            self.beta = (1.0 - mean_reward) * count as f64 + 1.0;
             self.count = count;
         } else {
             self.alpha = 1.0;
@@ -50,16 +52,8 @@ impl ThompsonSamplingArm {
         self.last_ts = get_timestamp();
     }
 
-    // apply an exponential decay d = exp(dt * ln2 / h)
-    fn decay_weight(&self, timestamp: f64) -> f64 {
-        self.halflife_seconds.map_or(1.0, |h| {
-            let dt = timestamp - self.last_ts;
-            (-dt * std::f64::consts::LN_2 / h).exp()
-        })
-    }
-
     fn apply_discount(&mut self, timestamp: f64) {
-        let decay = self.decay_weight(timestamp);
+        let decay = halflife_decay(self.halflife_seconds, timestamp - self.last_ts);
         self.alpha = (self.alpha * decay).max(EPS);
         self.beta = (self.beta * decay).max(EPS);
         self.last_ts = timestamp;
@@ -82,11 +76,13 @@ impl ThompsonSamplingArm {
         self.count += 1;
     }
 
-    fn stats(&self) -> ArmStats {
+    fn stats(&self, level: f64) -> ArmStats {
         ArmStats {
             pulls: self.count,
             mean_reward: self.alpha / (self.alpha + self.beta),
             is_active: self.is_active,
+            is_pruned: false,
+            interval: Some(Interval::beta(self.alpha, self.beta, level)),
         }
     }
 }
@@ -94,21 +90,41 @@ impl ThompsonSamplingArm {
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct ThompsonSampling {
     halflife_seconds: Option<f64>,
-    arms: HashMap<usize, ThompsonSamplingArm>,
+    #[serde(default)]
+    propensity_samples: usize,
+    arms: BTreeMap<usize, ThompsonSamplingArm>,
     rng: MaybeSeededRng,
     next_arm_id: usize,
 }
 
 impl ThompsonSampling {
     // Decayed Thomson Sampling using halflife, after which past evidence is halved
-    pub fn new(halflife_seconds: Option<f64>, seed: Option<u64>) -> Self {
+    pub fn new(
+        halflife_seconds: Option<f64>,
+        propensity_samples: usize,
+        seed: Option<u64>,
+    ) -> Self {
         Self {
             halflife_seconds,
-            arms: HashMap::new(),
+            propensity_samples,
+            arms: BTreeMap::new(),
             rng: MaybeSeededRng::new(seed),
             next_arm_id: 0,
         }
     }
+
+    // sample from the beta distribution for each arm and select the arm with the best statistic
+    fn sample_best_arm(&mut self) -> Option<usize> {
+        self.arms
+            .iter()
+            .filter(|(_, arm)| arm.is_active)
+            .filter_map(|(arm_id, arm)| {
+                arm.sample(self.rng.rng_mut())
+                    .map_or(None, |sample| Some((arm_id, sample)))
+            })
+            .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(Ordering::Equal))
+            .map(|(&arm_id, _)| arm_id)
+    }
 }
 
 impl CloneBoxedPolicy for ThompsonSampling {
@@ -122,10 +138,20 @@ impl Policy for ThompsonSampling {
     fn policy_type(&self) -> PolicyType {
         PolicyType::ThompsonSampling {
             halflife_seconds: self.halflife_seconds,
+            propensity_samples: self.propensity_samples,
             seed: self.rng.seed,
         }
     }
 
+    fn reward_domain(&self) -> RewardDomain {
+        RewardDomain::Binary
+    }
+
+    // draws discount the evidence of every arm and advance a seeded generator
+    fn draw_changes_state(&self) -> bool {
+        self.halflife_seconds.is_some() || self.rng.seed.is_some()
+    }
+
     fn reset(
         &mut self,
         arm_id: Option<usize>,
@@ -175,32 +201,32 @@ impl Policy for ThompsonSampling {
         Ok(())
     }
 
-    fn draw(&mut self) -> Result<DrawResult, PolicyError> {
-        let timestamp = get_timestamp();
-
+    fn draw_at(&mut self, timestamp: f64, _: Option<&[f64]>) -> Result<DrawResult, PolicyError> {
         // apply discount to all arms
         self.arms
             .values_mut()
             .filter(|arm| arm.is_active)
             .for_each(|arm| arm.apply_discount(timestamp));
 
-        // sample from the beta distribution for each arm and select the arm with the best statistic
-        let arm_id = self
-            .arms
-            .iter()
-            .filter(|(_, arm)| arm.is_active)
-            .filter_map(|(arm_id, arm)| {
-                arm.sample(self.rng.rng_mut())
-                    .map_or(None, |sample| Some((arm_id, sample)))
-            })
-            .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(Ordering::Equal))
-            .map(|(&arm_id, _)| arm_id)
-            .ok_or(PolicyError::NoArmsAvailable)?;
+        let arm_id = self.sample_best_arm().ok_or(PolicyError::NoArmsAvailable)?;
+        let propensity =
+            estimate_propensity(arm_id, self.propensity_samples, || self.sample_best_arm());
 
-        Ok(DrawResult { timestamp, arm_id })
+        Ok(DrawResult {
+            timestamp,
+            arm_id,
+            propensity,
+        })
     }
 
-    fn update(&mut self, timestamp: f64, arm_id: usize, reward: f64) -> Result<(), PolicyError> {
+    fn update(
+        &mut self,
+        timestamp: f64,
+        arm_id: usize,
+        reward: f64,
+        _: Option<&[f64]>,
+    ) -> Result<(), PolicyError> {
+        self.reward_domain().validate(reward)?;
         // update the arm statistics
         let arm = self
             .arms
@@ -216,34 +242,54 @@ impl Policy for ThompsonSampling {
     }
 
     fn update_batch(&mut self, updates: &[BatchUpdateElement]) -> Result<(), PolicyError> {
+        self.reward_domain().validate_batch(updates)?;
         updates.iter().try_for_each(
-            |&BatchUpdateElement {
+            |BatchUpdateElement {
                  timestamp,
                  arm_id,
                  reward,
-             }| self.update(timestamp, arm_id, reward),
+                 context,
+             }| self.update(*timestamp, *arm_id, *reward, context.as_deref()),
         )
     }
 
-    fn stats(&self) -> PolicyStats {
+    fn stats_at(&self, level: f64) -> PolicyStats {
         PolicyStats {
+            level,
             arms: self
                 .arms
                 .iter()
-                .map(|(&id, arm)| (id, arm.stats()))
+                .map(|(&id, arm)| (id, arm.stats(level)))
                 .collect(),
         }
     }
+
+    fn posteriors(&self) -> HashMap<usize, ArmPosterior> {
+        self.arms
+            .iter()
+            .filter(|(_, arm)| arm.is_active)
+            .map(|(&id, arm)| {
+                (
+                    id,
+                    ArmPosterior::Beta {
+                        alpha: arm.alpha,
+                        beta: arm.beta,
+                    },
+                )
+            })
+            .collect()
+    }
 }
 
 #[cfg(test)]
+#[allow(clippy::len_zero, clippy::option_map_unit_fn)]
 mod tests {
     use super::*;
 
     const DEFAULT_SEED: Option<u64> = Some(1234);
 
     fn make_policy() -> ThompsonSampling {
-        ThompsonSampling::new(None, DEFAULT_SEED)
+        ThompsonSampling::new(None, 0, DEFAULT_SEED)
     }
 
     #[test]
@@ -297,7 +343,10 @@ mod tests {
     fn draw() {
         let mut policy = make_policy();
         let arm_id = policy.add_arm(0.0, 0);
-        let result = policy.draw().ok().map(|DrawResult { arm_id, .. }| arm_id);
+        let result = policy
+            .draw(None)
+            .ok()
+            .map(|DrawResult { arm_id, .. }| arm_id);
         assert_eq!(result, Some(arm_id));
     }
 
@@ -308,14 +357,45 @@ mod tests {
         let _ = policy.add_arm(0.0, 0);
 
         policy.arms.get_mut(&arm_1).map(|arm| arm.alpha += 100.0);
-        let result = policy.draw().ok().map(|DrawResult { arm_id, .. }| arm_id);
+        let result = policy
+            .draw(None)
+            .ok()
+            .map(|DrawResult { arm_id, .. }| arm_id);
         assert_eq!(result, Some(arm_1));
     }
 
+    #[test]
+    fn draw_propensity() {
+        let mut policy = ThompsonSampling::new(None, 1000, DEFAULT_SEED);
+        let arm_1 = policy.add_arm(0.0, 0);
+        let _ = policy.add_arm(0.0, 0);
+
+        if let Some(arm) = policy.arms.get_mut(&arm_1) {
+            arm.alpha += 100.0;
+        }
+        let DrawResult {
+            arm_id, propensity, ..
+        } = policy.draw(None).unwrap();
+        assert_eq!(arm_id, arm_1);
+        assert!(propensity.is_some_and(|propensity| propensity > 0.99 && propensity <= 1.0));
+
+        // the propensity is only estimated when asked for
+        let mut policy = make_policy();
+        policy.add_arm(0.0, 0);
+        assert!(policy.draw(None).unwrap().propensity.is_none());
+    }
+
+    #[test]
+    fn draw_changes_state_with_halflife_or_seed() {
+        assert!(!ThompsonSampling::new(None, 0, None).draw_changes_state());
+        assert!(ThompsonSampling::new(Some(60.0), 0, None).draw_changes_state());
+        assert!(make_policy().draw_changes_state());
+    }
+
     #[test]
     fn draw_empty() {
         let mut policy = make_policy();
-        assert!(policy.draw().is_err());
+        assert!(policy.draw(None).is_err());
     }
 
     #[test]
@@ -326,9 +406,9 @@ mod tests {
 
         let DrawResult {
             timestamp, arm_id, ..
-        } = policy.draw().unwrap();
+        } = policy.draw(None).unwrap();
 
-        assert!(policy.update(timestamp + 1.0, arm_id, 1.0).is_ok());
+        assert!(policy.update(timestamp + 1.0, arm_id, 1.0, None).is_ok());
         println!("{:?}", policy.arms);
         assert_eq!(policy.arms.get(&arm_id).map(|arm| arm.alpha), Some(2.0));
         assert_eq!(policy.arms.get(&arm_id).map(|arm| arm.beta), Some(1.0));
@@ -341,7 +421,7 @@ mod tests {
         let _ = policy.add_arm(0.0, 0);
 
         let draws = (0..3)
-            .map(|_| policy.draw().unwrap())
+            .map(|_| policy.draw(None).unwrap())
             .collect::<Vec<DrawResult>>();
         let updates = draws
             .iter()
@@ -349,12 +429,50 @@ mod tests {
                 timestamp: draw.timestamp + 1.0,
                 arm_id: draw.arm_id,
                 reward: 1.0,
+                context: None,
             })
             .collect::<Vec<BatchUpdateElement>>();
 
         assert!(policy.update_batch(&updates).is_ok());
     }
 
+    #[test]
+    fn update_invalid_reward() {
+        let mut policy = make_policy();
+        let arm_id = policy.add_arm(0.0, 0);
+
+        for reward in [0.5, 2.0, -0.5, f64::NAN, f64::INFINITY] {
+            assert!(matches!(
+                policy.update(0.0, arm_id, reward, None),
+                Err(PolicyError::InvalidReward { .. })
+            ));
+        }
+        assert_eq!(policy.arms.get(&arm_id).map(|arm| arm.count), Some(0));
+    }
+
+    #[test]
+    fn update_batch_invalid_reward() {
+        let mut policy = make_policy();
+        let arm_id = policy.add_arm(0.0, 0);
+
+        let updates = [1.0, 0.0, 5.0]
+            .into_iter()
+            .map(|reward| BatchUpdateElement {
+                timestamp: 0.0,
+                arm_id,
+                reward,
+                context: None,
+            })
+            .collect::<Vec<BatchUpdateElement>>();
+
+        assert!(matches!(
+            policy.update_batch(&updates),
+            Err(PolicyError::InvalidReward { .. })
+        ));
+        // nothing was applied, including the valid elements before the invalid one
+        assert_eq!(policy.arms.get(&arm_id).map(|arm| arm.count), Some(0));
+    }
+
     #[test]
     fn no_discount() {
         let mut arm = ThompsonSamplingArm {
@@ -399,4 +517,36 @@ mod tests {
         assert!((arm.alpha - 0.5).abs() < EPS);
         assert!((arm.beta - 0.5).abs() < EPS);
     }
+
+    #[test]
+    fn initial_reward_is_mean() {
+        let mut policy = make_policy();
+        let arm_id = policy.add_arm(0.3, 100);
+        assert!((policy.arms[&arm_id].alpha - 31.0).abs() < EPS);
+        assert!((policy.arms[&arm_id].beta - 71.0).abs() < EPS);
+
+        policy.reset(Some(arm_id), Some(0.5), Some(10)).unwrap();
+        assert!((policy.arms[&arm_id].alpha - 6.0).abs() < EPS);
+        assert!((policy.arms[&arm_id].beta - 6.0).abs() < EPS);
+
+        // rewards are binary, their mean is a rate
+        assert!(policy.reward_domain().validate(0.3).is_err());
+        assert!(policy.reward_domain().validate_mean(0.3).is_ok());
+        assert!(policy.reward_domain().validate_mean(1.5).is_err());
+    }
+
+    #[test]
+    fn stats_interval() {
+        let mut policy = make_policy();
+        let arm_id = policy.add_arm(0.3, 100);
+
+        let stats = policy.stats_at(0.95);
+        let arm = &stats.arms[&arm_id];
+        let interval = arm.interval.unwrap();
+        assert!(interval.lower < arm.mean_reward && arm.mean_reward < interval.upper);
+
+        // the interval narrows as the requested level decreases
+        let narrow = policy.stats_at(0.5).arms[&arm_id].interval.unwrap();
+        assert!(narrow.lower > interval.lower && narrow.upper < interval.upper);
+    }
 }
@@ -1,6 +1,7 @@
+use super::intervals::RewardMoments;
 use super::policy::{
-    get_timestamp, ArmStats, BatchUpdateElement, CloneBoxedPolicy, DrawResult, Policy, PolicyStats,
-    PolicyType,
+    ArmStats, BatchUpdateElement, CloneBoxedPolicy, DrawResult, Policy, PolicyStats, PolicyType,
+    RewardDomain,
 };
 use super::rng::MaybeSeededRng;
 
@@ -9,13 +10,15 @@ use crate::errors::PolicyError;
 use rand::seq::IteratorRandom;
 use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 struct UcbArm {
     reward: f64,
     count: u64,
     is_active: bool,
+    #[serde(default)]
+    moments: RewardMoments,
 }
 
 impl UcbArm {
@@ -24,6 +27,7 @@ impl UcbArm {
             reward: initial_reward,
             count: initial_count,
             is_active: true,
+            moments: RewardMoments::new(initial_reward),
         }
     }
 
@@ -35,25 +39,29 @@ impl UcbArm {
     fn reset(&mut self, cumulative_reward: Option<f64>, count: Option<u64>) {
         self.reward = cumulative_reward.unwrap_or_default();
         self.count = count.unwrap_or_default();
+        self.moments = RewardMoments::new(self.reward);
     }
 
     fn update(&mut self, reward: f64, _: f64) {
         self.count += 1;
         self.reward += (reward - self.reward) / (self.count as f64);
+        self.moments.push(reward);
     }
 
-    fn stats(&self) -> ArmStats {
+    fn stats(&self, level: f64) -> ArmStats {
         ArmStats {
             pulls: self.count,
             mean_reward: self.reward,
             is_active: self.is_active,
+            is_pruned: false,
+            interval: self.moments.interval(self.reward, self.count, level),
         }
     }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Ucb {
-    arms: HashMap<usize, UcbArm>,
+    arms: BTreeMap<usize, UcbArm>,
     alpha: f64,
     rng: MaybeSeededRng,
     next_arm_id: usize,
@@ -62,7 +70,7 @@ pub struct Ucb {
 impl Ucb {
     pub fn new(alpha: f64, seed: Option<u64>) -> Self {
         Self {
-            arms: HashMap::new(),
+            arms: BTreeMap::new(),
             alpha,
             rng: MaybeSeededRng::new(seed),
             next_arm_id: 0,
@@ -93,6 +101,14 @@ impl Policy for Ucb {
         }
     }
 
+    fn reward_domain(&self) -> RewardDomain {
+        RewardDomain::Unbounded
+    }
+
+    fn reward_moments(&self, arm_id: usize) -> Option<&RewardMoments> {
+        self.arms.get(&arm_id).map(|arm| &arm.moments)
+    }
+
     fn reset(
         &mut self,
         arm_id: Option<usize>,
@@ -140,32 +156,44 @@ impl Policy for Ucb {
         Ok(())
     }
 
-    fn draw(&mut self) -> Result<DrawResult, PolicyError> {
-        let timestamp = get_timestamp();
-
+    fn draw_at(&mut self, timestamp: f64, _: Option<&[f64]>) -> Result<DrawResult, PolicyError> {
         // sample random arms while no feedback has been observed for every one, and then the one with the best statistic
-        let arm_id = if let Some(arm_id) = self
+        let unexplored_arm_ids = self
             .arms
             .iter()
             .filter(|(_, arm)| arm.is_active && (arm.count == 0))
             .map(|(&arm_id, _)| arm_id)
-            .choose(&mut self.rng.rng_mut())
-        {
-            Ok(arm_id)
-        } else {
-            self.arms
-                .iter()
-                .filter(|(_, arm)| arm.is_active)
-                .map(|(arm_id, arm)| (arm_id, arm.sample(self.alpha, self.total_count())))
-                .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(Ordering::Equal))
-                .map(|(&arm_id, _)| arm_id)
-                .ok_or(PolicyError::NoArmsAvailable)
-        }?;
-
-        Ok(DrawResult { timestamp, arm_id })
+            .collect::<Vec<usize>>();
+        let (arm_id, propensity) =
+            if let Some(&arm_id) = unexplored_arm_ids.iter().choose(&mut self.rng.rng_mut()) {
+                (arm_id, 1.0 / (unexplored_arm_ids.len() as f64))
+            } else {
+                let arm_id = self
+                    .arms
+                    .iter()
+                    .filter(|(_, arm)| arm.is_active)
+                    .map(|(arm_id, arm)| (arm_id, arm.sample(self.alpha, self.total_count())))
+                    .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(Ordering::Equal))
+                    .map(|(&arm_id, _)| arm_id)
+                    .ok_or(PolicyError::NoArmsAvailable)?;
+                (arm_id, 1.0)
+            };
+
+        Ok(DrawResult {
+            timestamp,
+            arm_id,
+            propensity: Some(propensity),
+        })
     }
 
-    fn update(&mut self, timestamp: f64, arm_id: usize, reward: f64) -> Result<(), PolicyError> {
+    fn update(
+        &mut self,
+        timestamp: f64,
+        arm_id: usize,
+        reward: f64,
+        _: Option<&[f64]>,
+    ) -> Result<(), PolicyError> {
+        self.reward_domain().validate(reward)?;
         // update the arm statistics
         let arm = self
             .arms
@@ -181,27 +209,31 @@ impl Policy for Ucb {
     }
 
     fn update_batch(&mut self, updates: &[BatchUpdateElement]) -> Result<(), PolicyError> {
+        self.reward_domain().validate_batch(updates)?;
         updates.iter().try_for_each(
-            |&BatchUpdateElement {
+            |BatchUpdateElement {
                  timestamp,
                  arm_id,
                  reward,
-             }| self.update(timestamp, arm_id, reward),
+                 context,
+             }| self.update(*timestamp, *arm_id, *reward, context.as_deref()),
         )
     }
 
-    fn stats(&self) -> PolicyStats {
+    fn stats_at(&self, level: f64) -> PolicyStats {
         PolicyStats {
+            level,
             arms: self
                 .arms
                 .iter()
-                .map(|(&id, arm)| (id, arm.stats()))
+                .map(|(&id, arm)| (id, arm.stats(level)))
                 .collect(),
         }
     }
 }
 
 #[cfg(test)]
+#[allow(clippy::len_zero, clippy::option_map_unit_fn)]
 mod tests {
     use super::*;
 
@@ -263,7 +295,10 @@ mod tests {
     fn draw() {
         let mut policy = make_policy();
         let arm_id = policy.add_arm(0.0, 0);
-        let result = policy.draw().ok().map(|DrawResult { arm_id, .. }| arm_id);
+        let result = policy
+            .draw(None)
+            .ok()
+            .map(|DrawResult { arm_id, .. }| arm_id);
         assert_eq!(result, Some(arm_id));
     }
 
@@ -282,14 +317,32 @@ mod tests {
             arm.count += 1
         });
 
-        let result = policy.draw().ok().map(|DrawResult { arm_id, .. }| arm_id);
+        let result = policy
+            .draw(None)
+            .ok()
+            .map(|DrawResult { arm_id, .. }| arm_id);
         assert_eq!(result, Some(arm_1));
     }
 
+    #[test]
+    fn draw_propensity() {
+        let mut policy = make_policy();
+        let arm_1 = policy.add_arm(0.0, 0);
+        let _ = policy.add_arm(0.0, 0);
+
+        // both arms are unexplored and equally likely
+        assert_eq!(policy.draw(None).unwrap().propensity, Some(0.5));
+
+        if let Some(arm) = policy.arms.get_mut(&arm_1) {
+            arm.count += 1;
+        }
+        assert_eq!(policy.draw(None).unwrap().propensity, Some(1.0));
+    }
+
     #[test]
     fn draw_empty() {
         let mut policy = make_policy();
-        assert!(policy.draw().is_err());
+        assert!(policy.draw(None).is_err());
     }
 
     #[test]
@@ -300,9 +353,9 @@ mod tests {
 
         let DrawResult {
             timestamp, arm_id, ..
-        } = policy.draw().unwrap();
+        } = policy.draw(None).unwrap();
 
-        assert!(policy.update(timestamp + 1.0, arm_id, 1.0).is_ok());
+        assert!(policy.update(timestamp + 1.0, arm_id, 1.0, None).is_ok());
         assert_eq!(policy.arms.get(&arm_id).map(|arm| arm.reward), Some(1.0));
     }
 
@@ -313,7 +366,7 @@ mod tests {
         let _ = policy.add_arm(0.0, 0);
 
         let draws = (0..3)
-            .map(|_| policy.draw().unwrap())
+            .map(|_| policy.draw(None).unwrap())
             .collect::<Vec<DrawResult>>();
         let updates = draws
             .iter()
@@ -321,6 +374,7 @@ mod tests {
                 timestamp: draw.timestamp + 1.0,
                 arm_id: draw.arm_id,
                 reward: 1.0,
+                context: None,
             })
             .collect::<Vec<BatchUpdateElement>>();
 
@@ -1,15 +1,28 @@
 use crate::actors::experiment::{
-    AddArm, Delete, DeleteArm, DisableArm, Draw, EnableArm, Experiment, GetStats, Ping, Reset,
-    Update, UpdateBatch,
+    AddArm, Audited, ChangeStatus, Delete, DeleteArm, DisableArm, Draw, EnableArm, Experiment,
+    Flush, GetMetadata, GetPosteriors, GetStats, Ping, RedeemTicket, Reset, RestoreSnapshot,
+    SetPruningRule, SetStoppingRule, TicketedDraw, Transition, Update, UpdateBatch,
 };
-use crate::actors::state_store::{LoadAllStates, StateStore};
+use crate::actors::state_store::{ListQuarantinedStates, ListSnapshots, LoadAllStates, StateStore};
 use crate::config::ExperimentConfig;
-use crate::errors::{RepositoryError, ServiceError};
-use crate::policies::{BatchUpdateElement, DrawResult, Policy, PolicyStats, PolicyType};
+use crate::errors::{PersistenceError, RepositoryError, ServiceError};
+use crate::evaluation::{LoggedInteraction, PruningRule, StoppingRule};
+use crate::policies::{
+    ArmPosterior, BatchUpdateElement, BatchUpdateReport, Policy, PolicyStats, PolicyType,
+    DEFAULT_LEVEL,
+};
+use crate::storage::{
+    event_log_path, replay_tail, ExperimentMetadata, ExperimentStatus, ExportFormat,
+    InteractionExport, InteractionRecord, InteractionRecords, QuarantinedState, Schedule,
+    SnapshotInfo, StoredState,
+};
 
 use actix::{prelude::*, Supervisor};
+use actix_web::web;
 use std::collections::HashMap;
-use tracing::info;
+use std::future::Future;
+use std::path::PathBuf;
+use tracing::{info, warn};
 use uuid::Uuid;
 
 struct RepositoryElement {
@@ -38,15 +51,51 @@ impl Repository {
             .await
             .map(|experiments| {
                 info!(num_experiments = %experiments.len(), "Loaded experiments");
-                for (experiment_id, policy) in experiments {
-                    self.create_experiment(Some(experiment_id), policy);
+                for (experiment_id, mut state) in experiments {
+                    // bring the snapshot up to date with the changes logged after it was taken
+                    let path = event_log_path(&self.experiment_config.event_log_dir, experiment_id);
+                    match replay_tail(state.policy.as_mut(), &path, state.event_seq) {
+                        Ok(replayed) => info!(id = %experiment_id, replayed, "Replayed event log"),
+                        Err(err) => {
+                            warn!(error = %err, id = %experiment_id, "Failed to replay event log")
+                        }
+                    }
+                    self.start_experiment(experiment_id, state);
                     info!(id = %experiment_id, "Loaded experiment");
                 }
             })
-            .map_err(|err| ServiceError::Mailbox {
-                actor: "StateStore",
-                source: err,
-            })
+            .map_err(|err| ServiceError::mailbox("StateStore", err))
+    }
+
+    pub async fn list_quarantined_states(&self) -> Result<Vec<QuarantinedState>, ServiceError> {
+        self.state_store
+            .send(ListQuarantinedStates)
+            .await
+            .map_err(|err| ServiceError::mailbox("StateStore", err))?
+            .map_err(ServiceError::from)
+    }
+
+    pub async fn list_experiment_snapshots(
+        &self,
+        experiment_id: Uuid,
+    ) -> Result<Vec<SnapshotInfo>, ServiceError> {
+        self.get_experiment_address(experiment_id)?;
+        self.state_store
+            .send(ListSnapshots { experiment_id })
+            .await
+            .map_err(|err| ServiceError::mailbox("StateStore", err))?
+            .map_err(ServiceError::from)
+    }
+
+    pub async fn restore_experiment_snapshot(
+        &self,
+        experiment_id: Uuid,
+        snapshot_id: u64,
+    ) -> Result<Audited<()>, ServiceError> {
+        self.send_to_experiment(experiment_id, RestoreSnapshot { snapshot_id })
+            .await?
+            .map_err(RepositoryError::from)
+            .map_err(ServiceError::from)
     }
 
     fn get_experiment_address(
@@ -72,10 +121,7 @@ impl Repository {
         self.get_experiment_address(experiment_id)?
             .send(message)
             .await
-            .map_err(|err| ServiceError::Mailbox {
-                actor: "Experiment",
-                source: err,
-            })
+            .map_err(|err| ServiceError::mailbox("Experiment", err))
     }
 
     pub async fn ping_experiment(&self, experiment_id: Uuid) -> Result<(), ServiceError> {
@@ -88,29 +134,131 @@ impl Repository {
             .map(|(id, el)| (id, &el.policy_type))
     }
 
-    pub fn clear(&mut self) {
-        self.experiments
-            .values()
-            .for_each(|experiment| experiment.address.do_send(Delete));
-        self.experiments.clear();
+    // experiments are removed right away, their stats before deletion are collected by awaiting
+    // the returned future, which does not need the repository any more
+    pub fn clear(&mut self) -> impl Future<Output = Vec<(Uuid, Option<PolicyStats>)>> {
+        let requests = self
+            .experiments
+            .drain()
+            .map(|(experiment_id, experiment)| (experiment_id, experiment.address.send(Delete)))
+            .collect::<Vec<_>>();
+
+        async move {
+            let mut before = Vec::with_capacity(requests.len());
+            for (experiment_id, request) in requests {
+                before.push((experiment_id, request.await.ok().flatten()));
+            }
+            before
+        }
+    }
+
+    // ask every experiment to store its current state, resolving once all writes are confirmed
+    pub async fn flush_experiments(&self) -> Vec<(Uuid, ServiceError)> {
+        // messages are queued right away, so that experiments flush concurrently
+        let requests = self
+            .experiments
+            .iter()
+            .map(|(experiment_id, experiment)| (*experiment_id, experiment.address.send(Flush)))
+            .collect::<Vec<_>>();
+
+        let mut failures = Vec::new();
+        for (experiment_id, request) in requests {
+            let result = request
+                .await
+                .map_err(|err| ServiceError::mailbox("Experiment", err))
+                .and_then(|result| {
+                    result
+                        .map_err(RepositoryError::from)
+                        .map_err(ServiceError::from)
+                });
+            if let Err(err) = result {
+                failures.push((experiment_id, err));
+            }
+        }
+        failures
     }
 
+    // rewards are only accepted through draw tickets unless raw updates are allowed
     pub fn create_experiment(
         &mut self,
         experiment_id: Option<Uuid>,
         policy: Box<dyn Policy + Send>,
+        raw_updates: bool,
+    ) -> Uuid {
+        self.create_experiment_as(
+            experiment_id,
+            policy,
+            ExperimentMetadata::new(ExperimentStatus::Running).with_raw_updates(raw_updates),
+        )
+    }
+
+    // a draft experiment can have its arms set up, but serves no draw until it is started, from
+    // which point it follows its schedule
+    pub fn create_draft_experiment(
+        &mut self,
+        policy: Box<dyn Policy + Send>,
+        schedule: Schedule,
+        raw_updates: bool,
+    ) -> Uuid {
+        self.create_experiment_as(
+            None,
+            policy,
+            ExperimentMetadata::new(ExperimentStatus::Draft)
+                .with_schedule(schedule)
+                .with_raw_updates(raw_updates),
+        )
+    }
+
+    // the experiment starts and ends by itself at the times set by its schedule
+    pub fn create_scheduled_experiment(
+        &mut self,
+        policy: Box<dyn Policy + Send>,
+        schedule: Schedule,
+        raw_updates: bool,
+    ) -> Uuid {
+        self.create_experiment_as(
+            None,
+            policy,
+            ExperimentMetadata::new(ExperimentStatus::Running)
+                .with_schedule(schedule)
+                .with_raw_updates(raw_updates),
+        )
+    }
+
+    fn create_experiment_as(
+        &mut self,
+        experiment_id: Option<Uuid>,
+        policy: Box<dyn Policy + Send>,
+        metadata: ExperimentMetadata,
     ) -> Uuid {
         let experiment_id = experiment_id.unwrap_or_else(Uuid::new_v4);
-        let policy_type = policy.policy_type();
+        self.start_experiment(
+            experiment_id,
+            StoredState {
+                policy,
+                event_seq: 0,
+                metadata,
+            },
+        );
+        experiment_id
+    }
+
+    fn start_experiment(&mut self, experiment_id: Uuid, state: StoredState) {
+        let policy_type = state.policy.policy_type();
         // use a Supervisor to handle auto restart of crashed experiments
         let address = Supervisor::start({
             let state_store = self.state_store.clone();
-            let save_every = self.experiment_config.save_every;
-            let mut first_policy = Some(policy);
+            let experiment_config = self.experiment_config.clone();
+            let mut first_state = Some(state);
 
             move |_| {
-                let policy = first_policy.take(); // Some on first start, None on supervisor restarts
-                Experiment::new(experiment_id, policy, state_store.clone(), save_every)
+                let state = first_state.take(); // Some on first start, None on supervisor restarts
+                Experiment::new(
+                    experiment_id,
+                    state,
+                    state_store.clone(),
+                    experiment_config.clone(),
+                )
             }
         });
 
@@ -121,14 +269,16 @@ impl Repository {
                 policy_type,
             },
         );
-
-        experiment_id
     }
 
-    pub fn delete_experiment(&mut self, experiment_id: Uuid) -> Result<(), ServiceError> {
-        self.get_experiment_address(experiment_id)?.do_send(Delete);
+    // the returned future resolves to the stats of the experiment before its deletion
+    pub fn delete_experiment(
+        &mut self,
+        experiment_id: Uuid,
+    ) -> Result<impl Future<Output = Option<PolicyStats>>, ServiceError> {
+        let request = self.get_experiment_address(experiment_id)?.send(Delete);
         self.experiments.remove(&experiment_id);
-        Ok(())
+        Ok(async move { request.await.ok().flatten() })
     }
 
     pub async fn reset_experiment(
@@ -137,7 +287,7 @@ impl Repository {
         arm_id: Option<usize>,
         cumulative_reward: Option<f64>,
         count: Option<u64>,
-    ) -> Result<(), ServiceError> {
+    ) -> Result<Audited<()>, ServiceError> {
         self.send_to_experiment(
             experiment_id,
             Reset {
@@ -156,7 +306,7 @@ impl Repository {
         experiment_id: Uuid,
         initial_reward: Option<f64>,
         initial_count: Option<u64>,
-    ) -> Result<usize, ServiceError> {
+    ) -> Result<Audited<usize>, ServiceError> {
         self.send_to_experiment(
             experiment_id,
             AddArm {
@@ -173,7 +323,7 @@ impl Repository {
         &self,
         experiment_id: Uuid,
         arm_id: usize,
-    ) -> Result<(), ServiceError> {
+    ) -> Result<Audited<()>, ServiceError> {
         self.send_to_experiment(experiment_id, EnableArm { arm_id })
             .await?
             .map_err(RepositoryError::from)
@@ -184,7 +334,7 @@ impl Repository {
         &self,
         experiment_id: Uuid,
         arm_id: usize,
-    ) -> Result<(), ServiceError> {
+    ) -> Result<Audited<()>, ServiceError> {
         self.send_to_experiment(experiment_id, DisableArm { arm_id })
             .await?
             .map_err(RepositoryError::from)
@@ -195,15 +345,19 @@ impl Repository {
         &self,
         experiment_id: Uuid,
         arm_id: usize,
-    ) -> Result<(), ServiceError> {
+    ) -> Result<Audited<()>, ServiceError> {
         self.send_to_experiment(experiment_id, DeleteArm { arm_id })
             .await?
             .map_err(RepositoryError::from)
             .map_err(ServiceError::from)
     }
 
-    pub async fn draw_experiment(&self, experiment_id: Uuid) -> Result<DrawResult, ServiceError> {
-        self.send_to_experiment(experiment_id, Draw)
+    pub async fn draw_experiment(
+        &self,
+        experiment_id: Uuid,
+        context: Option<Vec<f64>>,
+    ) -> Result<TicketedDraw, ServiceError> {
+        self.send_to_experiment(experiment_id, Draw { context })
             .await?
             .map_err(RepositoryError::from)
             .map_err(ServiceError::from)
@@ -215,6 +369,7 @@ impl Repository {
         timestamp: f64,
         arm_id: usize,
         reward: f64,
+        context: Option<Vec<f64>>,
     ) -> Result<(), ServiceError> {
         self.send_to_experiment(
             experiment_id,
@@ -222,6 +377,27 @@ impl Repository {
                 timestamp,
                 arm_id,
                 reward,
+                context,
+            },
+        )
+        .await?
+        .map_err(RepositoryError::from)
+        .map_err(ServiceError::from)
+    }
+
+    pub async fn redeem_experiment_ticket(
+        &self,
+        experiment_id: Uuid,
+        ticket: Uuid,
+        timestamp: f64,
+        reward: f64,
+    ) -> Result<(), ServiceError> {
+        self.send_to_experiment(
+            experiment_id,
+            RedeemTicket {
+                ticket,
+                timestamp,
+                reward,
             },
         )
         .await?
@@ -233,8 +409,103 @@ impl Repository {
         &self,
         experiment_id: Uuid,
         updates: Vec<BatchUpdateElement>,
-    ) -> Result<(), ServiceError> {
-        self.send_to_experiment(experiment_id, UpdateBatch { updates })
+        skip_invalid: bool,
+    ) -> Result<BatchUpdateReport, ServiceError> {
+        self.send_to_experiment(
+            experiment_id,
+            UpdateBatch {
+                updates,
+                skip_invalid,
+            },
+        )
+        .await?
+        .map_err(RepositoryError::from)
+        .map_err(ServiceError::from)
+    }
+
+    fn get_interaction_record_dir(&self, experiment_id: Uuid) -> Result<PathBuf, ServiceError> {
+        self.get_experiment_address(experiment_id)?;
+        self.experiment_config
+            .interaction_record_dir
+            .clone()
+            .ok_or(RepositoryError::InteractionsNotRecorded.into())
+    }
+
+    // recorded interactions are read from disk directly, on the blocking thread pool, leaving the
+    // experiment free to serve draws
+    pub async fn get_experiment_interactions(
+        &self,
+        experiment_id: Uuid,
+    ) -> Result<Vec<LoggedInteraction>, ServiceError> {
+        let dir = self.get_interaction_record_dir(experiment_id)?;
+        let records = web::block(move || {
+            InteractionRecords::open(&dir, experiment_id, None, None)?
+                .collect::<Result<Vec<InteractionRecord>, PersistenceError>>()
+        })
+        .await??;
+        Ok(LoggedInteraction::from_records(records))
+    }
+
+    // the export is read lazily, its chunks are expected to be pulled from the blocking thread pool
+    pub async fn export_experiment_interactions(
+        &self,
+        experiment_id: Uuid,
+        format: ExportFormat,
+        from: Option<f64>,
+        to: Option<f64>,
+    ) -> Result<InteractionExport, ServiceError> {
+        let dir = self.get_interaction_record_dir(experiment_id)?;
+        let export =
+            web::block(move || InteractionExport::open(&dir, experiment_id, format, from, to))
+                .await??;
+        Ok(export)
+    }
+
+    pub async fn set_experiment_stopping_rule(
+        &self,
+        experiment_id: Uuid,
+        stopping_rule: StoppingRule,
+    ) -> Result<Audited<()>, ServiceError> {
+        self.send_to_experiment(experiment_id, SetStoppingRule { stopping_rule })
+            .await?
+            .map_err(RepositoryError::from)
+            .map_err(ServiceError::from)
+    }
+
+    pub async fn set_experiment_pruning_rule(
+        &self,
+        experiment_id: Uuid,
+        pruning_rule: Option<PruningRule>,
+    ) -> Result<Audited<()>, ServiceError> {
+        self.send_to_experiment(experiment_id, SetPruningRule { pruning_rule })
+            .await?
+            .map_err(RepositoryError::from)
+            .map_err(ServiceError::from)
+    }
+
+    pub async fn change_experiment_status(
+        &self,
+        experiment_id: Uuid,
+        transition: Transition,
+    ) -> Result<Audited<()>, ServiceError> {
+        self.send_to_experiment(experiment_id, ChangeStatus { transition })
+            .await?
+            .map_err(RepositoryError::from)
+            .map_err(ServiceError::from)
+    }
+
+    pub async fn get_experiment_metadata(
+        &self,
+        experiment_id: Uuid,
+    ) -> Result<ExperimentMetadata, ServiceError> {
+        self.send_to_experiment(experiment_id, GetMetadata).await
+    }
+
+    pub async fn get_experiment_posteriors(
+        &self,
+        experiment_id: Uuid,
+    ) -> Result<HashMap<usize, ArmPosterior>, ServiceError> {
+        self.send_to_experiment(experiment_id, GetPosteriors)
             .await?
             .map_err(RepositoryError::from)
             .map_err(ServiceError::from)
@@ -244,7 +515,16 @@ impl Repository {
         &self,
         experiment_id: Uuid,
     ) -> Result<PolicyStats, ServiceError> {
-        self.send_to_experiment(experiment_id, GetStats)
+        self.get_experiment_stats_at(experiment_id, DEFAULT_LEVEL)
+            .await
+    }
+
+    pub async fn get_experiment_stats_at(
+        &self,
+        experiment_id: Uuid,
+        level: f64,
+    ) -> Result<PolicyStats, ServiceError> {
+        self.send_to_experiment(experiment_id, GetStats { level })
             .await?
             .map_err(RepositoryError::from)
             .map_err(ServiceError::from)
@@ -252,12 +532,15 @@ impl Repository {
 }
 
 #[cfg(test)]
+#[allow(clippy::unnecessary_get_then_check)]
 mod tests {
     use super::*;
-    use crate::actors::state_store::SaveState;
-    use crate::config::{ExperimentConfig, StateStoreConfig};
-    use crate::errors::{RepositoryError, ServiceError};
-    use crate::policies::{Policy, PolicyType};
+    use crate::actors::state_store::{LoadState, SaveState};
+    use crate::config::{ExperimentConfig, LateUpdates, StateBackendType, StateStoreConfig};
+    use crate::errors::{ExperimentError, PolicyError, RepositoryError, ServiceError};
+    use crate::policies::{get_timestamp, Policy, PolicyType};
+    use crate::storage::{EventLog, ExperimentEvent};
+    use crate::storage::{InteractionKind, InteractionRecord};
 
     use std::fs;
     use std::path::PathBuf;
@@ -283,13 +566,39 @@ mod tests {
 
     impl TestContext {
         fn new() -> Self {
-            let state_dir =
-                std::env::temp_dir().join(format!("state-store-{}", Uuid::new_v4()));
+            Self::with_config(|_| ())
+        }
+
+        fn with_late_updates(late_updates: LateUpdates) -> Self {
+            Self::with_config(|config| config.late_updates = late_updates)
+        }
+
+        fn with_ticket_capacity(ticket_capacity: usize) -> Self {
+            Self::with_config(|config| config.ticket_capacity = ticket_capacity)
+        }
+
+        fn with_config<F: FnOnce(&mut ExperimentConfig)>(configure: F) -> Self {
+            let state_dir = std::env::temp_dir().join(format!("state-store-{}", Uuid::new_v4()));
             let state_store_config = StateStoreConfig {
+                backend: StateBackendType::File,
                 dir: state_dir.clone(),
+                history_size: 5,
+                history_every: 0,
             };
-            let state_store = StateStore::new(state_store_config).start();
-            let experiment_config = ExperimentConfig { save_every: 86_400 };
+            let state_store = StateStore::new(state_store_config)
+                .expect("state store should open")
+                .start();
+            let mut experiment_config = ExperimentConfig {
+                save_every: 86_400,
+                evaluate_every: 1,
+                ticket_ttl: 3_600,
+                ticket_capacity: 100,
+                event_log_dir: state_dir.join("events"),
+                interaction_record_dir: Some(state_dir.join("interactions")),
+                interaction_record_size: 100,
+                late_updates: LateUpdates::Reject,
+            };
+            configure(&mut experiment_config);
             let repository = Repository::new(experiment_config, state_store.clone());
 
             Self {
@@ -309,7 +618,7 @@ mod tests {
     #[actix::test]
     async fn create_and_ping_experiment() {
         let mut ctx = TestContext::new();
-        let experiment_id = ctx.repository.create_experiment(None, make_policy());
+        let experiment_id = ctx.repository.create_experiment(None, make_policy(), true);
 
         ctx.repository
             .ping_experiment(experiment_id)
@@ -328,13 +637,14 @@ mod tests {
     #[actix::test]
     async fn manages_arm_lifecycle() {
         let mut ctx = TestContext::new();
-        let experiment_id = ctx.repository.create_experiment(None, make_policy());
+        let experiment_id = ctx.repository.create_experiment(None, make_policy(), true);
 
         let arm_id = ctx
             .repository
             .add_experiment_arm(experiment_id, Some(1.0), Some(1))
             .await
-            .expect("arm creation should succeed");
+            .expect("arm creation should succeed")
+            .value;
 
         let mut stats = ctx
             .repository
@@ -369,13 +679,13 @@ mod tests {
 
         let draw = ctx
             .repository
-            .draw_experiment(experiment_id)
+            .draw_experiment(experiment_id, None)
             .await
             .expect("draw should succeed");
-        assert_eq!(draw.arm_id, arm_id);
+        assert_eq!(draw.result.arm_id, arm_id);
 
         ctx.repository
-            .update_experiment(experiment_id, 42.0, arm_id, 2.0)
+            .update_experiment(experiment_id, 42.0, arm_id, 2.0, None)
             .await
             .expect("update should succeed");
         ctx.repository
@@ -386,13 +696,16 @@ mod tests {
                         timestamp: 1.0,
                         arm_id,
                         reward: 3.0,
+                        context: None,
                     },
                     BatchUpdateElement {
                         timestamp: 2.0,
                         arm_id,
                         reward: 1.0,
+                        context: None,
                     },
                 ],
+                false,
             )
             .await
             .expect("batch update should succeed");
@@ -431,7 +744,8 @@ mod tests {
 
         ctx.repository
             .delete_experiment(experiment_id)
-            .expect("delete experiment should succeed");
+            .expect("delete experiment should succeed")
+            .await;
         assert!(!ctx
             .repository
             .iter_experiments()
@@ -450,6 +764,241 @@ mod tests {
         }
     }
 
+    #[actix::test]
+    async fn rejects_invalid_initial_rewards() {
+        let mut ctx = TestContext::new();
+        let experiment_id = ctx.repository.create_experiment(None, make_policy(), true);
+
+        assert!(matches!(
+            ctx.repository
+                .add_experiment_arm(experiment_id, Some(f64::NAN), Some(1))
+                .await,
+            Err(ServiceError::Repository(RepositoryError::Experiment(
+                ExperimentError::PolicyError(PolicyError::InvalidReward { .. })
+            )))
+        ));
+        let arm_id = ctx
+            .repository
+            .add_experiment_arm(experiment_id, None, None)
+            .await
+            .expect("arm creation should succeed")
+            .value;
+        assert!(matches!(
+            ctx.repository
+                .reset_experiment(experiment_id, Some(arm_id), Some(f64::INFINITY), Some(1))
+                .await,
+            Err(ServiceError::Repository(RepositoryError::Experiment(
+                ExperimentError::PolicyError(PolicyError::InvalidReward { .. })
+            )))
+        ));
+
+        let stats = ctx
+            .repository
+            .get_experiment_stats(experiment_id)
+            .await
+            .expect("stats should be available");
+        assert_eq!(stats.arms.len(), 1);
+        assert_eq!(stats.arms[&arm_id].mean_reward, 0.0);
+    }
+
+    fn make_update(arm_id: usize, reward: f64) -> BatchUpdateElement {
+        BatchUpdateElement {
+            timestamp: 1.0,
+            arm_id,
+            reward,
+            context: None,
+        }
+    }
+
+    #[actix::test]
+    async fn batch_update_is_atomic() {
+        let mut ctx = TestContext::new();
+        let experiment_id = ctx.repository.create_experiment(None, make_policy(), true);
+        let arm_id = ctx
+            .repository
+            .add_experiment_arm(experiment_id, None, None)
+            .await
+            .expect("arm creation should succeed")
+            .value;
+
+        let err = ctx
+            .repository
+            .batch_update_experiment(
+                experiment_id,
+                vec![make_update(arm_id, 1.0), make_update(arm_id + 1, 1.0)],
+                false,
+            )
+            .await
+            .expect_err("batch with an unknown arm should fail");
+        assert!(matches!(
+            err,
+            ServiceError::Repository(RepositoryError::Experiment(_))
+        ));
+
+        let stats = ctx
+            .repository
+            .get_experiment_stats(experiment_id)
+            .await
+            .expect("stats should be available");
+        assert_eq!(stats.arms[&arm_id].pulls, 0);
+    }
+
+    #[actix::test]
+    async fn batch_update_skips_invalid() {
+        let mut ctx = TestContext::new();
+        let experiment_id = ctx.repository.create_experiment(None, make_policy(), true);
+        let arm_id = ctx
+            .repository
+            .add_experiment_arm(experiment_id, None, None)
+            .await
+            .expect("arm creation should succeed")
+            .value;
+
+        let report = ctx
+            .repository
+            .batch_update_experiment(
+                experiment_id,
+                vec![
+                    make_update(arm_id, 1.0),
+                    make_update(arm_id + 1, 1.0),
+                    make_update(arm_id, f64::NAN),
+                    make_update(arm_id, 0.0),
+                ],
+                true,
+            )
+            .await
+            .expect("batch should succeed when skipping invalid updates");
+        assert_eq!(report.applied, 2);
+        assert_eq!(
+            report
+                .rejected
+                .iter()
+                .map(|rejected| rejected.index)
+                .collect::<Vec<usize>>(),
+            vec![1, 2]
+        );
+
+        let stats = ctx
+            .repository
+            .get_experiment_stats(experiment_id)
+            .await
+            .expect("stats should be available");
+        assert_eq!(stats.arms[&arm_id].pulls, 2);
+    }
+
+    #[actix::test]
+    async fn redeems_draw_tickets() {
+        let mut ctx = TestContext::new();
+        let experiment_id = ctx.repository.create_experiment(None, make_policy(), false);
+        let arm_id = ctx
+            .repository
+            .add_experiment_arm(experiment_id, None, None)
+            .await
+            .expect("arm creation should succeed")
+            .value;
+
+        let draw = ctx
+            .repository
+            .draw_experiment(experiment_id, None)
+            .await
+            .expect("draw should succeed");
+
+        // the arm is disabled after being served, its reward is still attributed to it
+        ctx.repository
+            .disable_experiment_arm(experiment_id, arm_id)
+            .await
+            .expect("disable should succeed");
+        ctx.repository
+            .redeem_experiment_ticket(experiment_id, draw.ticket, 1.0, 1.0)
+            .await
+            .expect("ticket should be redeemed");
+
+        let stats = ctx
+            .repository
+            .get_experiment_stats(experiment_id)
+            .await
+            .expect("stats should be available");
+        assert_eq!(stats.arms[&arm_id].pulls, 1);
+        assert!(!stats.arms[&arm_id].is_active);
+
+        let interactions = ctx
+            .repository
+            .get_experiment_interactions(experiment_id)
+            .await
+            .expect("interactions should be available");
+        assert_eq!(interactions.len(), 1);
+        assert_eq!(interactions[0].arm_id, arm_id);
+        assert_eq!(interactions[0].propensity, 1.0);
+
+        let err = ctx
+            .repository
+            .redeem_experiment_ticket(experiment_id, draw.ticket, 1.0, 1.0)
+            .await
+            .expect_err("ticket cannot be redeemed twice");
+        assert!(matches!(
+            err,
+            ServiceError::Repository(RepositoryError::Experiment(
+                ExperimentError::DuplicateTicket(_)
+            ))
+        ));
+
+        let err = ctx
+            .repository
+            .redeem_experiment_ticket(experiment_id, Uuid::new_v4(), 1.0, 1.0)
+            .await
+            .expect_err("unknown ticket should be rejected");
+        assert!(matches!(
+            err,
+            ServiceError::Repository(RepositoryError::Experiment(ExperimentError::UnknownTicket(
+                _
+            )))
+        ));
+
+        // rewards cannot bypass tickets unless raw updates are allowed
+        assert!(matches!(
+            ctx.repository
+                .update_experiment(experiment_id, 1.0, arm_id, 1.0, None)
+                .await,
+            Err(ServiceError::Repository(RepositoryError::Experiment(
+                ExperimentError::RawUpdatesDisabled
+            )))
+        ));
+    }
+
+    #[actix::test]
+    async fn evicts_oldest_tickets() {
+        let mut ctx = TestContext::with_ticket_capacity(2);
+        let experiment_id = ctx.repository.create_experiment(None, make_policy(), false);
+        ctx.repository
+            .add_experiment_arm(experiment_id, None, None)
+            .await
+            .expect("arm creation should succeed");
+
+        let mut draws = Vec::new();
+        for _ in 0..3 {
+            draws.push(
+                ctx.repository
+                    .draw_experiment(experiment_id, None)
+                    .await
+                    .expect("draw should succeed"),
+            );
+        }
+        assert!(matches!(
+            ctx.repository
+                .redeem_experiment_ticket(experiment_id, draws[0].ticket, 1.0, 1.0)
+                .await,
+            Err(ServiceError::Repository(RepositoryError::Experiment(
+                ExperimentError::UnknownTicket(_)
+            )))
+        ));
+        for draw in &draws[1..] {
+            ctx.repository
+                .redeem_experiment_ticket(experiment_id, draw.ticket, 1.0, 1.0)
+                .await
+                .expect("recent tickets should be redeemed");
+        }
+    }
+
     #[actix::test]
     async fn loads_experiments_from_state_store() {
         let mut ctx = TestContext::new();
@@ -462,9 +1011,12 @@ mod tests {
             .send(SaveState {
                 experiment_id,
                 policy: saved_policy,
+                event_seq: 0,
+                metadata: ExperimentMetadata::default(),
             })
             .await
-            .expect("state should be saved");
+            .expect("state should be saved")
+            .expect("state should be written");
 
         ctx.repository
             .load_experiments()
@@ -486,4 +1038,659 @@ mod tests {
         assert_eq!(arm.pulls, 2);
         assert_eq!(arm.mean_reward, 5.0);
     }
+
+    #[actix::test]
+    async fn replays_event_log_on_top_of_snapshot() {
+        let mut ctx = TestContext::new();
+        let experiment_id = Uuid::new_v4();
+
+        let mut saved_policy = make_policy();
+        let arm_id = saved_policy.add_arm(0.0, 0);
+
+        // the first update is already part of the snapshot, the other two were logged after it
+        let mut event_log = EventLog::open(
+            event_log_path(
+                &ctx.repository.experiment_config.event_log_dir,
+                experiment_id,
+            ),
+            0,
+        )
+        .expect("event log should open");
+        for _ in 0..3 {
+            event_log
+                .append(ExperimentEvent::Update {
+                    timestamp: 0.0,
+                    arm_id,
+                    reward: 1.0,
+                    context: None,
+                })
+                .expect("event should be logged");
+        }
+        saved_policy
+            .update(0.0, arm_id, 1.0, None)
+            .expect("update should succeed");
+
+        ctx.state_store
+            .send(SaveState {
+                experiment_id,
+                policy: saved_policy,
+                event_seq: 1,
+                metadata: ExperimentMetadata::default(),
+            })
+            .await
+            .expect("state should be saved")
+            .expect("state should be written");
+
+        ctx.repository
+            .load_experiments()
+            .await
+            .expect("loading from state store should succeed");
+
+        let stats = ctx
+            .repository
+            .get_experiment_stats(experiment_id)
+            .await
+            .expect("stats should be retrievable");
+        assert_eq!(stats.arms[&arm_id].pulls, 3);
+
+        // changes made after the restart keep being logged
+        ctx.repository
+            .disable_experiment_arm(experiment_id, arm_id)
+            .await
+            .expect("disable should succeed");
+        let mut policy = make_policy();
+        policy.add_arm(0.0, 0);
+        let path = event_log_path(
+            &ctx.repository.experiment_config.event_log_dir,
+            experiment_id,
+        );
+        replay_tail(policy.as_mut(), &path, 0).expect("event log should replay");
+        assert_eq!(policy.stats().arms[&arm_id].pulls, 3);
+        assert!(!policy.stats().arms[&arm_id].is_active);
+    }
+
+    #[actix::test]
+    async fn restores_snapshot() {
+        let mut ctx = TestContext::new();
+        let experiment_id = Uuid::new_v4();
+
+        let mut saved_policy = make_policy();
+        let arm_id = saved_policy.add_arm(1.0, 2);
+        ctx.state_store
+            .send(SaveState {
+                experiment_id,
+                policy: saved_policy,
+                event_seq: 0,
+                metadata: ExperimentMetadata::default().with_raw_updates(true),
+            })
+            .await
+            .expect("state should be saved")
+            .expect("state should be written");
+        ctx.repository
+            .load_experiments()
+            .await
+            .expect("loading from state store should succeed");
+
+        for _ in 0..3 {
+            ctx.repository
+                .update_experiment(experiment_id, 0.0, arm_id, 0.0, None)
+                .await
+                .expect("update should succeed");
+        }
+
+        let snapshots = ctx
+            .repository
+            .list_experiment_snapshots(experiment_id)
+            .await
+            .expect("snapshots should be listed");
+        assert_eq!(snapshots.len(), 1);
+        ctx.repository
+            .restore_experiment_snapshot(experiment_id, snapshots[0].snapshot_id)
+            .await
+            .expect("restore should succeed");
+
+        let stats = ctx
+            .repository
+            .get_experiment_stats(experiment_id)
+            .await
+            .expect("stats should be retrievable");
+        assert_eq!(stats.arms[&arm_id].pulls, 2);
+        assert_eq!(stats.arms[&arm_id].mean_reward, 1.0);
+
+        let err = ctx
+            .repository
+            .restore_experiment_snapshot(experiment_id, 0)
+            .await
+            .expect_err("unknown snapshot should be rejected");
+        assert!(matches!(
+            err,
+            ServiceError::Repository(RepositoryError::Experiment(
+                ExperimentError::SnapshotNotFound(0)
+            ))
+        ));
+    }
+
+    #[actix::test]
+    async fn flushes_experiments_to_state_store() {
+        let mut ctx = TestContext::new();
+        let experiment_id = ctx.repository.create_experiment(None, make_policy(), true);
+        let arm_id = ctx
+            .repository
+            .add_experiment_arm(experiment_id, Some(1.0), Some(3))
+            .await
+            .expect("arm creation should succeed")
+            .value;
+
+        assert!(ctx.repository.flush_experiments().await.is_empty());
+
+        let state = ctx
+            .state_store
+            .send(LoadState { experiment_id })
+            .await
+            .expect("state store should respond")
+            .expect("state should have been flushed");
+        assert_eq!(state.policy.stats().arms[&arm_id].pulls, 3);
+        assert_eq!(state.event_seq, 1);
+    }
+
+    #[actix::test]
+    async fn exports_recorded_interactions() {
+        let mut ctx = TestContext::new();
+        let experiment_id = ctx.repository.create_experiment(None, make_policy(), true);
+        let arm_id = ctx
+            .repository
+            .add_experiment_arm(experiment_id, None, None)
+            .await
+            .expect("arm creation should succeed")
+            .value;
+
+        let draw = ctx
+            .repository
+            .draw_experiment(experiment_id, None)
+            .await
+            .expect("draw should succeed");
+        ctx.repository
+            .redeem_experiment_ticket(experiment_id, draw.ticket, draw.result.timestamp, 1.0)
+            .await
+            .expect("ticket should be redeemed");
+        ctx.repository
+            .update_experiment(
+                experiment_id,
+                draw.result.timestamp + 10.0,
+                arm_id,
+                0.0,
+                None,
+            )
+            .await
+            .expect("update should succeed");
+
+        let records = ctx
+            .repository
+            .export_experiment_interactions(experiment_id, ExportFormat::Ndjson, None, None)
+            .await
+            .expect("export should open")
+            .collect::<Result<String, _>>()
+            .expect("export should be read")
+            .lines()
+            .map(|line| serde_json::from_str::<InteractionRecord>(line).unwrap())
+            .collect::<Vec<InteractionRecord>>();
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0].kind, InteractionKind::Draw);
+        assert_eq!(records[0].propensity, Some(1.0));
+        // the reward redeemed through the ticket can be joined to its draw
+        assert_eq!(records[1].ticket, Some(draw.ticket));
+        assert_eq!(records[1].reward, Some(1.0));
+        assert_eq!(records[2].ticket, None);
+
+        let records = ctx
+            .repository
+            .export_experiment_interactions(
+                experiment_id,
+                ExportFormat::Csv,
+                Some(draw.result.timestamp + 1.0),
+                None,
+            )
+            .await
+            .expect("export should open")
+            .collect::<Result<String, _>>()
+            .expect("export should be read");
+        assert_eq!(records.lines().count(), 2);
+    }
+
+    #[actix::test]
+    async fn concludes_on_stopping_rule() {
+        let mut ctx = TestContext::new();
+        let experiment_id = ctx.repository.create_experiment(None, make_policy(), true);
+        let loser = ctx
+            .repository
+            .add_experiment_arm(experiment_id, Some(10.0), Some(100))
+            .await
+            .expect("arm creation should succeed")
+            .value;
+        let winner = ctx
+            .repository
+            .add_experiment_arm(experiment_id, Some(90.0), Some(100))
+            .await
+            .expect("arm creation should succeed")
+            .value;
+
+        ctx.repository
+            .set_experiment_stopping_rule(
+                experiment_id,
+                StoppingRule {
+                    max_samples: Some(500),
+                    ..Default::default()
+                },
+            )
+            .await
+            .expect("stopping rule should be set");
+        actix::clock::sleep(std::time::Duration::from_millis(1500)).await;
+        let metadata = ctx
+            .repository
+            .get_experiment_metadata(experiment_id)
+            .await
+            .expect("metadata should be available");
+        assert!(metadata.conclusion.is_none());
+
+        ctx.repository
+            .set_experiment_stopping_rule(
+                experiment_id,
+                StoppingRule {
+                    probability_best: Some(0.99),
+                    ..Default::default()
+                },
+            )
+            .await
+            .expect("stopping rule should be set");
+        actix::clock::sleep(std::time::Duration::from_millis(1500)).await;
+        let conclusion = ctx
+            .repository
+            .get_experiment_metadata(experiment_id)
+            .await
+            .expect("metadata should be available")
+            .conclusion
+            .expect("experiment should be concluded");
+        assert_eq!(conclusion.winner, winner);
+        assert_eq!(conclusion.samples, 200);
+        assert!(conclusion.analysis.arms[&loser].probability_best < 0.01);
+
+        // the winner is served from then on, and the decision survives a restart
+        for _ in 0..10 {
+            let draw = ctx
+                .repository
+                .draw_experiment(experiment_id, None)
+                .await
+                .expect("draw should succeed");
+            assert_eq!(draw.result.arm_id, winner);
+            assert_eq!(draw.result.propensity, Some(1.0));
+        }
+        let state = ctx
+            .state_store
+            .send(LoadState { experiment_id })
+            .await
+            .expect("state store should respond")
+            .expect("conclusion should have been stored");
+        assert_eq!(
+            state
+                .metadata
+                .conclusion
+                .map(|conclusion| conclusion.winner),
+            Some(winner)
+        );
+    }
+
+    #[actix::test]
+    async fn prunes_dominated_arms() {
+        let mut ctx = TestContext::new();
+        let experiment_id = ctx.repository.create_experiment(None, make_policy(), true);
+        let mut arm_ids = Vec::new();
+        for (mean_reward, count) in [(0.1, 200), (0.9, 200), (0.0, 5)] {
+            arm_ids.push(
+                ctx.repository
+                    .add_experiment_arm(experiment_id, Some(mean_reward), Some(count))
+                    .await
+                    .expect("arm creation should succeed")
+                    .value,
+            );
+        }
+
+        ctx.repository
+            .set_experiment_pruning_rule(
+                experiment_id,
+                Some(PruningRule {
+                    min_pulls: 100,
+                    level: DEFAULT_LEVEL,
+                }),
+            )
+            .await
+            .expect("pruning rule should be set");
+        actix::clock::sleep(std::time::Duration::from_millis(1500)).await;
+
+        let policy_stats = ctx
+            .repository
+            .get_experiment_stats(experiment_id)
+            .await
+            .expect("stats should be available");
+        let (loser, winner, unproven) = (arm_ids[0], arm_ids[1], arm_ids[2]);
+        assert!(!policy_stats.arms[&loser].is_active);
+        assert!(policy_stats.arms[&loser].is_pruned);
+        assert!(policy_stats.arms[&winner].is_active);
+        // too few pulls to be judged
+        assert!(policy_stats.arms[&unproven].is_active);
+        let metadata = ctx
+            .repository
+            .get_experiment_metadata(experiment_id)
+            .await
+            .expect("metadata should be available");
+        assert_eq!(metadata.pruned_arms[&loser].dominated_by, winner);
+
+        // an arm enabled through the API is no longer reported as pruned
+        ctx.repository
+            .set_experiment_pruning_rule(experiment_id, None)
+            .await
+            .expect("pruning rule should be removed");
+        ctx.repository
+            .enable_experiment_arm(experiment_id, loser)
+            .await
+            .expect("enable should succeed");
+        let policy_stats = ctx
+            .repository
+            .get_experiment_stats(experiment_id)
+            .await
+            .expect("stats should be available");
+        assert!(policy_stats.arms[&loser].is_active);
+        assert!(!policy_stats.arms[&loser].is_pruned);
+    }
+
+    #[actix::test]
+    async fn moves_through_lifecycle() {
+        let mut ctx = TestContext::new();
+        let experiment_id =
+            ctx.repository
+                .create_draft_experiment(make_policy(), Schedule::default(), true);
+        let fallback = ctx
+            .repository
+            .add_experiment_arm(experiment_id, Some(0.2), Some(10))
+            .await
+            .expect("arms can be set up in a draft")
+            .value;
+        let winner = ctx
+            .repository
+            .add_experiment_arm(experiment_id, Some(0.8), Some(10))
+            .await
+            .expect("arms can be set up in a draft")
+            .value;
+        assert!(matches!(
+            ctx.repository.draw_experiment(experiment_id, None).await,
+            Err(ServiceError::Repository(RepositoryError::Experiment(
+                ExperimentError::InvalidStatus {
+                    status: ExperimentStatus::Draft,
+                    ..
+                }
+            )))
+        ));
+
+        ctx.repository
+            .change_experiment_status(experiment_id, Transition::Start)
+            .await
+            .expect("draft should start");
+        ctx.repository
+            .draw_experiment(experiment_id, None)
+            .await
+            .expect("draw should succeed once running");
+
+        ctx.repository
+            .change_experiment_status(
+                experiment_id,
+                Transition::Pause {
+                    fallback_arm: fallback,
+                },
+            )
+            .await
+            .expect("running experiment should pause");
+        let draw = ctx
+            .repository
+            .draw_experiment(experiment_id, None)
+            .await
+            .expect("paused experiment should serve its fallback arm");
+        assert_eq!(draw.result.arm_id, fallback);
+        assert_eq!(draw.result.propensity, Some(1.0));
+        assert!(matches!(
+            ctx.repository
+                .update_experiment(experiment_id, 42.0, fallback, 1.0, None)
+                .await,
+            Err(ServiceError::Repository(RepositoryError::Experiment(
+                ExperimentError::InvalidStatus { .. }
+            )))
+        ));
+        assert!(matches!(
+            ctx.repository
+                .disable_experiment_arm(experiment_id, fallback)
+                .await,
+            Err(ServiceError::Repository(RepositoryError::Experiment(
+                ExperimentError::ServedArm(_)
+            )))
+        ));
+
+        ctx.repository
+            .change_experiment_status(experiment_id, Transition::Resume)
+            .await
+            .expect("paused experiment should resume");
+        ctx.repository
+            .change_experiment_status(experiment_id, Transition::Conclude { winner: None })
+            .await
+            .expect("running experiment should conclude");
+        let draw = ctx
+            .repository
+            .draw_experiment(experiment_id, None)
+            .await
+            .expect("concluded experiment should serve its winner");
+        assert_eq!(draw.result.arm_id, winner);
+
+        ctx.repository
+            .change_experiment_status(experiment_id, Transition::Archive)
+            .await
+            .expect("concluded experiment should be archived");
+        assert!(ctx
+            .repository
+            .draw_experiment(experiment_id, None)
+            .await
+            .is_err());
+        assert!(ctx
+            .repository
+            .add_experiment_arm(experiment_id, None, None)
+            .await
+            .is_err());
+        assert!(matches!(
+            ctx.repository
+                .change_experiment_status(experiment_id, Transition::Resume)
+                .await,
+            Err(ServiceError::Repository(RepositoryError::Experiment(
+                ExperimentError::InvalidTransition {
+                    from: ExperimentStatus::Archived,
+                    to: ExperimentStatus::Running,
+                }
+            )))
+        ));
+
+        // archived experiments stay in storage, readable
+        let state = ctx
+            .state_store
+            .send(LoadState { experiment_id })
+            .await
+            .expect("state store should respond")
+            .expect("archived experiment should be stored");
+        assert_eq!(state.metadata.status, ExperimentStatus::Archived);
+        assert!(ctx
+            .repository
+            .get_experiment_stats(experiment_id)
+            .await
+            .is_ok());
+    }
+
+    #[actix::test]
+    async fn follows_schedule() {
+        let mut ctx = TestContext::with_late_updates(LateUpdates::Record);
+        let now = get_timestamp();
+        let experiment_id = ctx.repository.create_scheduled_experiment(
+            make_policy(),
+            Schedule {
+                starts_at: Some(now + 0.5),
+                ends_at: Some(now + 1.0),
+                default_arm: Some(0),
+            },
+            true,
+        );
+        let arm_id = ctx
+            .repository
+            .add_experiment_arm(experiment_id, Some(0.5), Some(10))
+            .await
+            .expect("arms can be set up before the schedule starts")
+            .value;
+        ctx.repository
+            .add_experiment_arm(experiment_id, Some(0.5), Some(10))
+            .await
+            .expect("arms can be set up before the schedule starts");
+
+        let metadata = ctx
+            .repository
+            .get_experiment_metadata(experiment_id)
+            .await
+            .expect("metadata should be read");
+        assert_eq!(metadata.status, ExperimentStatus::Scheduled);
+        let draw = ctx
+            .repository
+            .draw_experiment(experiment_id, None)
+            .await
+            .expect("default arm should be served before the start");
+        assert_eq!(draw.result.arm_id, arm_id);
+        assert_eq!(draw.result.propensity, Some(1.0));
+
+        actix::clock::sleep(std::time::Duration::from_millis(700)).await;
+        let metadata = ctx
+            .repository
+            .get_experiment_metadata(experiment_id)
+            .await
+            .expect("metadata should be read");
+        assert_eq!(metadata.status, ExperimentStatus::Running);
+        let draw = ctx
+            .repository
+            .draw_experiment(experiment_id, None)
+            .await
+            .expect("draw should succeed while running");
+
+        actix::clock::sleep(std::time::Duration::from_millis(500)).await;
+        let metadata = ctx
+            .repository
+            .get_experiment_metadata(experiment_id)
+            .await
+            .expect("metadata should be read");
+        assert_eq!(metadata.status, ExperimentStatus::Ended);
+        let pulls = |stats: PolicyStats| stats.arms.values().map(|arm| arm.pulls).sum::<u64>();
+        let before = ctx
+            .repository
+            .get_experiment_stats(experiment_id)
+            .await
+            .expect("stats should be read");
+
+        // late rewards are recorded, but not learned from
+        ctx.repository
+            .redeem_experiment_ticket(experiment_id, draw.ticket, get_timestamp(), 1.0)
+            .await
+            .expect("late reward should be recorded");
+        ctx.repository
+            .update_experiment(experiment_id, get_timestamp(), arm_id, 1.0, None)
+            .await
+            .expect("late reward should be recorded");
+        let after = ctx
+            .repository
+            .get_experiment_stats(experiment_id)
+            .await
+            .expect("stats should be read");
+        assert_eq!(pulls(before), pulls(after));
+        let records = ctx
+            .repository
+            .export_experiment_interactions(experiment_id, ExportFormat::Ndjson, None, None)
+            .await
+            .expect("export should open")
+            .collect::<Result<String, _>>()
+            .expect("export should be read");
+        assert_eq!(
+            records
+                .lines()
+                .filter(|line| line.contains(&draw.ticket.to_string()))
+                .count(),
+            2
+        );
+
+        let draw = ctx
+            .repository
+            .draw_experiment(experiment_id, None)
+            .await
+            .expect("default arm should be served after the end");
+        assert_eq!(draw.result.arm_id, arm_id);
+
+        let state = ctx
+            .state_store
+            .send(LoadState { experiment_id })
+            .await
+            .expect("state store should respond")
+            .expect("ended experiment should be stored");
+        assert_eq!(state.metadata.status, ExperimentStatus::Ended);
+    }
+
+    #[actix::test]
+    async fn protects_default_arm() {
+        let mut ctx = TestContext::new();
+        let experiment_id = ctx.repository.create_draft_experiment(
+            make_policy(),
+            Schedule {
+                starts_at: None,
+                ends_at: Some(get_timestamp() + 60.0),
+                default_arm: Some(1),
+            },
+            true,
+        );
+        ctx.repository
+            .add_experiment_arm(experiment_id, Some(0.5), Some(10))
+            .await
+            .expect("arms can be set up in a draft");
+        assert!(matches!(
+            ctx.repository
+                .change_experiment_status(experiment_id, Transition::Start)
+                .await,
+            Err(ServiceError::Repository(RepositoryError::Experiment(
+                ExperimentError::PolicyError(PolicyError::ArmNotFound(1))
+            )))
+        ));
+
+        let default_arm = ctx
+            .repository
+            .add_experiment_arm(experiment_id, Some(0.5), Some(10))
+            .await
+            .expect("arms can be set up in a draft")
+            .value;
+        assert!(matches!(
+            ctx.repository
+                .disable_experiment_arm(experiment_id, default_arm)
+                .await,
+            Err(ServiceError::Repository(RepositoryError::Experiment(
+                ExperimentError::ServedArm(1)
+            )))
+        ));
+        ctx.repository
+            .change_experiment_status(experiment_id, Transition::Start)
+            .await
+            .expect("draft should start once its default arm exists");
+
+        // the default arm is served once the schedule is over, so that it is kept while running
+        assert!(matches!(
+            ctx.repository
+                .delete_experiment_arm(experiment_id, default_arm)
+                .await,
+            Err(ServiceError::Repository(RepositoryError::Experiment(
+                ExperimentError::ServedArm(1)
+            )))
+        ));
+    }
 }
@@ -0,0 +1,285 @@
+use super::file::FileBackend;
+use super::metadata::ExperimentMetadata;
+use super::sqlite::SqliteBackend;
+
+use crate::config::{StateBackendType, StateStoreConfig};
+use crate::errors::PersistenceError;
+use crate::policies::{get_timestamp, Policy};
+
+use serde::Serialize;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+// A state that could not be read back, set aside for inspection instead of being loaded
+#[derive(Debug, Serialize)]
+pub struct QuarantinedState {
+    pub experiment_id: Uuid,
+    pub location: String,
+    pub reason: String,
+    pub quarantined_at: f64,
+}
+
+// A past state of an experiment, identified by the millisecond at which it was saved, or the one
+// after the previous snapshot when several are saved within the same millisecond
+#[derive(Debug, Serialize)]
+pub struct SnapshotInfo {
+    pub snapshot_id: u64,
+    pub created_at: f64,
+}
+
+impl SnapshotInfo {
+    pub fn new(snapshot_id: u64) -> Self {
+        Self {
+            snapshot_id,
+            created_at: snapshot_id as f64 / 1000.0,
+        }
+    }
+}
+
+pub fn new_snapshot_id(last_snapshot_id: Option<u64>) -> u64 {
+    let snapshot_id = (get_timestamp() * 1000.0) as u64;
+    last_snapshot_id.map_or(snapshot_id, |last| snapshot_id.max(last + 1))
+}
+
+// Snapshot of a policy along with the sequence number of the last logged event it includes
+pub struct StoredState {
+    pub policy: Box<dyn Policy + Send>,
+    pub event_seq: u64,
+    pub metadata: ExperimentMetadata,
+}
+
+// Durable storage of policy states, keyed by experiment, along with a bounded history of each of them
+pub trait StateBackend: Send {
+    // the state is also added to the history of the experiment when snapshot is set
+    fn save(
+        &mut self,
+        experiment_id: Uuid,
+        policy: &(dyn Policy + Send),
+        event_seq: u64,
+        metadata: &ExperimentMetadata,
+        snapshot: bool,
+    ) -> Result<(), PersistenceError>;
+    fn load(&mut self, experiment_id: Uuid) -> Result<Option<StoredState>, PersistenceError>;
+    fn load_all(&mut self) -> Result<HashMap<Uuid, StoredState>, PersistenceError>;
+    fn delete(&mut self, experiment_id: Uuid) -> Result<(), PersistenceError>;
+    fn quarantined(&mut self) -> Result<Vec<QuarantinedState>, PersistenceError>;
+    fn snapshots(&mut self, experiment_id: Uuid) -> Result<Vec<SnapshotInfo>, PersistenceError>;
+    fn load_snapshot(
+        &mut self,
+        experiment_id: Uuid,
+        snapshot_id: u64,
+    ) -> Result<Option<StoredState>, PersistenceError>;
+}
+
+pub fn make_state_backend(
+    config: &StateStoreConfig,
+) -> Result<Box<dyn StateBackend>, PersistenceError> {
+    match config.backend {
+        StateBackendType::File => Ok(Box::new(FileBackend::new(
+            config.dir.clone(),
+            config.history_size,
+        )?)),
+        StateBackendType::Sqlite => Ok(Box::new(SqliteBackend::new(
+            &config.dir,
+            config.history_size,
+        )?)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluation::StoppingRule;
+    use crate::policies::PolicyType;
+    use crate::storage::{event_log_path, replay_tail, EventLog, ExperimentEvent};
+
+    use std::fs;
+
+    fn make_policy() -> Box<dyn Policy + Send> {
+        let mut policy = PolicyType::ThompsonSampling {
+            halflife_seconds: None,
+            propensity_samples: 0,
+            seed: Some(1234),
+        }
+        .into_inner();
+        policy.add_arm(0.5, 10);
+        policy
+    }
+
+    fn round_trip(backend: StateBackendType) {
+        let dir = std::env::temp_dir().join(format!("state-backend-{}", Uuid::new_v4()));
+        let config = StateStoreConfig {
+            backend,
+            dir: dir.clone(),
+            history_size: 2,
+            history_every: 0,
+        };
+        let mut backend = make_state_backend(&config).unwrap();
+        let experiment_id = Uuid::new_v4();
+
+        assert!(backend.load(experiment_id).unwrap().is_none());
+        backend
+            .save(
+                experiment_id,
+                make_policy().as_ref(),
+                1,
+                &ExperimentMetadata::default(),
+                true,
+            )
+            .unwrap();
+        // saving again replaces the previous state
+        let metadata = ExperimentMetadata {
+            stopping_rule: Some(StoppingRule {
+                max_samples: Some(100),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        backend
+            .save(experiment_id, make_policy().as_ref(), 2, &metadata, true)
+            .unwrap();
+
+        let state = backend.load(experiment_id).unwrap().unwrap();
+        assert_eq!(state.policy.stats().arms[&0].pulls, 10);
+        assert_eq!(state.event_seq, 2);
+        assert!((state.metadata.created_at - metadata.created_at).abs() < 1e-3);
+        assert_eq!(state.metadata.stopping_rule, metadata.stopping_rule);
+        let states = backend.load_all().unwrap();
+        assert_eq!(states.len(), 1);
+        assert!(states.contains_key(&experiment_id));
+
+        backend.delete(experiment_id).unwrap();
+        assert!(backend.load(experiment_id).unwrap().is_none());
+        assert!(backend.snapshots(experiment_id).unwrap().is_empty());
+        assert!(backend.load_all().unwrap().is_empty());
+        // deleting a missing state is not an error
+        assert!(backend.delete(experiment_id).is_ok());
+
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    fn history(backend: StateBackendType) {
+        let dir = std::env::temp_dir().join(format!("state-backend-{}", Uuid::new_v4()));
+        let config = StateStoreConfig {
+            backend,
+            dir: dir.clone(),
+            history_size: 2,
+            history_every: 0,
+        };
+        let mut backend = make_state_backend(&config).unwrap();
+        let experiment_id = Uuid::new_v4();
+
+        for count in 1..=3 {
+            let mut policy = make_policy();
+            policy.reset(Some(0), Some(0.0), Some(count)).unwrap();
+            backend
+                .save(
+                    experiment_id,
+                    policy.as_ref(),
+                    count,
+                    &ExperimentMetadata::default(),
+                    true,
+                )
+                .unwrap();
+        }
+
+        // only the most recent snapshots are kept, oldest first
+        let snapshots = backend.snapshots(experiment_id).unwrap();
+        assert_eq!(snapshots.len(), 2);
+        assert!(snapshots[0].snapshot_id < snapshots[1].snapshot_id);
+
+        let state = backend
+            .load_snapshot(experiment_id, snapshots[0].snapshot_id)
+            .unwrap()
+            .unwrap();
+        assert_eq!(state.policy.stats().arms[&0].pulls, 2);
+        assert_eq!(state.event_seq, 2);
+        assert!(backend.load_snapshot(experiment_id, 0).unwrap().is_none());
+
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    // a seeded policy restored from the store and its event log draws the same arms as the one it
+    // was saved from
+    fn reproducible_draws(backend: StateBackendType) {
+        let dir = std::env::temp_dir().join(format!("state-backend-{}", Uuid::new_v4()));
+        let config = StateStoreConfig {
+            backend,
+            dir: dir.clone(),
+            history_size: 0,
+            history_every: 0,
+        };
+        let mut backend = make_state_backend(&config).unwrap();
+        let experiment_id = Uuid::new_v4();
+
+        let mut policy = make_policy();
+        (0..4).for_each(|_| {
+            policy.add_arm(0.5, 10);
+        });
+        (0..10).for_each(|_| {
+            policy.draw(None).unwrap();
+        });
+        backend
+            .save(
+                experiment_id,
+                policy.as_ref(),
+                0,
+                &ExperimentMetadata::default(),
+                true,
+            )
+            .unwrap();
+
+        // draws made after the snapshot are logged, so that a crash does not repeat them
+        let path = event_log_path(&dir.join("events"), experiment_id);
+        let mut event_log = EventLog::open(path.clone(), 0).unwrap();
+        assert!(policy.draw_changes_state());
+        (0..10).for_each(|_| {
+            policy.draw(None).unwrap();
+            event_log
+                .append(ExperimentEvent::Draw { context: None })
+                .unwrap();
+        });
+        let mut restored = backend.load(experiment_id).unwrap().unwrap().policy;
+        assert_eq!(replay_tail(restored.as_mut(), &path, 0).unwrap(), 10);
+
+        let draws = (0..50)
+            .map(|_| policy.draw(None).unwrap().arm_id)
+            .collect::<Vec<usize>>();
+        let restored_draws = (0..50)
+            .map(|_| restored.draw(None).unwrap().arm_id)
+            .collect::<Vec<usize>>();
+        assert_eq!(draws, restored_draws);
+
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn file_round_trip() {
+        round_trip(StateBackendType::File);
+    }
+
+    #[test]
+    fn sqlite_round_trip() {
+        round_trip(StateBackendType::Sqlite);
+    }
+
+    #[test]
+    fn file_history() {
+        history(StateBackendType::File);
+    }
+
+    #[test]
+    fn sqlite_history() {
+        history(StateBackendType::Sqlite);
+    }
+
+    #[test]
+    fn file_reproducible_draws() {
+        reproducible_draws(StateBackendType::File);
+    }
+
+    #[test]
+    fn sqlite_reproducible_draws() {
+        reproducible_draws(StateBackendType::Sqlite);
+    }
+}
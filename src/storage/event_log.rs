@@ -0,0 +1,390 @@
+use crate::errors::{PersistenceError, PolicyError};
+use crate::policies::{get_timestamp, BatchUpdateElement, Policy};
+
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, ErrorKind, Write},
+    path::{Path, PathBuf},
+};
+use tracing::warn;
+use uuid::Uuid;
+
+// Every change made to the policy of an experiment, enough to rebuild it from its last snapshot
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ExperimentEvent {
+    AddArm {
+        initial_reward: f64,
+        initial_count: u64,
+    },
+    DisableArm {
+        arm_id: usize,
+    },
+    EnableArm {
+        arm_id: usize,
+    },
+    DeleteArm {
+        arm_id: usize,
+    },
+    Reset {
+        arm_id: Option<usize>,
+        cumulative_reward: Option<f64>,
+        count: Option<u64>,
+    },
+    Draw {
+        context: Option<Vec<f64>>,
+    },
+    Update {
+        timestamp: f64,
+        arm_id: usize,
+        reward: f64,
+        context: Option<Vec<f64>>,
+    },
+    UpdateBatch {
+        updates: Vec<BatchUpdateElement>,
+    },
+    // reward attributed through a draw ticket, learned from even if the arm was disabled since
+    Redeem {
+        timestamp: f64,
+        arm_id: usize,
+        reward: f64,
+        context: Option<Vec<f64>>,
+    },
+}
+
+impl ExperimentEvent {
+    // events are applied as of the time they were logged
+    pub fn apply(&self, policy: &mut dyn Policy, timestamp: f64) -> Result<(), PolicyError> {
+        match self {
+            Self::AddArm {
+                initial_reward,
+                initial_count,
+            } => {
+                policy.add_arm(*initial_reward, *initial_count);
+                Ok(())
+            }
+            Self::DisableArm { arm_id } => policy.disable_arm(*arm_id),
+            Self::EnableArm { arm_id } => policy.enable_arm(*arm_id),
+            Self::DeleteArm { arm_id } => policy.delete_arm(*arm_id),
+            Self::Reset {
+                arm_id,
+                cumulative_reward,
+                count,
+            } => policy.reset(*arm_id, *cumulative_reward, *count),
+            // draws matter for policies whose state decays over time
+            Self::Draw { context } => policy.draw_at(timestamp, context.as_deref()).map(|_| ()),
+            Self::Update {
+                timestamp,
+                arm_id,
+                reward,
+                context,
+            } => policy.update(*timestamp, *arm_id, *reward, context.as_deref()),
+            Self::UpdateBatch { updates } => policy.update_batch(updates),
+            Self::Redeem {
+                timestamp,
+                arm_id,
+                reward,
+                context,
+            } => {
+                let is_active = policy
+                    .stats()
+                    .arms
+                    .get(arm_id)
+                    .map(|arm| arm.is_active)
+                    .ok_or(PolicyError::ArmNotFound(*arm_id))?;
+
+                if is_active {
+                    policy.update(*timestamp, *arm_id, *reward, context.as_deref())
+                } else {
+                    // the arm was active when it was served, so its reward is still learned from
+                    policy.enable_arm(*arm_id)?;
+                    let result = policy.update(*timestamp, *arm_id, *reward, context.as_deref());
+                    policy.disable_arm(*arm_id)?;
+                    result
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct EventRecord {
+    seq: u64,
+    timestamp: f64,
+    event: ExperimentEvent,
+}
+
+pub fn event_log_path(dir: &Path, experiment_id: Uuid) -> PathBuf {
+    dir.join(format!("{experiment_id}.jsonl"))
+}
+
+// Append-only log of the events of one experiment, one JSON record per line
+pub struct EventLog {
+    path: PathBuf,
+    file: File,
+    last_seq: u64,
+}
+
+impl EventLog {
+    // sequence numbers resume after the ones already logged or covered by the last snapshot
+    pub fn open(path: PathBuf, snapshot_seq: u64) -> Result<Self, PersistenceError> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let (records, valid_len) = scan_events(&path, 0)?;
+        let last_seq = records
+            .last()
+            .map_or(snapshot_seq, |record| record.seq.max(snapshot_seq));
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+
+        // cut a line left partial by a crash, so the next append starts on a line of its own
+        if file.metadata()?.len() > valid_len {
+            warn!(path = ?path, "Truncating partial event log entry");
+            file.set_len(valid_len)?;
+            file.sync_data()?;
+        }
+
+        Ok(Self {
+            path,
+            file,
+            last_seq,
+        })
+    }
+
+    pub fn last_seq(&self) -> u64 {
+        self.last_seq
+    }
+
+    pub fn append(&mut self, event: ExperimentEvent) -> Result<(), PersistenceError> {
+        let record = EventRecord {
+            seq: self.last_seq + 1,
+            timestamp: get_timestamp(),
+            event,
+        };
+        let mut line = serde_json::to_string(&record)?;
+        line.push('\n');
+        self.file.write_all(line.as_bytes())?;
+        // an event is only acknowledged once it is on disk
+        self.file.sync_data()?;
+        self.last_seq = record.seq;
+        Ok(())
+    }
+
+    // drop the events already included in a snapshot, rewriting the log atomically
+    pub fn compact(&mut self, snapshot_seq: u64) -> Result<(), PersistenceError> {
+        let tail = read_events(&self.path, snapshot_seq)?;
+        let tmp_path = self.path.with_extension("jsonl.tmp");
+        let mut tmp_file = File::create(&tmp_path)?;
+        for record in &tail {
+            let mut line = serde_json::to_string(record)?;
+            line.push('\n');
+            tmp_file.write_all(line.as_bytes())?;
+        }
+        tmp_file.sync_all()?;
+        fs::rename(&tmp_path, &self.path)?;
+
+        self.file = OpenOptions::new().append(true).open(&self.path)?;
+        Ok(())
+    }
+
+    pub fn delete(self) -> Result<(), PersistenceError> {
+        match fs::remove_file(&self.path) {
+            Err(err) if err.kind() != ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+// events logged after a given sequence number, a line cut short by a crash ending the log
+fn read_events(path: &Path, after_seq: u64) -> Result<Vec<EventRecord>, PersistenceError> {
+    scan_events(path, after_seq).map(|(records, _)| records)
+}
+
+// the events of a log along with the length of its complete lines, only the last line may be partial
+fn scan_events(path: &Path, after_seq: u64) -> Result<(Vec<EventRecord>, u64), PersistenceError> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok((Vec::new(), 0)),
+        Err(err) => return Err(err.into()),
+    };
+
+    let mut reader = BufReader::new(file);
+    let mut records = Vec::new();
+    let mut valid_len = 0;
+    let mut line = Vec::new();
+    for line_number in 1.. {
+        line.clear();
+        let read = reader.read_until(b'\n', &mut line)?;
+        if read == 0 {
+            break;
+        }
+        // every append ends with a newline, so a line without one was cut short
+        let is_complete = line.ends_with(b"\n");
+        match serde_json::from_slice::<EventRecord>(&line) {
+            Ok(record) if is_complete => {
+                valid_len += read as u64;
+                if record.seq > after_seq {
+                    records.push(record);
+                }
+            }
+            Ok(_) => warn!(path = ?path, "Ignoring truncated event log entry"),
+            Err(err) => {
+                if reader.fill_buf()?.is_empty() {
+                    warn!(error = %err, path = ?path, "Ignoring truncated event log entry");
+                } else {
+                    return Err(PersistenceError::CorruptEvent {
+                        line: line_number,
+                        source: err,
+                    });
+                }
+            }
+        }
+    }
+    Ok((records, valid_len))
+}
+
+// apply the events logged after a snapshot on top of it, at the time they originally happened
+pub fn replay_tail(
+    policy: &mut dyn Policy,
+    path: &Path,
+    snapshot_seq: u64,
+) -> Result<usize, PersistenceError> {
+    let mut applied = 0;
+    for record in read_events(path, snapshot_seq)? {
+        match record.event.apply(policy, record.timestamp) {
+            Ok(()) => applied += 1,
+            Err(err) => warn!(error = %err, seq = record.seq, "Failed to replay event"),
+        }
+    }
+    Ok(applied)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::policies::PolicyType;
+
+    fn make_path() -> PathBuf {
+        std::env::temp_dir()
+            .join(format!("event-log-{}", Uuid::new_v4()))
+            .join("events.jsonl")
+    }
+
+    fn make_update(arm_id: usize) -> ExperimentEvent {
+        ExperimentEvent::Update {
+            timestamp: 0.0,
+            arm_id,
+            reward: 1.0,
+            context: None,
+        }
+    }
+
+    #[test]
+    fn append_and_compact() {
+        let path = make_path();
+        let mut log = EventLog::open(path.clone(), 0).unwrap();
+        for _ in 0..3 {
+            log.append(make_update(0)).unwrap();
+        }
+        assert_eq!(log.last_seq(), 3);
+
+        log.compact(2).unwrap();
+        log.append(make_update(0)).unwrap();
+        let records = read_events(&path, 0).unwrap();
+        assert_eq!(
+            records
+                .iter()
+                .map(|record| record.seq)
+                .collect::<Vec<u64>>(),
+            vec![3, 4]
+        );
+
+        // sequence numbers carry on after a reopen
+        let log = EventLog::open(path.clone(), 0).unwrap();
+        assert_eq!(log.last_seq(), 4);
+        log.delete().unwrap();
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn replay_truncated_log() {
+        let path = make_path();
+        let mut log = EventLog::open(path.clone(), 0).unwrap();
+        log.append(ExperimentEvent::AddArm {
+            initial_reward: 0.0,
+            initial_count: 0,
+        })
+        .unwrap();
+        log.append(make_update(0)).unwrap();
+        log.append(make_update(0)).unwrap();
+
+        // simulate a crash in the middle of the last append
+        let content = fs::read_to_string(&path).unwrap();
+        fs::write(&path, &content[..content.len() - 10]).unwrap();
+
+        let mut policy = PolicyType::Ucb {
+            alpha: 1.0,
+            seed: Some(1234),
+        }
+        .into_inner();
+        assert_eq!(replay_tail(policy.as_mut(), &path, 0).unwrap(), 2);
+        assert_eq!(policy.stats().arms[&0].pulls, 1);
+
+        log.delete().unwrap();
+    }
+
+    #[test]
+    fn append_after_partial_line() {
+        let path = make_path();
+        let mut log = EventLog::open(path.clone(), 0).unwrap();
+        log.append(ExperimentEvent::AddArm {
+            initial_reward: 0.0,
+            initial_count: 0,
+        })
+        .unwrap();
+        log.append(make_update(0)).unwrap();
+        drop(log);
+
+        // a crash leaves the last append half written
+        let content = fs::read_to_string(&path).unwrap();
+        fs::write(&path, &content[..content.len() - 10]).unwrap();
+
+        let mut log = EventLog::open(path.clone(), 0).unwrap();
+        assert_eq!(log.last_seq(), 1);
+        log.append(make_update(0)).unwrap();
+        log.append(make_update(0)).unwrap();
+
+        let mut policy = PolicyType::Ucb {
+            alpha: 1.0,
+            seed: Some(1234),
+        }
+        .into_inner();
+        assert_eq!(replay_tail(policy.as_mut(), &path, 0).unwrap(), 3);
+        assert_eq!(policy.stats().arms[&0].pulls, 2);
+
+        log.delete().unwrap();
+    }
+
+    #[test]
+    fn reject_corrupt_middle_line() {
+        let path = make_path();
+        let mut log = EventLog::open(path.clone(), 0).unwrap();
+        for _ in 0..3 {
+            log.append(make_update(0)).unwrap();
+        }
+
+        let content = fs::read_to_string(&path).unwrap();
+        let mut lines = content.lines().map(String::from).collect::<Vec<String>>();
+        lines[1].truncate(10);
+        fs::write(&path, lines.join("\n") + "\n").unwrap();
+
+        let err = read_events(&path, 0).unwrap_err();
+        assert!(matches!(
+            err,
+            PersistenceError::CorruptEvent { line: 2, .. }
+        ));
+        assert!(err.is_corruption());
+
+        log.delete().unwrap();
+    }
+}
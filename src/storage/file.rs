@@ -0,0 +1,435 @@
+use super::backend::{new_snapshot_id, QuarantinedState, SnapshotInfo, StateBackend, StoredState};
+use super::metadata::ExperimentMetadata;
+
+use crate::errors::PersistenceError;
+use crate::policies::{get_timestamp, Policy};
+
+use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+};
+use tracing::warn;
+use uuid::Uuid;
+
+const QUARANTINE_DIR: &str = "quarantine";
+const HISTORY_DIR: &str = "history";
+
+// On-disk layout of a state file, the checksum covering the exact bytes of the serialized state.
+// Both are missing from states written before checksums were introduced, which only hold the policy.
+#[derive(Serialize, Deserialize)]
+struct StateEnvelope<'a> {
+    checksum: Option<u32>,
+    #[serde(borrow)]
+    state: Option<&'a RawValue>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StateContents<'a> {
+    event_seq: u64,
+    #[serde(borrow)]
+    policy: &'a RawValue,
+    metadata: ExperimentMetadata,
+}
+
+// One JSON file per experiment, named after its id, past snapshots being kept in a directory per
+// experiment under history/
+pub struct FileBackend {
+    dir: PathBuf,
+    history_size: usize,
+}
+
+impl FileBackend {
+    pub fn new(dir: PathBuf, history_size: usize) -> Result<Self, PersistenceError> {
+        fs::create_dir_all(dir.join(QUARANTINE_DIR))?;
+        fs::create_dir_all(dir.join(HISTORY_DIR))?;
+        Ok(Self { dir, history_size })
+    }
+
+    fn path_for(&self, experiment_id: Uuid) -> PathBuf {
+        self.dir.join(format!("{experiment_id}.json"))
+    }
+
+    fn history_dir_for(&self, experiment_id: Uuid) -> PathBuf {
+        self.dir.join(HISTORY_DIR).join(experiment_id.to_string())
+    }
+
+    // snapshot ids of an experiment, oldest first
+    fn snapshot_ids(&self, experiment_id: Uuid) -> Result<Vec<u64>, PersistenceError> {
+        let entries = match fs::read_dir(self.history_dir_for(experiment_id)) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut snapshot_ids = entries
+            .flatten()
+            .filter_map(|entry| {
+                let path = entry.path();
+                if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                    return None;
+                }
+                path.file_stem()?.to_str()?.parse::<u64>().ok()
+            })
+            .collect::<Vec<u64>>();
+        snapshot_ids.sort_unstable();
+        Ok(snapshot_ids)
+    }
+
+    fn record_snapshot(
+        &self,
+        experiment_id: Uuid,
+        contents: &[u8],
+    ) -> Result<(), PersistenceError> {
+        let history_dir = self.history_dir_for(experiment_id);
+        fs::create_dir_all(&history_dir)?;
+        let mut snapshot_ids = self.snapshot_ids(experiment_id)?;
+        let snapshot_id = new_snapshot_id(snapshot_ids.last().copied());
+        write_atomic(&history_dir.join(format!("{snapshot_id}.json")), contents)?;
+        snapshot_ids.push(snapshot_id);
+
+        let excess = snapshot_ids.len().saturating_sub(self.history_size);
+        for snapshot_id in &snapshot_ids[..excess] {
+            fs::remove_file(history_dir.join(format!("{snapshot_id}.json")))?;
+        }
+        Ok(())
+    }
+
+    fn read_state(path: &Path) -> Result<StoredState, PersistenceError> {
+        let content = fs::read_to_string(path)?;
+        let envelope = serde_json::from_str::<StateEnvelope>(&content)?;
+        let Some(checksum) = envelope.checksum else {
+            return Ok(StoredState {
+                policy: serde_json::from_str(&content)?,
+                event_seq: 0,
+                metadata: ExperimentMetadata::default(),
+            });
+        };
+
+        let state = envelope
+            .state
+            .ok_or_else(|| <serde_json::Error as serde::de::Error>::missing_field("state"))?;
+        let actual = crc32fast::hash(state.get().as_bytes());
+        if actual != checksum {
+            return Err(PersistenceError::ChecksumMismatch {
+                expected: checksum,
+                actual,
+            });
+        }
+        let contents = serde_json::from_str::<StateContents>(state.get())?;
+        Ok(StoredState {
+            policy: serde_json::from_str(contents.policy.get())?,
+            event_seq: contents.event_seq,
+            metadata: contents.metadata,
+        })
+    }
+
+    // move an unreadable state aside, along with the reason it was rejected
+    fn quarantine(&self, experiment_id: Uuid, err: &PersistenceError) {
+        let timestamp = get_timestamp();
+        let stem = format!("{experiment_id}.{}", (timestamp * 1000.0) as u64);
+        let quarantine_dir = self.dir.join(QUARANTINE_DIR);
+        let result = fs::rename(
+            self.path_for(experiment_id),
+            quarantine_dir.join(format!("{stem}.json")),
+        )
+        .and_then(|()| {
+            fs::write(
+                quarantine_dir.join(format!("{stem}.reason")),
+                err.to_string(),
+            )
+        });
+
+        match result {
+            Ok(()) => warn!(error = %err, id = %experiment_id, "Quarantined unreadable state"),
+            Err(io_err) => {
+                warn!(error = %io_err, id = %experiment_id, "Failed to quarantine unreadable state")
+            }
+        }
+    }
+}
+
+// write to a temporary file which replaces the target only once fully on disk, so that a crash
+// leaves either the previous state or the new one
+fn write_atomic(path: &Path, contents: &[u8]) -> Result<(), PersistenceError> {
+    let tmp_path = path.with_extension("json.tmp");
+    let mut file = File::create(&tmp_path)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    fs::rename(&tmp_path, path)?;
+
+    // make the rename itself durable, which is not supported on every platform
+    if let Some(dir) = path.parent().and_then(|dir| File::open(dir).ok()) {
+        let _ = dir.sync_all();
+    }
+    Ok(())
+}
+
+impl StateBackend for FileBackend {
+    fn save(
+        &mut self,
+        experiment_id: Uuid,
+        policy: &(dyn Policy + Send),
+        event_seq: u64,
+        metadata: &ExperimentMetadata,
+        snapshot: bool,
+    ) -> Result<(), PersistenceError> {
+        let state = serde_json::to_string(&StateContents {
+            event_seq,
+            policy: &RawValue::from_string(serde_json::to_string(policy)?)?,
+            metadata: metadata.clone(),
+        })?;
+        let envelope = StateEnvelope {
+            checksum: Some(crc32fast::hash(state.as_bytes())),
+            state: Some(&RawValue::from_string(state)?),
+        };
+        let contents = serde_json::to_string(&envelope)?;
+        write_atomic(&self.path_for(experiment_id), contents.as_bytes())?;
+
+        if snapshot && self.history_size > 0 {
+            self.record_snapshot(experiment_id, contents.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    fn load(&mut self, experiment_id: Uuid) -> Result<Option<StoredState>, PersistenceError> {
+        let path = self.path_for(experiment_id);
+        if !path.exists() {
+            return Ok(None);
+        }
+        match Self::read_state(&path) {
+            Ok(state) => Ok(Some(state)),
+            Err(err) => {
+                if err.is_corruption() {
+                    self.quarantine(experiment_id, &err);
+                }
+                Err(err)
+            }
+        }
+    }
+
+    fn load_all(&mut self) -> Result<HashMap<Uuid, StoredState>, PersistenceError> {
+        let mut states = HashMap::new();
+
+        for entry in fs::read_dir(&self.dir)?.flatten() {
+            let path = entry.path();
+            match path.extension().and_then(|e| e.to_str()) {
+                Some("json") => (),
+                // leftover of a write interrupted before its rename
+                Some("tmp") => {
+                    let _ = fs::remove_file(&path);
+                    continue;
+                }
+                _ => continue,
+            }
+            let Some(stem) = path.file_stem().and_then(|s| s.to_str()) else {
+                continue;
+            };
+            let Ok(experiment_id) = Uuid::try_parse(stem) else {
+                warn!(path = ?path, "Skipping file with non-UUID name in state store directory");
+                continue;
+            };
+            match self.load(experiment_id) {
+                Ok(Some(state)) => {
+                    states.insert(experiment_id, state);
+                }
+                Ok(None) => (),
+                Err(err) => {
+                    warn!(error = %err, id = %experiment_id, "Failed to load state file");
+                }
+            }
+        }
+
+        Ok(states)
+    }
+
+    fn delete(&mut self, experiment_id: Uuid) -> Result<(), PersistenceError> {
+        match fs::remove_dir_all(self.history_dir_for(experiment_id)) {
+            Err(err) if err.kind() != ErrorKind::NotFound => return Err(err.into()),
+            _ => (),
+        }
+        match fs::remove_file(self.path_for(experiment_id)) {
+            Err(err) if err.kind() != ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn quarantined(&mut self) -> Result<Vec<QuarantinedState>, PersistenceError> {
+        let mut states = Vec::new();
+
+        for entry in fs::read_dir(self.dir.join(QUARANTINE_DIR))?.flatten() {
+            let path = entry.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+            // quarantined files are named <experiment_id>.<milliseconds>.json
+            let Some((experiment_id, millis)) = path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .and_then(|s| s.split_once('.'))
+            else {
+                continue;
+            };
+            let (Ok(experiment_id), Ok(millis)) =
+                (Uuid::try_parse(experiment_id), millis.parse::<u64>())
+            else {
+                continue;
+            };
+
+            states.push(QuarantinedState {
+                experiment_id,
+                location: path.display().to_string(),
+                reason: fs::read_to_string(path.with_extension("reason")).unwrap_or_default(),
+                quarantined_at: millis as f64 / 1000.0,
+            });
+        }
+
+        states.sort_by(|a, b| a.quarantined_at.total_cmp(&b.quarantined_at));
+        Ok(states)
+    }
+
+    fn snapshots(&mut self, experiment_id: Uuid) -> Result<Vec<SnapshotInfo>, PersistenceError> {
+        Ok(self
+            .snapshot_ids(experiment_id)?
+            .into_iter()
+            .map(SnapshotInfo::new)
+            .collect())
+    }
+
+    fn load_snapshot(
+        &mut self,
+        experiment_id: Uuid,
+        snapshot_id: u64,
+    ) -> Result<Option<StoredState>, PersistenceError> {
+        let path = self
+            .history_dir_for(experiment_id)
+            .join(format!("{snapshot_id}.json"));
+        if !path.exists() {
+            return Ok(None);
+        }
+        Self::read_state(&path).map(Some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::policies::PolicyType;
+
+    fn make_backend() -> FileBackend {
+        let dir = std::env::temp_dir().join(format!("file-backend-{}", Uuid::new_v4()));
+        FileBackend::new(dir, 0).unwrap()
+    }
+
+    fn make_policy() -> Box<dyn Policy + Send> {
+        let mut policy = PolicyType::Ucb {
+            alpha: 1.0,
+            seed: Some(1234),
+        }
+        .into_inner();
+        policy.add_arm(0.5, 10);
+        policy
+    }
+
+    #[test]
+    fn quarantine_truncated_state() {
+        let mut backend = make_backend();
+        let experiment_id = Uuid::new_v4();
+        backend
+            .save(
+                experiment_id,
+                make_policy().as_ref(),
+                0,
+                &ExperimentMetadata::default(),
+                true,
+            )
+            .unwrap();
+
+        let path = backend.path_for(experiment_id);
+        let content = fs::read_to_string(&path).unwrap();
+        fs::write(&path, &content[..content.len() / 2]).unwrap();
+
+        assert!(backend.load_all().unwrap().is_empty());
+        assert!(!path.exists());
+        let quarantined = backend.quarantined().unwrap();
+        assert_eq!(quarantined.len(), 1);
+        assert_eq!(quarantined[0].experiment_id, experiment_id);
+
+        let _ = fs::remove_dir_all(&backend.dir);
+    }
+
+    #[test]
+    fn quarantine_checksum_mismatch() {
+        let mut backend = make_backend();
+        let experiment_id = Uuid::new_v4();
+        backend
+            .save(
+                experiment_id,
+                make_policy().as_ref(),
+                0,
+                &ExperimentMetadata::default(),
+                true,
+            )
+            .unwrap();
+
+        // still valid JSON, but not what was written
+        let path = backend.path_for(experiment_id);
+        let content = fs::read_to_string(&path).unwrap().replace("10", "11");
+        fs::write(&path, content).unwrap();
+
+        assert!(matches!(
+            backend.load(experiment_id),
+            Err(PersistenceError::ChecksumMismatch { .. })
+        ));
+        assert!(backend.load(experiment_id).unwrap().is_none());
+        let quarantined = backend.quarantined().unwrap();
+        assert_eq!(quarantined.len(), 1);
+        assert!(quarantined[0].reason.contains("checksum"));
+
+        let _ = fs::remove_dir_all(&backend.dir);
+    }
+
+    #[test]
+    fn checksum_covers_metadata() {
+        let mut backend = make_backend();
+        let experiment_id = Uuid::new_v4();
+        backend
+            .save(
+                experiment_id,
+                make_policy().as_ref(),
+                3,
+                &ExperimentMetadata::default(),
+                true,
+            )
+            .unwrap();
+
+        let path = backend.path_for(experiment_id);
+        let content = fs::read_to_string(&path)
+            .unwrap()
+            .replace("\"event_seq\":3", "\"event_seq\":4");
+        fs::write(&path, content).unwrap();
+
+        assert!(matches!(
+            backend.load(experiment_id),
+            Err(PersistenceError::ChecksumMismatch { .. })
+        ));
+
+        let _ = fs::remove_dir_all(&backend.dir);
+    }
+
+    #[test]
+    fn load_legacy_state() {
+        let mut backend = make_backend();
+        let experiment_id = Uuid::new_v4();
+        let serialized = serde_json::to_string(&make_policy()).unwrap();
+        fs::write(backend.path_for(experiment_id), serialized).unwrap();
+
+        assert!(backend.load(experiment_id).unwrap().is_some());
+        assert!(backend.quarantined().unwrap().is_empty());
+
+        let _ = fs::remove_dir_all(&backend.dir);
+    }
+}
@@ -0,0 +1,361 @@
+use crate::errors::PersistenceError;
+
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, ErrorKind, Lines, Write},
+    path::{Path, PathBuf},
+};
+use uuid::Uuid;
+
+// records serialized at once by an export
+const EXPORT_CHUNK_SIZE: usize = 512;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InteractionKind {
+    Draw,
+    Update,
+}
+
+// A draw served or a reward received by an experiment. Draws and the rewards redeemed through
+// their ticket share the same ticket.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InteractionRecord {
+    pub kind: InteractionKind,
+    pub timestamp: f64,
+    pub arm_id: usize,
+    pub ticket: Option<Uuid>,
+    pub propensity: Option<f64>,
+    pub reward: Option<f64>,
+    pub context: Option<Vec<f64>>,
+}
+
+impl InteractionRecord {
+    const CSV_HEADER: &str = "kind,timestamp,arm_id,ticket,propensity,reward,context\n";
+
+    // the context is written as a single column with its values separated by semicolons
+    fn to_csv(&self) -> String {
+        let kind = match self.kind {
+            InteractionKind::Draw => "draw",
+            InteractionKind::Update => "update",
+        };
+        format!(
+            "{},{},{},{},{},{},{}\n",
+            kind,
+            self.timestamp,
+            self.arm_id,
+            self.ticket
+                .map(|ticket| ticket.to_string())
+                .unwrap_or_default(),
+            self.propensity.map(|p| p.to_string()).unwrap_or_default(),
+            self.reward.map(|r| r.to_string()).unwrap_or_default(),
+            self.context
+                .as_ref()
+                .map(|context| {
+                    context
+                        .iter()
+                        .map(f64::to_string)
+                        .collect::<Vec<String>>()
+                        .join(";")
+                })
+                .unwrap_or_default(),
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Ndjson,
+    Csv,
+}
+
+impl ExportFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::Ndjson => "application/x-ndjson",
+            Self::Csv => "text/csv",
+        }
+    }
+}
+
+// the current file of an experiment and the one it was rotated to
+pub fn interaction_log_paths(dir: &Path, experiment_id: Uuid) -> [PathBuf; 2] {
+    [
+        dir.join(format!("{experiment_id}.1.jsonl")),
+        dir.join(format!("{experiment_id}.jsonl")),
+    ]
+}
+
+// Log of the interactions of one experiment, one JSON record per line. Once the current file holds
+// max_records it replaces the previous one, so that at most twice as many records are kept on disk.
+pub struct InteractionLog {
+    paths: [PathBuf; 2],
+    file: File,
+    max_records: usize,
+    records: usize,
+}
+
+impl InteractionLog {
+    pub fn open(
+        dir: &Path,
+        experiment_id: Uuid,
+        max_records: usize,
+    ) -> Result<Self, PersistenceError> {
+        fs::create_dir_all(dir)?;
+        let paths = interaction_log_paths(dir, experiment_id);
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&paths[1])?;
+        let records = BufReader::new(File::open(&paths[1])?).lines().count();
+
+        Ok(Self {
+            paths,
+            file,
+            max_records: max_records.max(1),
+            records,
+        })
+    }
+
+    pub fn append(&mut self, record: &InteractionRecord) -> Result<(), PersistenceError> {
+        if self.records >= self.max_records {
+            self.rotate()?;
+        }
+        let mut line = serde_json::to_string(record)?;
+        line.push('\n');
+        self.file.write_all(line.as_bytes())?;
+        self.records += 1;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> Result<(), PersistenceError> {
+        let [previous, current] = &self.paths;
+        fs::rename(current, previous)?;
+        self.file = OpenOptions::new().create(true).append(true).open(current)?;
+        self.records = 0;
+        Ok(())
+    }
+
+    pub fn delete(self) -> Result<(), PersistenceError> {
+        for path in &self.paths {
+            match fs::remove_file(path) {
+                Err(err) if err.kind() != ErrorKind::NotFound => return Err(err.into()),
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+}
+
+// Records logged within a time range, oldest first. The files are opened upfront so that a rotation
+// happening while they are read does not affect them.
+pub struct InteractionRecords {
+    lines: Vec<Lines<BufReader<File>>>,
+    from: Option<f64>,
+    to: Option<f64>,
+}
+
+impl InteractionRecords {
+    pub fn open(
+        dir: &Path,
+        experiment_id: Uuid,
+        from: Option<f64>,
+        to: Option<f64>,
+    ) -> Result<Self, PersistenceError> {
+        let mut lines = Vec::new();
+        for path in interaction_log_paths(dir, experiment_id) {
+            match File::open(path) {
+                Ok(file) => lines.push(BufReader::new(file).lines()),
+                Err(err) if err.kind() == ErrorKind::NotFound => (),
+                Err(err) => return Err(err.into()),
+            }
+        }
+        lines.reverse();
+
+        Ok(Self { lines, from, to })
+    }
+
+    fn in_range(&self, timestamp: f64) -> bool {
+        self.from.is_none_or(|from| timestamp >= from) && self.to.is_none_or(|to| timestamp <= to)
+    }
+}
+
+impl Iterator for InteractionRecords {
+    type Item = Result<InteractionRecord, PersistenceError>;
+
+    // a line cut short while being written is skipped
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(lines) = self.lines.last_mut() {
+            match lines.next() {
+                Some(Ok(line)) => {
+                    if let Ok(record) = serde_json::from_str::<InteractionRecord>(&line) {
+                        if self.in_range(record.timestamp) {
+                            return Some(Ok(record));
+                        }
+                    }
+                }
+                Some(Err(err)) => return Some(Err(err.into())),
+                None => {
+                    self.lines.pop();
+                }
+            }
+        }
+        None
+    }
+}
+
+// Chunks of the records logged within a time range, formatted for export
+pub struct InteractionExport {
+    records: InteractionRecords,
+    format: ExportFormat,
+    header_written: bool,
+}
+
+impl InteractionExport {
+    pub fn open(
+        dir: &Path,
+        experiment_id: Uuid,
+        format: ExportFormat,
+        from: Option<f64>,
+        to: Option<f64>,
+    ) -> Result<Self, PersistenceError> {
+        Ok(Self {
+            records: InteractionRecords::open(dir, experiment_id, from, to)?,
+            format,
+            header_written: false,
+        })
+    }
+}
+
+impl Iterator for InteractionExport {
+    type Item = Result<String, PersistenceError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut chunk = String::new();
+        if !self.header_written {
+            self.header_written = true;
+            if self.format == ExportFormat::Csv {
+                chunk.push_str(InteractionRecord::CSV_HEADER);
+            }
+        }
+
+        for _ in 0..EXPORT_CHUNK_SIZE {
+            match self.records.next() {
+                Some(Ok(record)) => match self.format {
+                    ExportFormat::Ndjson => match serde_json::to_string(&record) {
+                        Ok(line) => {
+                            chunk.push_str(&line);
+                            chunk.push('\n');
+                        }
+                        Err(err) => return Some(Err(err.into())),
+                    },
+                    ExportFormat::Csv => chunk.push_str(&record.to_csv()),
+                },
+                Some(Err(err)) => return Some(Err(err)),
+                None => break,
+            }
+        }
+
+        (!chunk.is_empty()).then_some(Ok(chunk))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("interaction-log-{}", Uuid::new_v4()))
+    }
+
+    fn make_draw(timestamp: f64) -> InteractionRecord {
+        InteractionRecord {
+            kind: InteractionKind::Draw,
+            timestamp,
+            arm_id: 0,
+            ticket: None,
+            propensity: Some(0.5),
+            reward: None,
+            context: Some(vec![0.1, 0.2]),
+        }
+    }
+
+    fn export(
+        dir: &Path,
+        experiment_id: Uuid,
+        format: ExportFormat,
+        from: Option<f64>,
+        to: Option<f64>,
+    ) -> String {
+        InteractionExport::open(dir, experiment_id, format, from, to)
+            .unwrap()
+            .collect::<Result<String, PersistenceError>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn bounded_log() {
+        let dir = make_dir();
+        let experiment_id = Uuid::new_v4();
+        let mut log = InteractionLog::open(&dir, experiment_id, 2).unwrap();
+        for timestamp in 0..5 {
+            log.append(&make_draw(timestamp as f64)).unwrap();
+        }
+
+        // the oldest records were dropped by the second rotation
+        let timestamps = export(&dir, experiment_id, ExportFormat::Ndjson, None, None)
+            .lines()
+            .map(|line| {
+                serde_json::from_str::<InteractionRecord>(line)
+                    .unwrap()
+                    .timestamp
+            })
+            .collect::<Vec<f64>>();
+        assert_eq!(timestamps, vec![2.0, 3.0, 4.0]);
+
+        // the record count carries on after a reopen
+        let mut log = InteractionLog::open(&dir, experiment_id, 2).unwrap();
+        log.append(&make_draw(5.0)).unwrap();
+        log.append(&make_draw(6.0)).unwrap();
+        let exported = export(&dir, experiment_id, ExportFormat::Ndjson, None, None);
+        assert_eq!(exported.lines().count(), 3);
+
+        log.delete().unwrap();
+        assert_eq!(
+            export(&dir, experiment_id, ExportFormat::Ndjson, None, None),
+            ""
+        );
+    }
+
+    #[test]
+    fn export_time_range_as_csv() {
+        let dir = make_dir();
+        let experiment_id = Uuid::new_v4();
+        let mut log = InteractionLog::open(&dir, experiment_id, 10).unwrap();
+        for timestamp in 0..4 {
+            log.append(&make_draw(timestamp as f64)).unwrap();
+        }
+        log.append(&InteractionRecord {
+            kind: InteractionKind::Update,
+            timestamp: 2.0,
+            arm_id: 1,
+            ticket: None,
+            propensity: None,
+            reward: Some(1.0),
+            context: None,
+        })
+        .unwrap();
+
+        let exported = export(&dir, experiment_id, ExportFormat::Csv, Some(1.0), Some(2.0));
+        assert_eq!(
+            exported,
+            "kind,timestamp,arm_id,ticket,propensity,reward,context\n\
+             draw,1,0,,0.5,,0.1;0.2\n\
+             draw,2,0,,0.5,,0.1;0.2\n\
+             update,2,1,,,1,\n"
+        );
+    }
+}
@@ -0,0 +1,498 @@
+use crate::api::audit::AuditEvent;
+use crate::api::responses::LoggedResponse;
+use crate::config::{AccountantConfig, LogSinkType};
+use crate::errors::PersistenceError;
+use crate::policies::get_timestamp;
+
+use rusqlite::{params, Connection};
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+use tracing::warn;
+use uuid::Uuid;
+
+const ACTIVE_LOG_FILE: &str = "requests.jsonl";
+const AUDIT_LOG_FILE: &str = "audit.jsonl";
+const DATABASE_FILE: &str = "requests.db";
+
+// Durable storage of the responses tracked by the accountant, written in batches, and of the audit
+// trail of experiments, which is neither rotated nor subject to retention
+pub trait LogSink: Send {
+    fn write_batch(&mut self, records: &[LoggedResponse]) -> Result<(), PersistenceError>;
+    fn write_audit(&mut self, event: &AuditEvent) -> Result<(), PersistenceError>;
+    // events of an experiment within an optional time range, oldest first
+    fn audit_trail(
+        &mut self,
+        experiment_id: Uuid,
+        from: Option<f64>,
+        to: Option<f64>,
+    ) -> Result<Vec<AuditEvent>, PersistenceError>;
+}
+
+fn in_range(timestamp: f64, from: Option<f64>, to: Option<f64>) -> bool {
+    from.is_none_or(|from| timestamp >= from) && to.is_none_or(|to| timestamp <= to)
+}
+
+pub fn make_log_sink(config: &AccountantConfig) -> Result<Box<dyn LogSink>, PersistenceError> {
+    match config.sink {
+        LogSinkType::Jsonl => Ok(Box::new(JsonlSink::new(
+            config.dir.clone(),
+            config.rotation_size,
+            config.retention,
+        )?)),
+        LogSinkType::Sqlite => Ok(Box::new(SqliteSink::new(&config.dir, config.retention)?)),
+    }
+}
+
+fn now_millis() -> u64 {
+    (get_timestamp() * 1000.0) as u64
+}
+
+// One JSON record per line, the active file being renamed to requests.<milliseconds>.jsonl once
+// it reaches the rotation size. Rotated files older than the retention are deleted. The audit
+// trail is indexed by experiment when the sink is opened, so that queries only read their events.
+pub struct JsonlSink {
+    dir: PathBuf,
+    file: File,
+    size: u64,
+    rotation_size: u64,
+    retention: u64,
+    audit_file: File,
+    audit_size: u64,
+    audit_offsets: HashMap<Uuid, Vec<u64>>,
+}
+
+impl JsonlSink {
+    pub fn new(dir: PathBuf, rotation_size: u64, retention: u64) -> Result<Self, PersistenceError> {
+        fs::create_dir_all(&dir)?;
+        let file = Self::open_active(&dir)?;
+        let size = file.metadata()?.len();
+
+        let audit_path = dir.join(AUDIT_LOG_FILE);
+        let audit_file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&audit_path)?;
+        let (audit_offsets, audit_size) = index_audit_trail(&audit_path)?;
+        // cut a line left partial by a crash, so the next event starts on a line of its own
+        if audit_file.metadata()?.len() > audit_size {
+            warn!(path = ?audit_path, "Truncating partial audit trail entry");
+            audit_file.set_len(audit_size)?;
+        }
+
+        Ok(Self {
+            dir,
+            file,
+            size,
+            rotation_size,
+            retention,
+            audit_file,
+            audit_size,
+            audit_offsets,
+        })
+    }
+
+    fn open_active(dir: &Path) -> Result<File, PersistenceError> {
+        Ok(OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(dir.join(ACTIVE_LOG_FILE))?)
+    }
+
+    // milliseconds at which each rotated file was closed, oldest first
+    fn rotated_files(&self) -> Result<Vec<(u64, PathBuf)>, PersistenceError> {
+        let mut files = fs::read_dir(&self.dir)?
+            .flatten()
+            .filter_map(|entry| {
+                let path = entry.path();
+                let millis = path
+                    .file_name()?
+                    .to_str()?
+                    .strip_prefix("requests.")?
+                    .strip_suffix(".jsonl")?
+                    .parse::<u64>()
+                    .ok()?;
+                Some((millis, path))
+            })
+            .collect::<Vec<(u64, PathBuf)>>();
+        files.sort_unstable();
+        Ok(files)
+    }
+
+    fn rotate(&mut self) -> Result<(), PersistenceError> {
+        let now = now_millis();
+        fs::rename(
+            self.dir.join(ACTIVE_LOG_FILE),
+            self.dir.join(format!("requests.{now}.jsonl")),
+        )?;
+        self.file = Self::open_active(&self.dir)?;
+        self.size = 0;
+
+        if self.retention > 0 {
+            let oldest = now.saturating_sub(self.retention * 1000);
+            for (millis, path) in self.rotated_files()? {
+                if millis < oldest {
+                    fs::remove_file(path)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl LogSink for JsonlSink {
+    fn write_batch(&mut self, records: &[LoggedResponse]) -> Result<(), PersistenceError> {
+        let mut lines = String::new();
+        for record in records {
+            lines.push_str(&serde_json::to_string(record)?);
+            lines.push('\n');
+        }
+        self.file.write_all(lines.as_bytes())?;
+        self.file.flush()?;
+        self.size += lines.len() as u64;
+
+        if self.rotation_size > 0 && self.size >= self.rotation_size {
+            self.rotate()?;
+        }
+        Ok(())
+    }
+
+    fn write_audit(&mut self, event: &AuditEvent) -> Result<(), PersistenceError> {
+        let mut line = serde_json::to_string(event)?;
+        line.push('\n');
+        self.audit_file.write_all(line.as_bytes())?;
+        self.audit_file.flush()?;
+        self.audit_offsets
+            .entry(event.experiment_id)
+            .or_default()
+            .push(self.audit_size);
+        self.audit_size += line.len() as u64;
+        Ok(())
+    }
+
+    fn audit_trail(
+        &mut self,
+        experiment_id: Uuid,
+        from: Option<f64>,
+        to: Option<f64>,
+    ) -> Result<Vec<AuditEvent>, PersistenceError> {
+        let Some(offsets) = self.audit_offsets.get(&experiment_id) else {
+            return Ok(Vec::new());
+        };
+
+        let mut reader = BufReader::new(File::open(self.dir.join(AUDIT_LOG_FILE))?);
+        let mut events = Vec::new();
+        let mut line = String::new();
+        for &offset in offsets {
+            reader.seek(SeekFrom::Start(offset))?;
+            line.clear();
+            reader.read_line(&mut line)?;
+            let event = serde_json::from_str::<AuditEvent>(&line)?;
+            if in_range(event.timestamp, from, to) {
+                events.push(event);
+            }
+        }
+        events.sort_by(|a, b| a.timestamp.total_cmp(&b.timestamp));
+        Ok(events)
+    }
+}
+
+// offsets of the events of each experiment in the audit trail, along with the length of its
+// readable lines. Unreadable lines are skipped, a line cut short by a crash ending the file.
+fn index_audit_trail(path: &Path) -> Result<(HashMap<Uuid, Vec<u64>>, u64), PersistenceError> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut offsets = HashMap::<Uuid, Vec<u64>>::new();
+    let mut offset = 0;
+    let mut valid_len = 0;
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        let read = reader.read_until(b'\n', &mut line)? as u64;
+        if read == 0 {
+            break;
+        }
+        match serde_json::from_slice::<AuditEvent>(&line) {
+            Ok(event) if line.ends_with(b"\n") => {
+                offsets.entry(event.experiment_id).or_default().push(offset);
+                valid_len = offset + read;
+            }
+            Ok(_) => warn!(path = ?path, "Ignoring truncated audit trail entry"),
+            Err(err) => {
+                warn!(error = %err, path = ?path, "Ignoring unreadable audit trail entry");
+                if line.ends_with(b"\n") {
+                    valid_len = offset + read;
+                }
+            }
+        }
+        offset += read;
+    }
+    Ok((offsets, valid_len))
+}
+
+// Embedded database holding every record in a single table, rows older than the retention being
+// deleted after each batch
+pub struct SqliteSink {
+    connection: Connection,
+    retention: u64,
+}
+
+impl SqliteSink {
+    pub fn new(dir: &Path, retention: u64) -> Result<Self, PersistenceError> {
+        fs::create_dir_all(dir)?;
+        let connection = Connection::open(dir.join(DATABASE_FILE))?;
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS request_logs (
+                id TEXT PRIMARY KEY,
+                timestamp INTEGER NOT NULL,
+                route TEXT NOT NULL,
+                status INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS request_logs_timestamp ON request_logs (timestamp);
+            CREATE TABLE IF NOT EXISTS audit_events (
+                id TEXT PRIMARY KEY,
+                experiment_id TEXT NOT NULL,
+                timestamp REAL NOT NULL,
+                event TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS audit_events_experiment
+            ON audit_events (experiment_id, timestamp);",
+        )?;
+        Ok(Self {
+            connection,
+            retention,
+        })
+    }
+}
+
+impl LogSink for SqliteSink {
+    fn write_batch(&mut self, records: &[LoggedResponse]) -> Result<(), PersistenceError> {
+        let transaction = self.connection.transaction()?;
+        {
+            let mut statement = transaction.prepare(
+                "INSERT OR REPLACE INTO request_logs (id, timestamp, route, status)
+                VALUES (?1, ?2, ?3, ?4)",
+            )?;
+            for record in records {
+                statement.execute(params![
+                    record.id.to_string(),
+                    record.timestamp as i64,
+                    record.route,
+                    record.status
+                ])?;
+            }
+        }
+        if self.retention > 0 {
+            transaction.execute(
+                "DELETE FROM request_logs WHERE timestamp < ?1",
+                params![now_millis().saturating_sub(self.retention * 1000) as i64],
+            )?;
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn write_audit(&mut self, event: &AuditEvent) -> Result<(), PersistenceError> {
+        self.connection.execute(
+            "INSERT INTO audit_events (id, experiment_id, timestamp, event) VALUES (?1, ?2, ?3, ?4)",
+            params![
+                event.id.to_string(),
+                event.experiment_id.to_string(),
+                event.timestamp,
+                serde_json::to_string(event)?
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn audit_trail(
+        &mut self,
+        experiment_id: Uuid,
+        from: Option<f64>,
+        to: Option<f64>,
+    ) -> Result<Vec<AuditEvent>, PersistenceError> {
+        let rows = self
+            .connection
+            .prepare(
+                "SELECT event FROM audit_events WHERE experiment_id = ?1
+                AND (?2 IS NULL OR timestamp >= ?2) AND (?3 IS NULL OR timestamp <= ?3)
+                ORDER BY timestamp",
+            )?
+            .query_map(params![experiment_id.to_string(), from, to], |row| {
+                row.get::<_, String>(0)
+            })?
+            .collect::<Result<Vec<String>, rusqlite::Error>>()?;
+
+        rows.iter()
+            .map(|event| serde_json::from_str(event).map_err(PersistenceError::from))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::audit::AuditAction;
+
+    fn make_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("log-sink-{}", Uuid::new_v4()))
+    }
+
+    fn make_records(size: usize) -> Vec<LoggedResponse> {
+        (0..size)
+            .map(|_| LoggedResponse::new(Uuid::new_v4(), "/v1/experiments/list", 200))
+            .collect()
+    }
+
+    fn make_audit_event(experiment_id: Uuid, timestamp: f64) -> AuditEvent {
+        AuditEvent {
+            id: Uuid::new_v4(),
+            timestamp,
+            experiment_id,
+            action: AuditAction::DisableArm,
+            client: Some("127.0.0.1".to_string()),
+            payload: serde_json::json!({"arm_id": 0}),
+            before: None,
+            after: None,
+        }
+    }
+
+    fn audit_trail(sink: &mut dyn LogSink) {
+        let experiment_id = Uuid::new_v4();
+        for timestamp in [3.0, 1.0, 2.0] {
+            sink.write_audit(&make_audit_event(experiment_id, timestamp))
+                .unwrap();
+        }
+        sink.write_audit(&make_audit_event(Uuid::new_v4(), 2.0))
+            .unwrap();
+
+        let events = sink.audit_trail(experiment_id, None, None).unwrap();
+        assert_eq!(
+            events
+                .iter()
+                .map(|event| event.timestamp)
+                .collect::<Vec<f64>>(),
+            vec![1.0, 2.0, 3.0]
+        );
+        assert_eq!(events[0].action, AuditAction::DisableArm);
+        let events = sink
+            .audit_trail(experiment_id, Some(1.5), Some(2.5))
+            .unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].timestamp, 2.0);
+        assert!(sink
+            .audit_trail(Uuid::new_v4(), None, None)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn jsonl_audit_trail() {
+        let dir = make_dir();
+        audit_trail(&mut JsonlSink::new(dir.clone(), 0, 0).unwrap());
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn jsonl_truncated_audit_trail() {
+        let dir = make_dir();
+        let experiment_id = Uuid::new_v4();
+        let mut sink = JsonlSink::new(dir.clone(), 0, 0).unwrap();
+        for timestamp in [1.0, 2.0] {
+            sink.write_audit(&make_audit_event(experiment_id, timestamp))
+                .unwrap();
+        }
+        drop(sink);
+
+        // a crash leaves the last event half written
+        let path = dir.join(AUDIT_LOG_FILE);
+        let content = fs::read_to_string(&path).unwrap();
+        fs::write(&path, &content[..content.len() - 10]).unwrap();
+
+        let mut sink = JsonlSink::new(dir.clone(), 0, 0).unwrap();
+        assert_eq!(
+            sink.audit_trail(experiment_id, None, None).unwrap().len(),
+            1
+        );
+        sink.write_audit(&make_audit_event(experiment_id, 3.0))
+            .unwrap();
+        assert_eq!(
+            sink.audit_trail(experiment_id, None, None)
+                .unwrap()
+                .iter()
+                .map(|event| event.timestamp)
+                .collect::<Vec<f64>>(),
+            vec![1.0, 3.0]
+        );
+        // events written since the sink was opened are found after a restart as well
+        let mut sink = JsonlSink::new(dir.clone(), 0, 0).unwrap();
+        assert_eq!(
+            sink.audit_trail(experiment_id, None, None).unwrap().len(),
+            2
+        );
+
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn sqlite_audit_trail() {
+        let dir = make_dir();
+        audit_trail(&mut SqliteSink::new(&dir, 0).unwrap());
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn jsonl_rotation() {
+        let dir = make_dir();
+        let mut sink = JsonlSink::new(dir.clone(), 200, 0).unwrap();
+
+        sink.write_batch(&make_records(1)).unwrap();
+        assert!(sink.rotated_files().unwrap().is_empty());
+        sink.write_batch(&make_records(2)).unwrap();
+        let rotated = sink.rotated_files().unwrap();
+        assert_eq!(rotated.len(), 1);
+        assert_eq!(
+            fs::read_to_string(&rotated[0].1).unwrap().lines().count(),
+            3
+        );
+        assert_eq!(fs::metadata(dir.join(ACTIVE_LOG_FILE)).unwrap().len(), 0);
+
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn jsonl_retention() {
+        let dir = make_dir();
+        fs::create_dir_all(&dir).unwrap();
+        let expired = dir.join("requests.1000.jsonl");
+        fs::write(&expired, "").unwrap();
+
+        let mut sink = JsonlSink::new(dir.clone(), 1, 3600).unwrap();
+        sink.write_batch(&make_records(1)).unwrap();
+        assert!(!expired.exists());
+        assert_eq!(sink.rotated_files().unwrap().len(), 1);
+
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn sqlite_retention() {
+        let dir = make_dir();
+        let mut sink = SqliteSink::new(&dir, 3600).unwrap();
+
+        let mut records = make_records(3);
+        records[0].timestamp = 1000;
+        sink.write_batch(&records).unwrap();
+        let count = sink
+            .connection
+            .query_row("SELECT COUNT(*) FROM request_logs", [], |row| {
+                row.get::<_, u64>(0)
+            })
+            .unwrap();
+        assert_eq!(count, 2);
+
+        let _ = fs::remove_dir_all(dir);
+    }
+}
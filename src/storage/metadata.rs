@@ -0,0 +1,248 @@
+use crate::evaluation::{Conclusion, PrunedArm, PruningRule, StoppingRule};
+use crate::policies::get_timestamp;
+
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fmt};
+
+// Stage of the lifecycle of an experiment, deciding what its draws serve and whether it learns
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExperimentStatus {
+    // arms can be set up, but nothing is served yet
+    Draft,
+    // waiting for the start of its schedule, serving the default arm if any
+    Scheduled,
+    #[default]
+    Running,
+    // the fallback arm is served and rewards are rejected
+    Paused,
+    // the schedule is over, the default arm is served if any and rewards are not learned from
+    Ended,
+    // the winner is served from then on
+    Concluded,
+    // kept for reference only, nothing can be changed anymore
+    Archived,
+}
+
+impl ExperimentStatus {
+    pub fn can_become(self, next: Self) -> bool {
+        matches!(
+            (self, next),
+            (Self::Draft | Self::Scheduled | Self::Paused, Self::Running)
+                | (Self::Draft, Self::Scheduled)
+                | (Self::Running, Self::Paused)
+                | (Self::Scheduled | Self::Running | Self::Paused, Self::Ended)
+                | (Self::Running | Self::Paused | Self::Ended, Self::Concluded)
+                | (
+                    Self::Draft
+                        | Self::Scheduled
+                        | Self::Running
+                        | Self::Paused
+                        | Self::Ended
+                        | Self::Concluded,
+                    Self::Archived
+                )
+        )
+    }
+}
+
+impl fmt::Display for ExperimentStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Draft => write!(f, "draft"),
+            Self::Scheduled => write!(f, "scheduled"),
+            Self::Running => write!(f, "running"),
+            Self::Paused => write!(f, "paused"),
+            Self::Ended => write!(f, "ended"),
+            Self::Concluded => write!(f, "concluded"),
+            Self::Archived => write!(f, "archived"),
+        }
+    }
+}
+
+// Window in which an experiment runs, switched on and off by the experiment itself, along with the
+// arm served outside of it
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Schedule {
+    #[serde(default)]
+    pub starts_at: Option<f64>,
+    #[serde(default)]
+    pub ends_at: Option<f64>,
+    #[serde(default)]
+    pub default_arm: Option<usize>,
+}
+
+impl Schedule {
+    pub fn validate(&self, now: f64) -> Result<(), &'static str> {
+        if let Some(ends_at) = self.ends_at {
+            if ends_at <= now {
+                return Err("ends_at must be in the future");
+            }
+            if self.starts_at.is_some_and(|starts_at| starts_at >= ends_at) {
+                return Err("starts_at must be before ends_at");
+            }
+        }
+        Ok(())
+    }
+
+    // status the experiment should be in at the given time, once started
+    pub fn status_at(&self, now: f64) -> ExperimentStatus {
+        if self.starts_at.is_some_and(|starts_at| now < starts_at) {
+            ExperimentStatus::Scheduled
+        } else if self.ends_at.is_some_and(|ends_at| now >= ends_at) {
+            ExperimentStatus::Ended
+        } else {
+            ExperimentStatus::Running
+        }
+    }
+}
+
+// Settings and outcome of an experiment, stored along with its policy. States written before it
+// existed are given one created at the time they are loaded, running since then.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExperimentMetadata {
+    #[serde(default = "get_timestamp")]
+    pub created_at: f64,
+    #[serde(default)]
+    pub status: ExperimentStatus,
+    // time the experiment left its draft, from which its duration is counted
+    #[serde(default)]
+    pub started_at: Option<f64>,
+    // arm served while the experiment is paused
+    #[serde(default)]
+    pub fallback_arm: Option<usize>,
+    #[serde(default)]
+    pub schedule: Schedule,
+    #[serde(default)]
+    pub stopping_rule: Option<StoppingRule>,
+    #[serde(default)]
+    pub conclusion: Option<Conclusion>,
+    #[serde(default)]
+    pub pruning_rule: Option<PruningRule>,
+    // arms disabled by the pruning rule, until they are enabled again
+    #[serde(default)]
+    pub pruned_arms: HashMap<usize, PrunedArm>,
+    // rewards can be sent for a raw arm id rather than through the ticket of a draw, skipping every
+    // check made on tickets
+    #[serde(default)]
+    pub raw_updates: bool,
+}
+
+impl ExperimentMetadata {
+    pub fn new(status: ExperimentStatus) -> Self {
+        let created_at = get_timestamp();
+        Self {
+            created_at,
+            status,
+            started_at: (status != ExperimentStatus::Draft).then_some(created_at),
+            fallback_arm: None,
+            schedule: Schedule::default(),
+            stopping_rule: None,
+            conclusion: None,
+            pruning_rule: None,
+            pruned_arms: HashMap::new(),
+            raw_updates: false,
+        }
+    }
+
+    pub fn with_raw_updates(mut self, raw_updates: bool) -> Self {
+        self.raw_updates = raw_updates;
+        self
+    }
+
+    // an experiment created running only starts once its schedule does
+    pub fn with_schedule(mut self, schedule: Schedule) -> Self {
+        if self.status == ExperimentStatus::Running {
+            self.status = schedule.status_at(self.created_at);
+            if self.status == ExperimentStatus::Scheduled {
+                self.started_at = None;
+            }
+        }
+        self.schedule = schedule;
+        self
+    }
+
+    pub fn started_at(&self) -> f64 {
+        self.started_at.unwrap_or(self.created_at)
+    }
+
+    // arm served regardless of the policy in the current status, if any
+    pub fn served_arm(&self) -> Option<usize> {
+        match self.status {
+            ExperimentStatus::Scheduled | ExperimentStatus::Ended => self.schedule.default_arm,
+            ExperimentStatus::Paused => self.fallback_arm,
+            ExperimentStatus::Concluded => self.conclusion.as_ref().map(|c| c.winner),
+            _ => None,
+        }
+    }
+}
+
+impl Default for ExperimentMetadata {
+    fn default() -> Self {
+        Self::new(ExperimentStatus::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transitions() {
+        use ExperimentStatus::*;
+
+        assert!(Draft.can_become(Running));
+        assert!(Running.can_become(Paused));
+        assert!(Paused.can_become(Running));
+        assert!(Paused.can_become(Concluded));
+        assert!(Concluded.can_become(Archived));
+        assert!(!Draft.can_become(Paused));
+        assert!(!Concluded.can_become(Running));
+        assert!(!Archived.can_become(Running));
+        assert!(!Running.can_become(Running));
+
+        // states stored before the lifecycle existed are running
+        let metadata = serde_json::from_str::<ExperimentMetadata>("{}").unwrap();
+        assert_eq!(metadata.status, Running);
+        assert_eq!(metadata.started_at(), metadata.created_at);
+        assert!(ExperimentMetadata::new(Draft).started_at.is_none());
+    }
+
+    #[test]
+    fn schedule() {
+        use ExperimentStatus::*;
+
+        let schedule = Schedule {
+            starts_at: Some(100.0),
+            ends_at: Some(200.0),
+            default_arm: Some(1),
+        };
+        assert!(schedule.validate(50.0).is_ok());
+        assert!(schedule.validate(250.0).is_err());
+        assert!(Schedule {
+            starts_at: Some(300.0),
+            ..schedule.clone()
+        }
+        .validate(50.0)
+        .is_err());
+
+        assert_eq!(schedule.status_at(50.0), Scheduled);
+        assert_eq!(schedule.status_at(100.0), Running);
+        assert_eq!(schedule.status_at(200.0), Ended);
+        assert_eq!(Schedule::default().status_at(50.0), Running);
+
+        let metadata = ExperimentMetadata::new(Running).with_schedule(Schedule {
+            starts_at: Some(get_timestamp() + 60.0),
+            ..schedule.clone()
+        });
+        assert_eq!(metadata.status, Scheduled);
+        assert!(metadata.started_at.is_none());
+        assert_eq!(metadata.served_arm(), Some(1));
+        // a draft only follows its schedule once started
+        let metadata = ExperimentMetadata::new(Draft).with_schedule(schedule);
+        assert_eq!(metadata.status, Draft);
+        assert!(Draft.can_become(Scheduled));
+        assert!(Paused.can_become(Ended));
+        assert!(!Ended.can_become(Running));
+    }
+}
@@ -0,0 +1,16 @@
+mod backend;
+mod event_log;
+mod file;
+mod interaction_log;
+mod log_sink;
+mod metadata;
+mod sqlite;
+
+pub use backend::{make_state_backend, QuarantinedState, SnapshotInfo, StateBackend, StoredState};
+pub use event_log::{event_log_path, replay_tail, EventLog, ExperimentEvent};
+pub use interaction_log::{
+    ExportFormat, InteractionExport, InteractionKind, InteractionLog, InteractionRecord,
+    InteractionRecords,
+};
+pub use log_sink::{make_log_sink, LogSink};
+pub use metadata::{ExperimentMetadata, ExperimentStatus, Schedule};
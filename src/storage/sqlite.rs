@@ -0,0 +1,333 @@
+use super::backend::{new_snapshot_id, QuarantinedState, SnapshotInfo, StateBackend, StoredState};
+use super::metadata::ExperimentMetadata;
+
+use crate::errors::PersistenceError;
+use crate::policies::{get_timestamp, Policy};
+
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use std::{collections::HashMap, fs, path::Path};
+use tracing::warn;
+use uuid::Uuid;
+
+const DATABASE_FILE: &str = "state.db";
+
+// Embedded database holding the serialized policy of every experiment in a single table, and their
+// past snapshots in another
+pub struct SqliteBackend {
+    connection: Connection,
+    history_size: usize,
+}
+
+impl SqliteBackend {
+    pub fn new(dir: &Path, history_size: usize) -> Result<Self, PersistenceError> {
+        fs::create_dir_all(dir)?;
+        let connection = Connection::open(dir.join(DATABASE_FILE))?;
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS experiment_states (
+                experiment_id TEXT PRIMARY KEY,
+                policy TEXT NOT NULL,
+                updated_at REAL NOT NULL,
+                event_seq INTEGER NOT NULL DEFAULT 0,
+                metadata TEXT NOT NULL DEFAULT '{}'
+            );
+            CREATE TABLE IF NOT EXISTS state_snapshots (
+                experiment_id TEXT NOT NULL,
+                snapshot_id INTEGER NOT NULL,
+                policy TEXT NOT NULL,
+                event_seq INTEGER NOT NULL,
+                metadata TEXT NOT NULL DEFAULT '{}',
+                PRIMARY KEY (experiment_id, snapshot_id)
+            );
+            CREATE TABLE IF NOT EXISTS quarantined_states (
+                experiment_id TEXT NOT NULL,
+                policy TEXT NOT NULL,
+                reason TEXT NOT NULL,
+                quarantined_at REAL NOT NULL
+            );",
+        )?;
+        // databases created before event sequence numbers or metadata were stored lack the columns
+        for (table, column, definition) in [
+            (
+                "experiment_states",
+                "event_seq",
+                "INTEGER NOT NULL DEFAULT 0",
+            ),
+            (
+                "experiment_states",
+                "metadata",
+                "TEXT NOT NULL DEFAULT '{}'",
+            ),
+            ("state_snapshots", "metadata", "TEXT NOT NULL DEFAULT '{}'"),
+        ] {
+            let has_column = connection
+                .prepare(&format!(
+                    "SELECT 1 FROM pragma_table_info('{table}') WHERE name = '{column}'"
+                ))?
+                .exists([])?;
+            if !has_column {
+                connection.execute(
+                    &format!("ALTER TABLE {table} ADD COLUMN {column} {definition}"),
+                    [],
+                )?;
+            }
+        }
+        Ok(Self {
+            connection,
+            history_size,
+        })
+    }
+
+    // move an unreadable state to the quarantine table, in a single transaction
+    fn quarantine(&mut self, experiment_id: &str, err: &PersistenceError) {
+        let result = self.connection.transaction().and_then(|transaction| {
+            transaction.execute(
+                "INSERT INTO quarantined_states (experiment_id, policy, reason, quarantined_at)
+                SELECT experiment_id, policy, ?2, ?3 FROM experiment_states WHERE experiment_id = ?1",
+                params![experiment_id, err.to_string(), get_timestamp()],
+            )?;
+            transaction.execute(
+                "DELETE FROM experiment_states WHERE experiment_id = ?1",
+                params![experiment_id],
+            )?;
+            transaction.commit()
+        });
+
+        match result {
+            Ok(()) => warn!(error = %err, id = %experiment_id, "Quarantined unreadable state"),
+            Err(db_err) => {
+                warn!(error = %db_err, id = %experiment_id, "Failed to quarantine unreadable state")
+            }
+        }
+    }
+}
+
+// serialized policy, event sequence number and serialized metadata of a state, in that order
+fn read_state_row(row: &Row) -> Result<(String, u64, String), rusqlite::Error> {
+    Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+}
+
+fn parse_state(
+    serialized: &str,
+    event_seq: u64,
+    metadata: &str,
+) -> Result<StoredState, PersistenceError> {
+    Ok(StoredState {
+        policy: serde_json::from_str(serialized)?,
+        event_seq,
+        metadata: serde_json::from_str(metadata)?,
+    })
+}
+
+impl StateBackend for SqliteBackend {
+    fn save(
+        &mut self,
+        experiment_id: Uuid,
+        policy: &(dyn Policy + Send),
+        event_seq: u64,
+        metadata: &ExperimentMetadata,
+        snapshot: bool,
+    ) -> Result<(), PersistenceError> {
+        let serialized = serde_json::to_string(policy)?;
+        let metadata = serde_json::to_string(metadata)?;
+        let experiment_id = experiment_id.to_string();
+        let transaction = self.connection.transaction()?;
+        transaction.execute(
+            "INSERT INTO experiment_states (experiment_id, policy, updated_at, event_seq, metadata)
+            VALUES (?1, ?2, ?3, ?4, ?5)
+            ON CONFLICT(experiment_id) DO UPDATE SET policy = excluded.policy,
+            updated_at = excluded.updated_at, event_seq = excluded.event_seq,
+            metadata = excluded.metadata",
+            params![
+                experiment_id,
+                serialized,
+                get_timestamp(),
+                event_seq,
+                metadata
+            ],
+        )?;
+        if snapshot && self.history_size > 0 {
+            let last_snapshot_id = transaction.query_row(
+                "SELECT MAX(snapshot_id) FROM state_snapshots WHERE experiment_id = ?1",
+                params![experiment_id],
+                |row| row.get::<_, Option<u64>>(0),
+            )?;
+            transaction.execute(
+                "INSERT OR REPLACE INTO state_snapshots
+                (experiment_id, snapshot_id, policy, event_seq, metadata)
+                VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    experiment_id,
+                    new_snapshot_id(last_snapshot_id),
+                    serialized,
+                    event_seq,
+                    metadata
+                ],
+            )?;
+            transaction.execute(
+                "DELETE FROM state_snapshots WHERE experiment_id = ?1 AND snapshot_id NOT IN (
+                    SELECT snapshot_id FROM state_snapshots WHERE experiment_id = ?1
+                    ORDER BY snapshot_id DESC LIMIT ?2
+                )",
+                params![experiment_id, self.history_size],
+            )?;
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn load(&mut self, experiment_id: Uuid) -> Result<Option<StoredState>, PersistenceError> {
+        let row = self
+            .connection
+            .query_row(
+                "SELECT policy, event_seq, metadata FROM experiment_states WHERE experiment_id = ?1",
+                params![experiment_id.to_string()],
+                read_state_row,
+            )
+            .optional()?;
+        let Some((serialized, event_seq, metadata)) = row else {
+            return Ok(None);
+        };
+        match parse_state(&serialized, event_seq, &metadata) {
+            Ok(state) => Ok(Some(state)),
+            Err(err) => {
+                if err.is_corruption() {
+                    self.quarantine(&experiment_id.to_string(), &err);
+                }
+                Err(err)
+            }
+        }
+    }
+
+    fn load_all(&mut self) -> Result<HashMap<Uuid, StoredState>, PersistenceError> {
+        let rows = self
+            .connection
+            .prepare("SELECT policy, event_seq, metadata, experiment_id FROM experiment_states")?
+            .query_map([], |row| {
+                Ok((read_state_row(row)?, row.get::<_, String>(3)?))
+            })?
+            .collect::<Result<Vec<_>, rusqlite::Error>>()?;
+
+        let mut states = HashMap::new();
+        for ((serialized, event_seq, metadata), experiment_id) in rows {
+            let Ok(parsed_id) = Uuid::try_parse(&experiment_id) else {
+                warn!(id = %experiment_id, "Skipping row with non-UUID id in state store database");
+                continue;
+            };
+            match parse_state(&serialized, event_seq, &metadata) {
+                Ok(state) => {
+                    states.insert(parsed_id, state);
+                }
+                Err(err) if err.is_corruption() => self.quarantine(&experiment_id, &err),
+                Err(err) => {
+                    warn!(error = %err, id = %experiment_id, "Failed to load state row");
+                }
+            }
+        }
+
+        Ok(states)
+    }
+
+    fn delete(&mut self, experiment_id: Uuid) -> Result<(), PersistenceError> {
+        let transaction = self.connection.transaction()?;
+        transaction.execute(
+            "DELETE FROM experiment_states WHERE experiment_id = ?1",
+            params![experiment_id.to_string()],
+        )?;
+        transaction.execute(
+            "DELETE FROM state_snapshots WHERE experiment_id = ?1",
+            params![experiment_id.to_string()],
+        )?;
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn quarantined(&mut self) -> Result<Vec<QuarantinedState>, PersistenceError> {
+        let rows = self
+            .connection
+            .prepare(
+                "SELECT experiment_id, reason, quarantined_at FROM quarantined_states
+                ORDER BY quarantined_at",
+            )?
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, f64>(2)?,
+                ))
+            })?
+            .collect::<Result<Vec<(String, String, f64)>, rusqlite::Error>>()?;
+
+        Ok(rows
+            .into_iter()
+            .filter_map(|(experiment_id, reason, quarantined_at)| {
+                Some(QuarantinedState {
+                    experiment_id: Uuid::try_parse(&experiment_id).ok()?,
+                    location: "quarantined_states".to_string(),
+                    reason,
+                    quarantined_at,
+                })
+            })
+            .collect())
+    }
+
+    fn snapshots(&mut self, experiment_id: Uuid) -> Result<Vec<SnapshotInfo>, PersistenceError> {
+        let snapshot_ids = self
+            .connection
+            .prepare(
+                "SELECT snapshot_id FROM state_snapshots WHERE experiment_id = ?1
+                ORDER BY snapshot_id",
+            )?
+            .query_map(params![experiment_id.to_string()], |row| {
+                row.get::<_, u64>(0)
+            })?
+            .collect::<Result<Vec<u64>, rusqlite::Error>>()?;
+
+        Ok(snapshot_ids.into_iter().map(SnapshotInfo::new).collect())
+    }
+
+    fn load_snapshot(
+        &mut self,
+        experiment_id: Uuid,
+        snapshot_id: u64,
+    ) -> Result<Option<StoredState>, PersistenceError> {
+        let row = self
+            .connection
+            .query_row(
+                "SELECT policy, event_seq, metadata FROM state_snapshots
+                WHERE experiment_id = ?1 AND snapshot_id = ?2",
+                params![experiment_id.to_string(), snapshot_id],
+                read_state_row,
+            )
+            .optional()?;
+
+        row.map(|(serialized, event_seq, metadata)| parse_state(&serialized, event_seq, &metadata))
+            .transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quarantine_unreadable_state() {
+        let dir = std::env::temp_dir().join(format!("sqlite-backend-{}", Uuid::new_v4()));
+        let mut backend = SqliteBackend::new(&dir, 0).unwrap();
+        let experiment_id = Uuid::new_v4();
+        backend
+            .connection
+            .execute(
+                "INSERT INTO experiment_states (experiment_id, policy, updated_at) VALUES (?1, ?2, ?3)",
+                params![experiment_id.to_string(), "{\"type\": ", 0.0],
+            )
+            .unwrap();
+
+        assert!(backend.load_all().unwrap().is_empty());
+        assert!(backend.load(experiment_id).unwrap().is_none());
+        let quarantined = backend.quarantined().unwrap();
+        assert_eq!(quarantined.len(), 1);
+        assert_eq!(quarantined[0].experiment_id, experiment_id);
+
+        let _ = fs::remove_dir_all(dir);
+    }
+}